@@ -0,0 +1,83 @@
+//! 掷骰子机器人
+//!
+//! 用法: cargo run -p chat-bot --example dice -- [addr] [--user NAME]
+//!
+//! 聊天中发送 `!roll 2d6`、`!flip` 或 `!help`。
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+use anyhow::Result;
+use chat_bot::Bot;
+use chat_client_core::SessionConfig;
+use tracing_subscriber::EnvFilter;
+
+const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+
+/// 单次掷骰的最大骰子数
+const MAX_DICE: u32 = 20;
+
+/// 骰子最大面数
+const MAX_SIDES: u32 = 1000;
+
+/// 返回 1..=sides 之间的随机数
+fn random(sides: u32) -> u32 {
+    // 每个 RandomState 使用不同的随机种子，足够掷骰子使用
+    let value = RandomState::new().build_hasher().finish();
+    (value % sides as u64) as u32 + 1
+}
+
+/// 解析 `NdM`（N 省略时为 1），如 "2d6"、"d20"
+fn parse_dice(spec: &str) -> Option<(u32, u32)> {
+    let (count, sides) = spec.split_once(['d', 'D'])?;
+    let count = if count.is_empty() { 1 } else { count.parse().ok()? };
+    let sides = sides.parse().ok()?;
+    ((1..=MAX_DICE).contains(&count) && (2..=MAX_SIDES).contains(&sides)).then_some((count, sides))
+}
+
+fn roll(spec: &str) -> String {
+    let spec = if spec.is_empty() { "1d6" } else { spec };
+    let Some((count, sides)) = parse_dice(spec) else {
+        return format!("Usage: !roll NdM (N ≤ {}, 2 ≤ M ≤ {})", MAX_DICE, MAX_SIDES);
+    };
+    let rolls: Vec<u32> = (0..count).map(|_| random(sides)).collect();
+    let total: u32 = rolls.iter().sum();
+    if count == 1 {
+        format!("🎲 {}", total)
+    } else {
+        let rolls: Vec<String> = rolls.iter().map(u32::to_string).collect();
+        format!("🎲 {} = {}", rolls.join(" + "), total)
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env().add_directive("chat_bot=info".parse()?))
+        .init();
+
+    let mut addr = DEFAULT_ADDR.to_string();
+    let mut username = "dice".to_string();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--user" => {
+                username = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--user requires a value"))?;
+            }
+            _ => addr = arg,
+        }
+    }
+
+    Bot::new(SessionConfig::new(addr, username))
+        .command("roll", "Roll dice, e.g. !roll 2d6", |cmd| async move {
+            Some(roll(&cmd.args))
+        })
+        .command("flip", "Flip a coin", |_| async move {
+            Some(if random(2) == 1 { "Heads" } else { "Tails" }.to_string())
+        })
+        .run()
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,437 @@
+//! 机器人运行时
+//!
+//! `Bot::run` 负责连接、断线重连以及把命令分发给处理器；
+//! Join 握手和心跳由 `ChatSession` 完成。
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chat_client_core::{
+    ChatSession, DisconnectReason, SessionConfig, SessionError, SessionEvent, SessionHandle,
+};
+use protocol::ErrorCode;
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+use tracing::{debug, info, warn};
+
+use crate::command::{escape_slash, parse_command, Command};
+
+/// 默认命令前缀
+const DEFAULT_PREFIX: char = '!';
+
+/// 首次重连前的等待时间
+const DEFAULT_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// 重连等待时间上限（每次失败翻倍）
+const DEFAULT_MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// 主动发送队列容量
+const OUTBOX_CAPACITY: usize = 32;
+
+type HandlerFuture = Pin<Box<dyn Future<Output = Option<String>> + Send>>;
+type Handler = Arc<dyn Fn(Command) -> HandlerFuture + Send + Sync>;
+
+/// 已注册的命令
+struct Registered {
+    description: String,
+    handler: Handler,
+}
+
+/// 聊天机器人
+///
+/// ```no_run
+/// use chat_bot::Bot;
+/// use chat_client_core::SessionConfig;
+///
+/// # async fn demo() -> chat_client_core::Result<()> {
+/// Bot::new(SessionConfig::new("127.0.0.1:8080", "echobot"))
+///     .command("echo", "Repeat the arguments", |cmd| async move { Some(cmd.args) })
+///     .run()
+///     .await
+/// # }
+/// ```
+pub struct Bot {
+    config: SessionConfig,
+    prefix: char,
+    commands: BTreeMap<String, Registered>,
+    reconnect_delay: Duration,
+    max_reconnect_delay: Duration,
+    outbox_tx: mpsc::Sender<String>,
+    outbox_rx: mpsc::Receiver<String>,
+}
+
+impl Bot {
+    pub fn new(config: SessionConfig) -> Self {
+        let (outbox_tx, outbox_rx) = mpsc::channel(OUTBOX_CAPACITY);
+        Self {
+            config,
+            prefix: DEFAULT_PREFIX,
+            commands: BTreeMap::new(),
+            reconnect_delay: DEFAULT_RECONNECT_DELAY,
+            max_reconnect_delay: DEFAULT_MAX_RECONNECT_DELAY,
+            outbox_tx,
+            outbox_rx,
+        }
+    }
+
+    /// 设置命令前缀（默认 `!`）
+    pub fn with_prefix(mut self, prefix: char) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    /// 设置重连等待时间：首次为 `initial`，之后每次失败翻倍，最多 `max`
+    pub fn with_reconnect_delay(mut self, initial: Duration, max: Duration) -> Self {
+        self.reconnect_delay = initial;
+        self.max_reconnect_delay = max.max(initial);
+        self
+    }
+
+    /// 注册命令处理器
+    ///
+    /// 处理器在独立任务中运行；返回 Some 时机器人以回复的形式发送该文本。
+    /// 未注册 `help` 时，机器人自动回复已注册命令的列表。
+    pub fn command<F, Fut>(mut self, name: &str, description: &str, handler: F) -> Self
+    where
+        F: Fn(Command) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<String>> + Send + 'static,
+    {
+        let handler: Handler = Arc::new(move |cmd| Box::pin(handler(cmd)));
+        self.commands.insert(
+            name.to_string(),
+            Registered {
+                description: description.to_string(),
+                handler,
+            },
+        );
+        self
+    }
+
+    /// 用于主动发送消息的句柄（如部署通知）
+    ///
+    /// 断线期间的消息会排队，重连后发送。
+    pub fn sender(&self) -> BotSender {
+        BotSender {
+            tx: self.outbox_tx.clone(),
+        }
+    }
+
+    /// 运行机器人，断线后自动重连
+    ///
+    /// 只在用户名被服务器永久拒绝（无效或被封禁）时返回错误。
+    pub async fn run(mut self) -> chat_client_core::Result<()> {
+        let mut delay = self.reconnect_delay;
+        loop {
+            match ChatSession::connect(self.config.clone()).await {
+                Ok(session) => {
+                    info!("Bot joined as {}", session.username());
+                    delay = self.reconnect_delay;
+                    let reason = self.serve(session).await;
+                    info!("Bot disconnected: {:?}", reason);
+                }
+                Err(e) if is_fatal(&e) => return Err(e),
+                Err(e) => warn!("Bot failed to connect: {}", e),
+            }
+            sleep(delay).await;
+            delay = (delay * 2).min(self.max_reconnect_delay);
+        }
+    }
+
+    /// 处理一个会话直到断开
+    async fn serve(&mut self, mut session: ChatSession) -> DisconnectReason {
+        let handle = session.handle().clone();
+        loop {
+            tokio::select! {
+                event = session.next_event() => match event {
+                    Some(SessionEvent::ChatMessage { message_id, username, content, .. }) => {
+                        // 忽略自己的消息，避免命令回复再次触发命令
+                        if username != session.username() {
+                            self.dispatch(&handle, message_id, username, &content);
+                        }
+                    }
+                    Some(SessionEvent::Disconnected { reason }) => return reason,
+                    Some(_) => {}
+                    None => return DisconnectReason::ConnectionClosed,
+                },
+
+                // outbox_tx 由 Bot 自己持有，recv 不会返回 None
+                Some(content) = self.outbox_rx.recv() => {
+                    if let Err(e) = handle.send_chat(escape_slash(content), None).await {
+                        warn!("Failed to send bot message: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// 解析命令并在独立任务中运行处理器
+    fn dispatch(&self, session: &SessionHandle, message_id: u64, username: String, content: &str) {
+        let Some((name, args)) = parse_command(self.prefix, content) else {
+            return;
+        };
+        let session = session.clone();
+
+        let Some(registered) = self.commands.get(name) else {
+            if name == "help" {
+                let help = self.help_text();
+                tokio::spawn(async move {
+                    if let Err(e) = session.send_chat(escape_slash(help), Some(message_id)).await {
+                        warn!("Failed to send help: {}", e);
+                    }
+                });
+            } else {
+                debug!("Unknown command from {}: {}", username, name);
+            }
+            return;
+        };
+
+        let handler = Arc::clone(&registered.handler);
+        let command = Command {
+            name: name.to_string(),
+            args: args.to_string(),
+            username,
+            message_id,
+            session: session.clone(),
+        };
+        tokio::spawn(async move {
+            if let Some(reply) = handler(command).await {
+                if let Err(e) = session.send_chat(escape_slash(reply), Some(message_id)).await {
+                    warn!("Failed to send command reply: {}", e);
+                }
+            }
+        });
+    }
+
+    /// 自动生成的帮助文本
+    fn help_text(&self) -> String {
+        self.commands
+            .iter()
+            .map(|(name, registered)| format!("{}{} - {}", self.prefix, name, registered.description))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// 机器人的主动发送端，可克隆
+#[derive(Debug, Clone)]
+pub struct BotSender {
+    tx: mpsc::Sender<String>,
+}
+
+impl BotSender {
+    /// 发送消息（断线期间排队），机器人已停止时返回 `SessionError::Closed`
+    pub async fn send(&self, content: impl Into<String>) -> chat_client_core::Result<()> {
+        self.tx
+            .send(content.into())
+            .await
+            .map_err(|_| SessionError::Closed)
+    }
+}
+
+/// 重连也无法解决的错误：用户名无效或被封禁
+fn is_fatal(error: &SessionError) -> bool {
+    let code = match error {
+        SessionError::Rejected { code, .. } => *code,
+        SessionError::Protocol(e) => e.code(),
+        _ => return false,
+    };
+    matches!(
+        code,
+        ErrorCode::UsernameEmpty
+            | ErrorCode::UsernameTooLong
+            | ErrorCode::UsernameInvalidChars
+            | ErrorCode::UsernameMixedScript
+            | ErrorCode::Banned
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chat_server::{ChatServer, ServerConfig};
+    use protocol::{TcpListener, TransportListener};
+
+    /// 在进程内启动服务器
+    async fn start_server(listener: TcpListener) {
+        let port = listener.local_addr().unwrap().port();
+        let config = ServerConfig {
+            files_dir: std::env::temp_dir().join(format!("chat-bot-test-{}", port)),
+            ..ServerConfig::default()
+        };
+        tokio::spawn(async move { ChatServer::with_config(config).serve(listener).await });
+    }
+
+    /// 等待指定用户发送的下一条聊天消息，返回 (内容, 回复的消息 ID)
+    async fn next_chat_from(session: &mut ChatSession, from: &str) -> (String, Option<u64>) {
+        loop {
+            match session.next_event().await.expect("session ended") {
+                SessionEvent::ChatMessage { username, content, reply_to, .. } if username == from => {
+                    return (content, reply_to);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// 等待自己发送的消息被广播，返回其消息 ID
+    async fn own_message_id(session: &mut ChatSession) -> u64 {
+        let me = session.username().to_string();
+        loop {
+            if let Some(SessionEvent::ChatMessage { message_id, username, .. }) = session.next_event().await {
+                if username == me {
+                    return message_id;
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commands_are_routed_to_handlers() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        start_server(listener).await;
+
+        let bot = Bot::new(SessionConfig::new(&addr, "echobot"))
+            .command("echo", "Repeat the arguments", |cmd| async move { Some(cmd.args) })
+            .command("whoami", "Show your name", |cmd| async move { Some(cmd.username) });
+        tokio::spawn(bot.run());
+
+        let mut user = ChatSession::connect(SessionConfig::new(&addr, "alice")).await.unwrap();
+        // 等机器人加入后再发命令
+        loop {
+            if let Some(SessionEvent::UserJoined { username, .. }) = user.next_event().await {
+                if username == "echobot" {
+                    break;
+                }
+            }
+        }
+
+        user.send_chat("!echo  hello bot ", None).await.unwrap();
+        let message_id = own_message_id(&mut user).await;
+        assert_eq!(
+            next_chat_from(&mut user, "echobot").await,
+            ("hello bot".to_string(), Some(message_id))
+        );
+
+        user.send_chat("!whoami", None).await.unwrap();
+        assert_eq!(next_chat_from(&mut user, "echobot").await.0, "alice");
+
+        // 未注册的命令被忽略，help 列出已注册的命令
+        user.send_chat("!unknown", None).await.unwrap();
+        user.send_chat("!help", None).await.unwrap();
+        assert_eq!(
+            next_chat_from(&mut user, "echobot").await.0,
+            "!echo - Repeat the arguments\n!whoami - Show your name"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replies_are_not_run_as_server_commands() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        start_server(listener).await;
+
+        let bot = Bot::new(SessionConfig::new(&addr, "echobot"))
+            .command("echo", "Repeat the arguments", |cmd| async move { Some(cmd.args) });
+        tokio::spawn(bot.run());
+
+        let mut user = ChatSession::connect(SessionConfig::new(&addr, "alice")).await.unwrap();
+        loop {
+            if let Some(SessionEvent::UserJoined { username, .. }) = user.next_event().await {
+                if username == "echobot" {
+                    break;
+                }
+            }
+        }
+
+        // 回复作为普通消息发出，机器人没有改名
+        user.send_chat("!echo /nick x", None).await.unwrap();
+        loop {
+            match user.next_event().await.expect("session ended") {
+                SessionEvent::UserRenamed { old_username, new_username } => {
+                    panic!("{} was renamed to {}", old_username, new_username)
+                }
+                SessionEvent::ChatMessage { username, content, .. } if username == "echobot" => {
+                    assert_eq!(content, "/nick x");
+                    break;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cleared_read_receipt_disappears() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        start_server(listener).await;
+
+        let mut alice = ChatSession::connect(SessionConfig::new(&addr, "alice")).await.unwrap();
+        let mut bob = ChatSession::connect(SessionConfig::new(&addr, "bob")).await.unwrap();
+        alice.send_chat("hello", None).await.unwrap();
+        let message_id = own_message_id(&mut alice).await;
+
+        // 已读位置广播给其他用户，清除后收到 up_to 为 0 的回执
+        alice.handle().mark_read(message_id).await.unwrap();
+        alice.handle().clear_read().await.unwrap();
+        let mut seen = Vec::new();
+        while seen.last() != Some(&0) {
+            if let Some(SessionEvent::ReadReceipts { receipts }) = bob.next_event().await {
+                seen.extend(receipts.iter().filter(|r| r.username == "alice").map(|r| r.up_to));
+            }
+        }
+        assert_eq!(seen, vec![message_id, 0]);
+
+        // 之后加入的用户看不到已清除的已读位置
+        let mut carol = ChatSession::connect(SessionConfig::new(&addr, "carol")).await.unwrap();
+        carol.send_chat("hi", None).await.unwrap();
+        loop {
+            match carol.next_event().await.expect("session ended") {
+                SessionEvent::ReadReceipts { receipts } => panic!("unexpected receipts: {:?}", receipts),
+                SessionEvent::ChatMessage { username, .. } if username == "carol" => break,
+                _ => {}
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_until_server_is_up() {
+        // 先取得一个空闲端口，服务器稍后才在该端口启动
+        let addr = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+
+        let bot = Bot::new(SessionConfig::new(&addr, "notifier"))
+            .with_reconnect_delay(Duration::from_millis(20), Duration::from_millis(100));
+        let sender = bot.sender();
+        tokio::spawn(bot.run());
+
+        sleep(Duration::from_millis(100)).await;
+        assert!(ChatSession::connect(SessionConfig::new(&addr, "alice")).await.is_err());
+
+        start_server(TcpListener::bind(&addr).await.unwrap()).await;
+        let mut user = ChatSession::connect(SessionConfig::new(&addr, "alice")).await.unwrap();
+        if !user.online_users().iter().any(|u| u.username == "notifier") {
+            loop {
+                if let Some(SessionEvent::UserJoined { username, .. }) = user.next_event().await {
+                    if username == "notifier" {
+                        break;
+                    }
+                }
+            }
+        }
+
+        sender.send("deploy finished").await.unwrap();
+        assert_eq!(next_chat_from(&mut user, "notifier").await.0, "deploy finished");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_username_is_fatal() {
+        let result = Bot::new(SessionConfig::new("127.0.0.1:1", "bad name")).run().await;
+        assert!(matches!(result, Err(SessionError::Protocol(_))));
+    }
+}
@@ -0,0 +1,72 @@
+//! 命令解析
+
+use chat_client_core::SessionHandle;
+
+/// 收到的一条机器人命令，如 `!roll 2d6`
+#[derive(Debug, Clone)]
+pub struct Command {
+    /// 命令名（不含前缀），如 "roll"
+    pub name: String,
+    /// 命令名之后的参数文本（已去除首尾空白）
+    pub args: String,
+    /// 发送命令的用户
+    pub username: String,
+    /// 命令所在消息的 ID，回复会引用这条消息
+    pub message_id: u64,
+    /// 当前会话，处理器可用它发送额外的消息或文件
+    ///
+    /// 直接用它发送的消息不会转义开头的 `/`，会被服务器当作机器人执行的命令。
+    pub session: SessionHandle,
+}
+
+impl Command {
+    /// 按空白分隔的参数
+    pub fn arg_list(&self) -> Vec<&str> {
+        self.args.split_whitespace().collect()
+    }
+}
+
+/// 解析 `<prefix>name args`，返回 (命令名, 参数)；不是命令时返回 None
+pub(crate) fn parse_command(prefix: char, content: &str) -> Option<(&str, &str)> {
+    let rest = content.trim_start().strip_prefix(prefix)?;
+    let (name, args) = match rest.split_once(char::is_whitespace) {
+        Some((name, args)) => (name, args.trim()),
+        None => (rest, ""),
+    };
+    if name.is_empty() {
+        return None;
+    }
+    Some((name, args))
+}
+
+/// 转义机器人要发送的消息：服务器会把 `/` 开头的消息当作命令，以机器人的身份和角色执行，
+/// 因此开头的 `/` 写成 `//`，服务器会还原为普通消息
+pub(crate) fn escape_slash(content: String) -> String {
+    if content.starts_with('/') {
+        format!("/{}", content)
+    } else {
+        content
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command('!', "!roll 2d6"), Some(("roll", "2d6")));
+        assert_eq!(parse_command('!', "  !page  on-call  now "), Some(("page", "on-call  now")));
+        assert_eq!(parse_command('!', "!help"), Some(("help", "")));
+        assert_eq!(parse_command('!', "! roll"), None);
+        assert_eq!(parse_command('!', "hello !roll"), None);
+        assert_eq!(parse_command('/', "/roll"), Some(("roll", "")));
+    }
+
+    #[test]
+    fn test_escape_slash() {
+        assert_eq!(escape_slash("/kick alice".to_string()), "//kick alice");
+        assert_eq!(escape_slash("//already".to_string()), "///already");
+        assert_eq!(escape_slash("hi /me".to_string()), "hi /me");
+    }
+}
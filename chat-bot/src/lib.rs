@@ -0,0 +1,11 @@
+//! 聊天机器人 SDK
+//!
+//! 基于 `chat-client-core` 的会话：自动完成 Join、心跳和断线重连，
+//! 把 `!command args` 形式的消息分发给注册的异步处理器。
+//! 示例见 `examples/dice.rs`。
+
+mod bot;
+mod command;
+
+pub use bot::{Bot, BotSender};
+pub use command::Command;
@@ -0,0 +1,220 @@
+//! 私聊端到端加密
+//!
+//! 每个客户端持有一个 X25519 密钥对，公钥随 Join 发给服务器并分发给其他用户。
+//! 双方用 X25519 协商出共享密钥，经 HKDF-SHA256 派生出 ChaCha20-Poly1305 密钥，
+//! 服务器只能看到密文。密钥派生和附加数据都使用按发送顺序排列的双方公钥（发送者在前），
+//! 因此两个方向的密钥不同：服务器无法把 A 发给 B 的密文冒充成 B 发给 A 的消息退回给 A。
+//! 发送者仍能用 [`Identity::decrypt_sent`] 解密服务器回显的自己的消息。
+//!
+//! 没有重放保护：服务器可以把同一方向的旧密文换一个消息 ID 再次投递，接收方无法分辨。
+
+use std::fmt;
+
+use chacha20poly1305::aead::{Aead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use hkdf::Hkdf;
+use protocol::{AEAD_TAG_LEN, NONCE_LEN, PUBLIC_KEY_LEN};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// HKDF 的 info 参数，区分协议版本
+const KDF_INFO: &[u8] = b"chat-e2e-direct-v2";
+
+/// 指纹使用的摘要字节数
+const FINGERPRINT_BYTES: usize = 16;
+
+/// 加解密错误
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum E2eError {
+    /// 对方没有发布公钥（或已离线）
+    #[error("{username} has no public key")]
+    NoPublicKey { username: String },
+    /// 公钥长度错误
+    #[error("invalid public key")]
+    InvalidPublicKey,
+    /// 系统随机数生成器不可用
+    #[error("random number generator failed: {0}")]
+    Random(String),
+    /// 明文过长
+    #[error("message too long: {len} bytes (max: {max})")]
+    TooLong { len: usize, max: usize },
+    /// 密文被篡改、密钥不匹配或 nonce 错误
+    #[error("message could not be decrypted")]
+    Decrypt,
+}
+
+/// 本地密钥对
+///
+/// 私钥应长期保存，公钥变化时其他用户会收到密钥变更警告。
+#[derive(Clone)]
+pub struct Identity {
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl Identity {
+    /// 生成新的密钥对
+    pub fn generate() -> Result<Self, E2eError> {
+        let mut bytes = [0u8; 32];
+        getrandom::fill(&mut bytes).map_err(|e| E2eError::Random(e.to_string()))?;
+        Ok(Self::from_secret_bytes(bytes))
+    }
+
+    /// 从保存的私钥恢复
+    pub fn from_secret_bytes(bytes: [u8; 32]) -> Self {
+        let secret = StaticSecret::from(bytes);
+        let public = PublicKey::from(&secret);
+        Self { secret, public }
+    }
+
+    /// 私钥字节，用于持久化
+    pub fn secret_bytes(&self) -> [u8; 32] {
+        self.secret.to_bytes()
+    }
+
+    /// 公钥字节
+    pub fn public_key(&self) -> [u8; PUBLIC_KEY_LEN] {
+        self.public.to_bytes()
+    }
+
+    /// 自己公钥的指纹
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.public_key())
+    }
+
+    /// 为 `their_key` 的持有者加密，返回 (nonce, 密文)
+    pub fn encrypt(&self, their_key: &[u8], plaintext: &[u8]) -> Result<(Vec<u8>, Vec<u8>), E2eError> {
+        let (cipher, aad) = self.cipher(their_key, Direction::Sent)?;
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::fill(&mut nonce).map_err(|e| E2eError::Random(e.to_string()))?;
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: &aad })
+            .map_err(|_| E2eError::Decrypt)?;
+        Ok((nonce.to_vec(), ciphertext))
+    }
+
+    /// 解密 `their_key` 的持有者发给自己的消息
+    pub fn decrypt(&self, their_key: &[u8], nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, E2eError> {
+        self.open(their_key, Direction::Received, nonce, ciphertext)
+    }
+
+    /// 解密自己发给 `their_key` 的持有者的消息（服务器回显）
+    pub fn decrypt_sent(&self, their_key: &[u8], nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, E2eError> {
+        self.open(their_key, Direction::Sent, nonce, ciphertext)
+    }
+
+    fn open(&self, their_key: &[u8], direction: Direction, nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, E2eError> {
+        if nonce.len() != NONCE_LEN || ciphertext.len() < AEAD_TAG_LEN {
+            return Err(E2eError::Decrypt);
+        }
+        let (cipher, aad) = self.cipher(their_key, direction)?;
+        cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad })
+            .map_err(|_| E2eError::Decrypt)
+    }
+
+    /// 派生一个方向的 AEAD 密钥，HKDF 盐和附加数据为发送者公钥加收件人公钥
+    fn cipher(&self, their_key: &[u8], direction: Direction) -> Result<(ChaCha20Poly1305, Vec<u8>), E2eError> {
+        let their_key: [u8; PUBLIC_KEY_LEN] = their_key.try_into().map_err(|_| E2eError::InvalidPublicKey)?;
+        let shared = self.secret.diffie_hellman(&PublicKey::from(their_key));
+        if !shared.was_contributory() {
+            return Err(E2eError::InvalidPublicKey);
+        }
+
+        let own_key = self.public_key();
+        let (sender, recipient) = match direction {
+            Direction::Sent => (own_key, their_key),
+            Direction::Received => (their_key, own_key),
+        };
+        let aad = [sender, recipient].concat();
+
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(Some(&aad), shared.as_bytes())
+            .expand(KDF_INFO, &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        Ok((ChaCha20Poly1305::new(Key::from_slice(&key)), aad))
+    }
+}
+
+/// 消息相对本地用户的方向
+#[derive(Clone, Copy)]
+enum Direction {
+    Sent,
+    Received,
+}
+
+impl fmt::Debug for Identity {
+    // 不输出私钥
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Identity")
+            .field("fingerprint", &self.fingerprint())
+            .finish_non_exhaustive()
+    }
+}
+
+/// 公钥指纹：SHA-256 摘要前 16 字节的十六进制，每 4 位一组，供用户通过其他渠道核对
+pub fn fingerprint(public_key: &[u8]) -> String {
+    let digest = Sha256::digest(public_key);
+    digest[..FINGERPRINT_BYTES]
+        .chunks(2)
+        .map(|pair| format!("{:02x}{:02x}", pair[0], pair[1]))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt_both_directions() {
+        let alice = Identity::generate().unwrap();
+        let bob = Identity::generate().unwrap();
+
+        let (nonce, ciphertext) = alice.encrypt(&bob.public_key(), "你好 bob".as_bytes()).unwrap();
+        assert_eq!(ciphertext.len(), "你好 bob".len() + AEAD_TAG_LEN);
+        assert_eq!(bob.decrypt(&alice.public_key(), &nonce, &ciphertext).unwrap(), "你好 bob".as_bytes());
+        // 发送者能解密服务器回显的自己的消息
+        assert_eq!(alice.decrypt_sent(&bob.public_key(), &nonce, &ciphertext).unwrap(), "你好 bob".as_bytes());
+
+        let eve = Identity::generate().unwrap();
+        assert_eq!(eve.decrypt(&alice.public_key(), &nonce, &ciphertext), Err(E2eError::Decrypt));
+    }
+
+    #[test]
+    fn test_tampered_ciphertext_is_rejected() {
+        let alice = Identity::generate().unwrap();
+        let bob = Identity::generate().unwrap();
+        let (nonce, mut ciphertext) = alice.encrypt(&bob.public_key(), b"hi").unwrap();
+        ciphertext[0] ^= 1;
+        assert_eq!(bob.decrypt(&alice.public_key(), &nonce, &ciphertext), Err(E2eError::Decrypt));
+        assert_eq!(alice.encrypt(&[0; 31], b"hi"), Err(E2eError::InvalidPublicKey));
+        // 全零公钥会得到全零共享密钥
+        assert_eq!(alice.encrypt(&[0; 32], b"hi"), Err(E2eError::InvalidPublicKey));
+    }
+
+    #[test]
+    fn test_reflected_ciphertext_is_rejected() {
+        let alice = Identity::generate().unwrap();
+        let bob = Identity::generate().unwrap();
+        let (nonce, ciphertext) = alice.encrypt(&bob.public_key(), b"hi bob").unwrap();
+        // 服务器把 alice 的密文标成 bob 发来的消息退回给 alice
+        assert_eq!(alice.decrypt(&bob.public_key(), &nonce, &ciphertext), Err(E2eError::Decrypt));
+        // bob 也不能把收到的消息当成自己发出的回显
+        assert_eq!(bob.decrypt_sent(&alice.public_key(), &nonce, &ciphertext), Err(E2eError::Decrypt));
+    }
+
+    #[test]
+    fn test_identity_roundtrip_and_fingerprint() {
+        let identity = Identity::generate().unwrap();
+        let restored = Identity::from_secret_bytes(identity.secret_bytes());
+        assert_eq!(restored.public_key(), identity.public_key());
+
+        let fp = identity.fingerprint();
+        assert_eq!(fp.len(), 8 * 4 + 7);
+        assert_eq!(fp, fingerprint(&identity.public_key()));
+        assert_ne!(fp, Identity::generate().unwrap().fingerprint());
+        assert!(!format!("{:?}", identity).contains(&format!("{:?}", identity.secret_bytes())));
+    }
+}
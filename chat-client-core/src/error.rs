@@ -0,0 +1,50 @@
+//! 会话错误类型
+
+use protocol::{ErrorCode, ProtocolError};
+use thiserror::Error;
+
+/// 会话操作错误
+#[derive(Error, Debug)]
+pub enum SessionError {
+    /// 无法连接服务器
+    #[error("Failed to connect: {0}")]
+    Connect(#[source] ProtocolError),
+
+    /// 服务器拒绝加入
+    #[error("Join rejected: {code:?}")]
+    Rejected {
+        code: ErrorCode,
+        message: Option<String>,
+    },
+
+    /// 服务器在 Welcome 和在线用户列表之前（或之间）发送了其他消息
+    #[error("Unexpected response to Join")]
+    UnexpectedResponse,
+
+    /// 协议错误
+    #[error(transparent)]
+    Protocol(#[from] ProtocolError),
+
+    /// 无法读取要上传的文件
+    #[error("Cannot read file: {0}")]
+    File(#[from] std::io::Error),
+
+    /// 要上传的文件名无效
+    #[error("Invalid file name")]
+    InvalidFileName,
+
+    /// 要上传的文件过大
+    #[error("File too large: {size} bytes (max: {max})")]
+    FileTooLarge { size: u64, max: u64 },
+
+    /// 无法生成加密私聊的密钥
+    #[error(transparent)]
+    E2e(#[from] crate::e2e::E2eError),
+
+    /// 会话已结束
+    #[error("Session closed")]
+    Closed,
+}
+
+/// 会话操作结果类型
+pub type Result<T> = std::result::Result<T, SessionError>;
@@ -0,0 +1,166 @@
+//! 会话事件
+
+use protocol::{CommandReply, ErrorCode, ReadReceipt, Role, SearchHit};
+use thiserror::Error;
+
+use crate::e2e::E2eError;
+
+/// 会话产生的事件，按接收顺序通过 `ChatSession::next_event` 取出
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionEvent {
+    /// 收到聊天消息
+    ChatMessage {
+        message_id: u64,
+        username: String,
+        content: String,
+        timestamp: u64,
+        reply_to: Option<u64>,
+        /// 消息 @提及 的在线用户
+        mentions: Vec<String>,
+    },
+    /// 服务器的每日消息，加入后收到
+    Motd { message: String },
+    /// 收到 `/me` 动作消息
+    ActionMessage {
+        message_id: u64,
+        username: String,
+        content: String,
+        timestamp: u64,
+    },
+    /// 收到私聊消息（包括自己发出消息的回显）
+    DirectMessage {
+        message_id: u64,
+        from: String,
+        to: String,
+        content: String,
+        timestamp: u64,
+        /// 是否端到端加密（`/msg` 命令发送的私聊经过服务器明文转发）
+        encrypted: bool,
+        /// 是否是离线期间收到、加入后才投递的私聊
+        offline: bool,
+    },
+    /// 收到无法解密的加密私聊（对方使用的密钥与其公布的不符，或消息被篡改）
+    UndecryptableMessage {
+        message_id: u64,
+        from: String,
+        to: String,
+        timestamp: u64,
+    },
+    /// 加密私聊未能发送
+    DirectFailed { to: String, error: E2eError },
+    /// 用户加入，`public_key` 为其用于加密私聊的公钥
+    UserJoined {
+        username: String,
+        role: Role,
+        public_key: Option<Vec<u8>>,
+    },
+    /// 用户离开
+    UserLeft { username: String },
+    /// 用户改名（自己改名时 `ChatSession::username` 随之更新）
+    UserRenamed {
+        old_username: String,
+        new_username: String,
+    },
+    /// 用户角色变更
+    RoleChanged {
+        username: String,
+        role: Role,
+        by: String,
+    },
+    /// 话题变更
+    TopicChanged { topic: String, username: String },
+    /// 斜杠命令的执行结果
+    CommandReply { reply: CommandReply },
+    /// 历史消息搜索结果，`before` 为请求中的翻页位置（第一页为 None）
+    SearchResults {
+        before: Option<u64>,
+        hits: Vec<SearchHit>,
+        next_before: Option<u64>,
+    },
+    /// 用户的已读位置（加入时为所有用户，之后为已读位置前进的用户）
+    ReadReceipts { receipts: Vec<ReadReceipt> },
+    /// 有用户共享了文件
+    FileShared {
+        file_id: u64,
+        username: String,
+        name: String,
+        size: u64,
+        mime: String,
+        timestamp: u64,
+    },
+    /// 传输开始
+    TransferStarted {
+        transfer_id: u32,
+        name: String,
+        direction: TransferDirection,
+        total: u64,
+    },
+    /// 传输进度（每隔若干分块上报一次）
+    TransferProgress { transfer_id: u32, transferred: u64 },
+    /// 上传完成
+    UploadComplete { transfer_id: u32 },
+    /// 下载完成
+    DownloadComplete {
+        transfer_id: u32,
+        file_id: u64,
+        data: Vec<u8>,
+    },
+    /// 传输失败或被取消
+    TransferFailed {
+        transfer_id: u32,
+        error: TransferError,
+    },
+    /// 服务器返回的错误
+    ServerError {
+        code: ErrorCode,
+        message: Option<String>,
+    },
+    /// 连接断开，之后不会再有事件
+    Disconnected { reason: DisconnectReason },
+}
+
+/// 传输方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferDirection {
+    Upload,
+    Download,
+}
+
+/// 传输失败的原因
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TransferError {
+    /// 本地取消
+    #[error("Cancelled")]
+    Cancelled,
+    /// 服务器取消或拒绝，附带服务器给出的原因
+    #[error("Cancelled by server: {0}")]
+    Server(String),
+    /// 未知的传输 ID
+    #[error("Unknown transfer")]
+    Unknown,
+    /// 分块顺序错误
+    #[error("Chunk {index} out of order")]
+    OutOfOrder { index: u32 },
+    /// 分块校验失败
+    #[error("Chunk {index} checksum mismatch")]
+    ChecksumMismatch { index: u32 },
+    /// 收到的数据超过声明的大小
+    #[error("File size does not match")]
+    SizeMismatch,
+    /// 下载结束时数据不完整
+    #[error("File is incomplete")]
+    Incomplete,
+}
+
+/// 连接断开的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// 本地调用了 disconnect
+    Requested,
+    /// 服务器关闭，附带服务器的通知
+    ServerShutdown { message: String },
+    /// 服务器关闭了连接
+    ConnectionClosed,
+    /// 网络或协议错误
+    Error(String),
+}
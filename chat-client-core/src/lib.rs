@@ -0,0 +1,33 @@
+//! 聊天客户端网络库
+//!
+//! 封装与服务器的连接、Join 握手、心跳和文件分块传输，供图形界面、终端界面和机器人共用:
+//!
+//! ```no_run
+//! use chat_client_core::{ChatSession, SessionConfig, SessionEvent};
+//!
+//! # async fn demo() -> chat_client_core::Result<()> {
+//! let mut session = ChatSession::connect(SessionConfig::new("127.0.0.1:8080", "alice")).await?;
+//! session.send_chat("hello", None).await?;
+//! while let Some(event) = session.next_event().await {
+//!     if let SessionEvent::ChatMessage { username, content, .. } = event {
+//!         println!("{}: {}", username, content);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! 私聊使用 `SessionHandle::send_direct` 端到端加密（见 `e2e` 模块），服务器只转发密文。
+//!
+//! 本库不做本地化：错误和事件都是类型化的，由界面自行转换为显示文本。
+
+pub mod e2e;
+mod error;
+mod event;
+mod session;
+mod transfer;
+
+pub use e2e::{E2eError, Identity};
+pub use error::{Result, SessionError};
+pub use event::{DisconnectReason, SessionEvent, TransferDirection, TransferError};
+pub use session::{ChatSession, SearchRequest, SessionConfig, SessionHandle};
@@ -0,0 +1,1079 @@
+//! 聊天会话
+//!
+//! `ChatSession::connect` 完成 TCP 连接和 Join 握手后，在后台任务中收发消息：
+//! 服务器消息转换为 `SessionEvent`，通过 `next_event` 按顺序取出；
+//! 发送操作通过可克隆的 `SessionHandle` 提交给后台任务。
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use protocol::{
+    mime_from_name, normalize_username, username_key, validate_username, ClientMessage, Codec, CodecKind,
+    Compression, Connection, FrameReader, FrameWriter, OnlineUser, ProtocolError, ServerMessage, TcpTransport,
+    Transport, TransportConfig, AEAD_TAG_LEN, CONNECT_TIMEOUT, HEARTBEAT_INTERVAL, JOIN_TIMEOUT,
+    MAX_CIPHERTEXT_LEN, MAX_FILE_SIZE,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc;
+use tokio::time::{interval, timeout};
+use tracing::{debug, info, warn};
+
+use crate::e2e::{E2eError, Identity};
+use crate::error::{Result, SessionError};
+use crate::event::{DisconnectReason, SessionEvent, TransferDirection, TransferError};
+use crate::transfer::TransferManager;
+
+/// 每发送/接收多少个分块上报一次进度
+const PROGRESS_REPORT_CHUNKS: u32 = 16;
+
+/// 命令和事件通道容量
+const CHANNEL_CAPACITY: usize = 32;
+
+/// 默认每页搜索结果数
+const SEARCH_PAGE_SIZE: u32 = 20;
+
+/// 会话连接参数
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// 服务器地址，如 "127.0.0.1:8080"
+    pub addr: String,
+    /// 用户名（连接前会做 NFKC 规范化）
+    pub username: String,
+    /// 序列化格式（须与服务器一致）
+    pub codec: CodecKind,
+    /// 聊天室的密码或邀请码
+    pub access_key: Option<String>,
+    /// 加密私聊的密钥对，None 时每次连接生成临时密钥
+    pub identity: Option<Identity>,
+}
+
+impl SessionConfig {
+    /// 使用默认序列化格式
+    pub fn new(addr: impl Into<String>, username: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            username: username.into(),
+            codec: CodecKind::default(),
+            access_key: None,
+            identity: None,
+        }
+    }
+
+    /// 指定序列化格式
+    pub fn with_codec(mut self, codec: CodecKind) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// 指定密码保护或仅限邀请的聊天室所需的密码或邀请码
+    pub fn with_access_key(mut self, access_key: impl Into<String>) -> Self {
+        self.access_key = Some(access_key.into());
+        self
+    }
+
+    /// 指定加密私聊的密钥对（应在多次连接间保持不变，否则对方会看到密钥变更警告）
+    pub fn with_identity(mut self, identity: Identity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+}
+
+/// 历史消息搜索条件，见 `SessionHandle::search`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchRequest {
+    /// 关键词，以空白分隔，须全部出现
+    pub query: String,
+    /// 只搜索该用户发送的消息
+    pub from_user: Option<String>,
+    /// 时间范围（Unix 时间戳，秒，包含两端）
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    /// 翻页：上一页 `SearchResults` 事件的 `next_before`
+    pub before: Option<u64>,
+    /// 每页结果数
+    pub limit: u32,
+}
+
+impl SearchRequest {
+    /// 搜索关键词，不限用户和时间
+    pub fn new(query: impl Into<String>) -> Self {
+        Self {
+            query: query.into(),
+            from_user: None,
+            since: None,
+            until: None,
+            before: None,
+            limit: SEARCH_PAGE_SIZE,
+        }
+    }
+}
+
+/// 提交给后台任务的命令
+#[derive(Debug)]
+enum Command {
+    Chat {
+        content: String,
+        reply_to: Option<u64>,
+    },
+    Upload {
+        transfer_id: u32,
+        name: String,
+        data: Vec<u8>,
+    },
+    Download {
+        transfer_id: u32,
+        file_id: u64,
+        name: String,
+        size: u64,
+    },
+    Cancel {
+        transfer_id: u32,
+    },
+    Direct {
+        to: String,
+        content: String,
+    },
+    Search(SearchRequest),
+    MarkRead {
+        up_to: u64,
+    },
+    Disconnect,
+}
+
+/// 会话的发送端，可克隆后在其他任务中使用
+///
+/// 会话结束后所有操作返回 `SessionError::Closed`。
+#[derive(Debug, Clone)]
+pub struct SessionHandle {
+    cmd_tx: mpsc::Sender<Command>,
+    next_transfer_id: Arc<AtomicU32>,
+}
+
+impl SessionHandle {
+    async fn command(&self, cmd: Command) -> Result<()> {
+        self.cmd_tx.send(cmd).await.map_err(|_| SessionError::Closed)
+    }
+
+    fn allocate_transfer_id(&self) -> u32 {
+        self.next_transfer_id.fetch_add(1, Ordering::Relaxed).wrapping_add(1)
+    }
+
+    /// 发送聊天消息，`reply_to` 为回复的消息 ID
+    pub async fn send_chat(&self, content: impl Into<String>, reply_to: Option<u64>) -> Result<()> {
+        self.command(Command::Chat {
+            content: content.into(),
+            reply_to,
+        })
+        .await
+    }
+
+    /// 发送端到端加密的私聊
+    ///
+    /// 使用对方加入时公布的公钥加密；对方没有公钥或消息过长时产生 `DirectFailed` 事件。
+    /// 发送成功后收到与对方相同的 `DirectMessage` 回显。
+    pub async fn send_direct(&self, to: impl Into<String>, content: impl Into<String>) -> Result<()> {
+        self.command(Command::Direct {
+            to: to.into(),
+            content: content.into(),
+        })
+        .await
+    }
+
+    /// 搜索服务器保存的历史消息
+    ///
+    /// 结果通过 `SearchResults` 事件返回；服务器未启用消息历史时收到
+    /// `ErrorCode::SearchUnavailable` 的 `ServerError` 事件。
+    pub async fn search(&self, request: SearchRequest) -> Result<()> {
+        self.command(Command::Search(request)).await
+    }
+
+    /// 告诉服务器已看到 ID 不大于 `up_to` 的所有消息
+    ///
+    /// 服务器把已读位置广播给所有用户（`ReadReceipts` 事件）；不想公开已读状态时不要调用。
+    pub async fn mark_read(&self, up_to: u64) -> Result<()> {
+        self.command(Command::MarkRead { up_to }).await
+    }
+
+    /// 让服务器清除自己的已读位置，其他用户收到 `up_to` 为 0 的回执
+    ///
+    /// 关闭已读回执时调用，否则之前的位置会一直保留，之后使用同一名字的用户也会沿用它。
+    pub async fn clear_read(&self) -> Result<()> {
+        self.command(Command::MarkRead { up_to: 0 }).await
+    }
+
+    /// 上传内存中的文件，返回传输 ID
+    ///
+    /// 进度和结果通过 `TransferProgress`、`UploadComplete`、`TransferFailed` 事件报告。
+    pub async fn upload(&self, name: impl Into<String>, data: Vec<u8>) -> Result<u32> {
+        let size = data.len() as u64;
+        if size > MAX_FILE_SIZE {
+            return Err(SessionError::FileTooLarge {
+                size,
+                max: MAX_FILE_SIZE,
+            });
+        }
+        let transfer_id = self.allocate_transfer_id();
+        self.command(Command::Upload {
+            transfer_id,
+            name: name.into(),
+            data,
+        })
+        .await?;
+        Ok(transfer_id)
+    }
+
+    /// 读取并上传本地文件，返回传输 ID
+    pub async fn upload_file(&self, path: impl AsRef<Path>) -> Result<u32> {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or(SessionError::InvalidFileName)?
+            .to_string();
+        let size = tokio::fs::metadata(path).await?.len();
+        if size > MAX_FILE_SIZE {
+            return Err(SessionError::FileTooLarge {
+                size,
+                max: MAX_FILE_SIZE,
+            });
+        }
+        let data = tokio::fs::read(path).await?;
+        self.upload(name, data).await
+    }
+
+    /// 下载服务器上的文件，返回传输 ID
+    ///
+    /// `name` 仅用于 `TransferStarted` 事件；内容在 `DownloadComplete` 事件中返回。
+    pub async fn download(&self, file_id: u64, name: impl Into<String>, size: u64) -> Result<u32> {
+        let transfer_id = self.allocate_transfer_id();
+        self.command(Command::Download {
+            transfer_id,
+            file_id,
+            name: name.into(),
+            size,
+        })
+        .await?;
+        Ok(transfer_id)
+    }
+
+    /// 取消传输
+    pub async fn cancel_transfer(&self, transfer_id: u32) -> Result<()> {
+        self.command(Command::Cancel { transfer_id }).await
+    }
+
+    /// 离开聊天室并断开连接
+    ///
+    /// 断开后事件流以 `Disconnected { reason: Requested }` 结束。
+    pub async fn disconnect(&self) -> Result<()> {
+        self.command(Command::Disconnect).await
+    }
+}
+
+/// 已加入聊天室的会话
+///
+/// 丢弃会话（以及所有 `SessionHandle`）时后台任务发送 Leave 后退出。
+#[derive(Debug)]
+pub struct ChatSession {
+    user_id: u32,
+    username: String,
+    online_users: Vec<OnlineUser>,
+    topic: Option<String>,
+    identity: Identity,
+    handle: SessionHandle,
+    event_rx: mpsc::Receiver<SessionEvent>,
+}
+
+impl ChatSession {
+    /// 连接服务器并加入聊天室
+    ///
+    /// 返回时已收到 Welcome 和完整的在线用户列表；服务器拒绝加入时返回 `SessionError::Rejected`。
+    pub async fn connect(config: SessionConfig) -> Result<Self> {
+        let username = normalize_username(&config.username);
+        validate_username(&username)?;
+        let identity = match config.identity.clone() {
+            Some(identity) => identity,
+            None => Identity::generate()?,
+        };
+
+        let transport_config = TransportConfig {
+            connect_timeout: CONNECT_TIMEOUT,
+            nodelay: true,
+        };
+        let transport = TcpTransport::connect(&config.addr, &transport_config)
+            .await
+            .map_err(SessionError::Connect)?;
+        info!("Connected to {}", config.addr);
+
+        let mut conn = Connection::with_codec(transport, config.codec);
+        conn.send(&ClientMessage::Join {
+            username: username.clone(),
+            compression: Compression::SUPPORTED.to_vec(),
+            access_key: config.access_key.clone(),
+            public_key: Some(identity.public_key().to_vec()),
+        })
+        .await?;
+
+        let welcome = timeout(JOIN_TIMEOUT, conn.recv::<ServerMessage>())
+            .await
+            .map_err(|_| ProtocolError::ConnectionTimeout)??;
+        let (user_id, topic) = match welcome {
+            ServerMessage::Welcome {
+                user_id,
+                compression,
+                topic,
+            } => {
+                conn.set_compression(compression);
+                (user_id, topic)
+            }
+            ServerMessage::Error { code, message } => {
+                debug!("Join rejected: {:?} {:?}", code, message);
+                return Err(SessionError::Rejected { code, message });
+            }
+            _ => return Err(SessionError::UnexpectedResponse),
+        };
+        info!("Joined as user_id={}", user_id);
+
+        // 在线用户列表可能分成多帧
+        let mut online_users = Vec::new();
+        loop {
+            let msg = timeout(JOIN_TIMEOUT, conn.recv::<ServerMessage>())
+                .await
+                .map_err(|_| ProtocolError::ConnectionTimeout)??;
+            let ServerMessage::OnlineUsers { users, more } = msg else {
+                return Err(SessionError::UnexpectedResponse);
+            };
+            online_users.extend(users);
+            if !more {
+                break;
+            }
+        }
+
+        let (cmd_tx, cmd_rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let (event_tx, event_rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let (reader, writer) = conn.split();
+        let chunk_size = config.codec.max_chunk_size();
+        let keys = KeyRing::new(identity.clone(), username.clone(), &online_users);
+        tokio::spawn(async move {
+            let reason = run(reader, writer, chunk_size, keys, cmd_rx, &event_tx).await;
+            info!("Session ended: {:?}", reason);
+            let _ = event_tx.send(SessionEvent::Disconnected { reason }).await;
+        });
+
+        Ok(Self {
+            user_id,
+            username,
+            online_users,
+            topic,
+            identity,
+            handle: SessionHandle {
+                cmd_tx,
+                next_transfer_id: Arc::new(AtomicU32::new(0)),
+            },
+            event_rx,
+        })
+    }
+
+    /// 服务器分配的用户 ID
+    pub fn user_id(&self) -> u32 {
+        self.user_id
+    }
+
+    /// 规范化后的用户名（与服务器保存的一致，`/nick` 改名后随之更新）
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    /// 加入时的在线用户列表，之后的变化通过 `UserJoined`/`UserLeft` 事件报告
+    pub fn online_users(&self) -> &[OnlineUser] {
+        &self.online_users
+    }
+
+    /// 聊天室当前话题（随 `TopicChanged` 事件更新）
+    pub fn topic(&self) -> Option<&str> {
+        self.topic.as_deref()
+    }
+
+    /// 本会话加密私聊使用的密钥对
+    pub fn identity(&self) -> &Identity {
+        &self.identity
+    }
+
+    /// 会话的发送端
+    pub fn handle(&self) -> &SessionHandle {
+        &self.handle
+    }
+
+    /// 等待下一个事件，`Disconnected` 之后返回 None
+    ///
+    /// 此方法是取消安全的，可以在 `tokio::select!` 中使用。
+    pub async fn next_event(&mut self) -> Option<SessionEvent> {
+        let event = self.event_rx.recv().await;
+        match &event {
+            Some(SessionEvent::UserRenamed { old_username, new_username }) if *old_username == self.username => {
+                self.username = new_username.clone();
+            }
+            Some(SessionEvent::TopicChanged { topic, .. }) => self.topic = Some(topic.clone()),
+            _ => {}
+        }
+        event
+    }
+
+    /// 发送聊天消息，见 `SessionHandle::send_chat`
+    pub async fn send_chat(&self, content: impl Into<String>, reply_to: Option<u64>) -> Result<()> {
+        self.handle.send_chat(content, reply_to).await
+    }
+
+    /// 离开聊天室，见 `SessionHandle::disconnect`
+    pub async fn disconnect(&self) -> Result<()> {
+        self.handle.disconnect().await
+    }
+}
+
+/// 加密私聊的密钥：自己的密钥对和在线用户的公钥（按用户名比较键索引）
+struct KeyRing {
+    identity: Identity,
+    username: String,
+    keys: HashMap<String, Vec<u8>>,
+}
+
+impl KeyRing {
+    fn new(identity: Identity, username: String, online_users: &[OnlineUser]) -> Self {
+        let keys = online_users
+            .iter()
+            .filter_map(|u| Some((username_key(&u.username), u.public_key.clone()?)))
+            .collect();
+        Self { identity, username, keys }
+    }
+
+    fn set(&mut self, username: &str, public_key: Option<Vec<u8>>) {
+        match public_key {
+            Some(key) => self.keys.insert(username_key(username), key),
+            None => self.keys.remove(&username_key(username)),
+        };
+    }
+
+    fn rename(&mut self, old_username: &str, new_username: &str) {
+        if username_key(old_username) == username_key(&self.username) {
+            self.username = new_username.to_string();
+        }
+        let key = self.keys.remove(&username_key(old_username));
+        self.set(new_username, key);
+    }
+
+    fn encrypt(&self, to: &str, content: &str) -> std::result::Result<(Vec<u8>, Vec<u8>), E2eError> {
+        let key = self.keys.get(&username_key(to)).ok_or_else(|| E2eError::NoPublicKey {
+            username: to.to_string(),
+        })?;
+        self.identity.encrypt(key, content.as_bytes())
+    }
+
+    /// 解密 `from` 发给 `to` 的消息；自己发出的回显用收件人的公钥解密
+    ///
+    /// 密钥与方向绑定，声称的发送者与实际不符时解密失败。
+    fn decrypt(&self, from: &str, to: &str, nonce: &[u8], ciphertext: &[u8]) -> std::result::Result<String, E2eError> {
+        let plaintext = if username_key(from) == username_key(&self.username) {
+            let key = self.keys.get(&username_key(to)).ok_or(E2eError::Decrypt)?;
+            self.identity.decrypt_sent(key, nonce, ciphertext)?
+        } else {
+            let key = self.keys.get(&username_key(from)).ok_or(E2eError::Decrypt)?;
+            self.identity.decrypt(key, nonce, ciphertext)?
+        };
+        String::from_utf8(plaintext).map_err(|_| E2eError::Decrypt)
+    }
+}
+
+/// 后台任务：收发消息直到连接断开，返回断开原因
+async fn run<R, W>(
+    mut reader: FrameReader<R, CodecKind>,
+    mut writer: FrameWriter<W, CodecKind>,
+    chunk_size: usize,
+    mut keys: KeyRing,
+    mut cmd_rx: mpsc::Receiver<Command>,
+    event_tx: &mpsc::Sender<SessionEvent>,
+) -> DisconnectReason
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    // 心跳定时器
+    let mut heartbeat = interval(HEARTBEAT_INTERVAL);
+    heartbeat.tick().await; // 跳过第一次立即触发
+
+    // 文件传输状态
+    let mut transfers = TransferManager::new(chunk_size);
+
+    // 加密私聊的密文与文件分块受同样的帧大小限制
+    let max_direct_len = chunk_size.min(MAX_CIPHERTEXT_LEN) - AEAD_TAG_LEN;
+
+    loop {
+        tokio::select! {
+            // 接收服务器消息
+            result = reader.recv::<ServerMessage>() => {
+                let msg = match result {
+                    Ok(msg) => msg,
+                    Err(ProtocolError::ConnectionClosed) => {
+                        info!("Server closed connection");
+                        return DisconnectReason::ConnectionClosed;
+                    }
+                    Err(e) => {
+                        warn!("Receive error: {}", e);
+                        return DisconnectReason::Error(e.to_string());
+                    }
+                };
+                let event = match msg {
+                    ServerMessage::ChatBroadcast { message_id, username, content, timestamp, reply_to, mentions } => {
+                        Some(SessionEvent::ChatMessage { message_id, username, content, timestamp, reply_to, mentions })
+                    }
+                    ServerMessage::ActionBroadcast { message_id, username, content, timestamp } => {
+                        Some(SessionEvent::ActionMessage { message_id, username, content, timestamp })
+                    }
+                    ServerMessage::DirectMessage { message_id, from, to, content, timestamp } => {
+                        Some(SessionEvent::DirectMessage { message_id, from, to, content, timestamp, encrypted: false, offline: false })
+                    }
+                    ServerMessage::OfflineDirect { message_id, from, to, content, timestamp } => {
+                        Some(SessionEvent::DirectMessage { message_id, from, to, content, timestamp, encrypted: false, offline: true })
+                    }
+                    ServerMessage::EncryptedDirect { message_id, from, to, nonce, ciphertext, timestamp } => {
+                        Some(match keys.decrypt(&from, &to, &nonce, &ciphertext) {
+                            Ok(content) => SessionEvent::DirectMessage { message_id, from, to, content, timestamp, encrypted: true, offline: false },
+                            Err(_) => {
+                                warn!("Could not decrypt direct message #{} from {}", message_id, from);
+                                SessionEvent::UndecryptableMessage { message_id, from, to, timestamp }
+                            }
+                        })
+                    }
+                    ServerMessage::UserRenamed { old_username, new_username } => {
+                        keys.rename(&old_username, &new_username);
+                        Some(SessionEvent::UserRenamed { old_username, new_username })
+                    }
+                    ServerMessage::TopicChanged { topic, username } => Some(SessionEvent::TopicChanged { topic, username }),
+                    ServerMessage::CommandReply { reply } => Some(SessionEvent::CommandReply { reply }),
+                    ServerMessage::SearchResults { before, hits, next_before } => {
+                        Some(SessionEvent::SearchResults { before, hits, next_before })
+                    }
+                    ServerMessage::ReadReceipts { receipts } => Some(SessionEvent::ReadReceipts { receipts }),
+                    ServerMessage::Motd { message } => Some(SessionEvent::Motd { message }),
+                    ServerMessage::UserJoined { username, role, public_key } => {
+                        keys.set(&username, public_key.clone());
+                        Some(SessionEvent::UserJoined { username, role, public_key })
+                    }
+                    ServerMessage::RoleChanged { username, role, by } => {
+                        Some(SessionEvent::RoleChanged { username, role, by })
+                    }
+                    ServerMessage::UserLeft { username } => {
+                        keys.set(&username, None);
+                        Some(SessionEvent::UserLeft { username })
+                    }
+                    ServerMessage::FileShared { file_id, username, name, size, mime, timestamp } => {
+                        Some(SessionEvent::FileShared { file_id, username, name, size, mime, timestamp })
+                    }
+                    ServerMessage::FileAccepted { transfer_id } => {
+                        transfers.accept_upload(transfer_id);
+                        None
+                    }
+                    ServerMessage::FileChunk { transfer_id, index, data, checksum } => {
+                        match transfers.receive_chunk(transfer_id, index, &data, checksum) {
+                            Ok(received) => {
+                                (index + 1).is_multiple_of(PROGRESS_REPORT_CHUNKS).then_some(
+                                    SessionEvent::TransferProgress { transfer_id, transferred: received },
+                                )
+                            }
+                            Err(error) => {
+                                transfers.remove(transfer_id);
+                                if let Err(e) = writer.send(&ClientMessage::FileCancel { transfer_id }).await {
+                                    warn!("Failed to cancel transfer: {}", e);
+                                    return DisconnectReason::Error(e.to_string());
+                                }
+                                Some(SessionEvent::TransferFailed { transfer_id, error })
+                            }
+                        }
+                    }
+                    ServerMessage::FileComplete { transfer_id } => {
+                        if transfers.is_upload(transfer_id) {
+                            transfers.remove(transfer_id);
+                            Some(SessionEvent::UploadComplete { transfer_id })
+                        } else {
+                            Some(match transfers.finish_download(transfer_id) {
+                                Ok((file_id, data)) => SessionEvent::DownloadComplete { transfer_id, file_id, data },
+                                Err(error) => SessionEvent::TransferFailed { transfer_id, error },
+                            })
+                        }
+                    }
+                    ServerMessage::FileCancelled { transfer_id, reason, .. } => {
+                        transfers.remove(transfer_id);
+                        Some(SessionEvent::TransferFailed { transfer_id, error: TransferError::Server(reason) })
+                    }
+                    ServerMessage::Error { code, message } => {
+                        debug!("Server error: {:?} {:?}", code, message);
+                        Some(SessionEvent::ServerError { code, message })
+                    }
+                    ServerMessage::Pong => {
+                        debug!("Received pong");
+                        None
+                    }
+                    ServerMessage::Welcome { .. } | ServerMessage::OnlineUsers { .. } => {
+                        // 忽略重复的 Welcome 和在线用户列表
+                        None
+                    }
+                    ServerMessage::Shutdown { message } => {
+                        info!("Server shutdown: {}", message);
+                        return DisconnectReason::ServerShutdown { message };
+                    }
+                };
+                if let Some(event) = event {
+                    let _ = event_tx.send(event).await;
+                }
+            }
+
+            // 心跳
+            _ = heartbeat.tick() => {
+                if let Err(e) = writer.send(&ClientMessage::Ping).await {
+                    warn!("Failed to send ping: {}", e);
+                    return DisconnectReason::Error(e.to_string());
+                }
+                debug!("Sent ping");
+            }
+
+            // 发送排队的上传和下载请求（不超过服务器的同时传输上限）
+            _ = std::future::ready(()), if transfers.has_ready_request() => {
+                if let Some(msg) = transfers.next_request() {
+                    if let Err(e) = writer.send(&msg).await {
+                        warn!("Failed to send transfer request: {}", e);
+                        return DisconnectReason::Error(e.to_string());
+                    }
+                }
+            }
+
+            // 发送上传分块（每轮只发送一块，不阻塞其他消息的收发）
+            _ = std::future::ready(()), if transfers.has_pending_chunks() => {
+                if let Some((transfer_id, msg, sent)) = transfers.next_upload_message() {
+                    let report = match &msg {
+                        ClientMessage::FileChunk { index, .. } => (index + 1).is_multiple_of(PROGRESS_REPORT_CHUNKS),
+                        _ => true,
+                    };
+                    if let Err(e) = writer.send(&msg).await {
+                        warn!("Failed to send file chunk: {}", e);
+                        return DisconnectReason::Error(e.to_string());
+                    }
+                    if report {
+                        let _ = event_tx.send(SessionEvent::TransferProgress {
+                            transfer_id,
+                            transferred: sent,
+                        }).await;
+                    }
+                }
+            }
+
+            // 处理命令
+            cmd = cmd_rx.recv() => {
+                let result = match cmd {
+                    Some(Command::Chat { content, reply_to }) => {
+                        writer.send(&ClientMessage::Chat { content, reply_to }).await
+                    }
+                    Some(Command::Direct { to, content }) => {
+                        let encrypted = if content.len() > max_direct_len {
+                            Err(E2eError::TooLong { len: content.len(), max: max_direct_len })
+                        } else {
+                            keys.encrypt(&to, &content)
+                        };
+                        match encrypted {
+                            Ok((nonce, ciphertext)) => {
+                                writer.send(&ClientMessage::EncryptedDirect { to, nonce, ciphertext }).await
+                            }
+                            Err(error) => {
+                                let _ = event_tx.send(SessionEvent::DirectFailed { to, error }).await;
+                                Ok(())
+                            }
+                        }
+                    }
+                    Some(Command::Search(request)) => {
+                        let SearchRequest { query, from_user, since, until, before, limit } = request;
+                        writer.send(&ClientMessage::Search { query, from_user, since, until, before, limit }).await
+                    }
+                    Some(Command::MarkRead { up_to }) => writer.send(&ClientMessage::MarkRead { up_to }).await,
+                    Some(Command::Upload { transfer_id, name, data }) => {
+                        let size = data.len() as u64;
+                        let mime = mime_from_name(&name).to_string();
+                        transfers.start_upload(transfer_id, data);
+                        let _ = event_tx.send(SessionEvent::TransferStarted {
+                            transfer_id,
+                            name: name.clone(),
+                            direction: TransferDirection::Upload,
+                            total: size,
+                        }).await;
+                        transfers.queue_request(ClientMessage::FileOffer { transfer_id, name, size, mime });
+                        Ok(())
+                    }
+                    Some(Command::Download { transfer_id, file_id, name, size }) => {
+                        transfers.start_download(transfer_id, file_id, size);
+                        let _ = event_tx.send(SessionEvent::TransferStarted {
+                            transfer_id,
+                            name,
+                            direction: TransferDirection::Download,
+                            total: size,
+                        }).await;
+                        transfers.queue_request(ClientMessage::FileDownload { transfer_id, file_id });
+                        Ok(())
+                    }
+                    Some(Command::Cancel { transfer_id }) => {
+                        transfers.remove(transfer_id);
+                        let result = writer.send(&ClientMessage::FileCancel { transfer_id }).await;
+                        let _ = event_tx.send(SessionEvent::TransferFailed {
+                            transfer_id,
+                            error: TransferError::Cancelled,
+                        }).await;
+                        result
+                    }
+                    // 会话和所有句柄都已丢弃时同样离开
+                    Some(Command::Disconnect) | None => {
+                        let _ = writer.send(&ClientMessage::Leave).await;
+                        return DisconnectReason::Requested;
+                    }
+                };
+                if let Err(e) = result {
+                    warn!("Failed to send message: {}", e);
+                    return DisconnectReason::Error(e.to_string());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::{ErrorCode, ReadReceipt, Role, SearchHit, TcpListener, TransportListener};
+
+    /// 启动只接受一个连接的测试服务器，返回其地址
+    async fn fake_server<F, Fut>(serve: F) -> String
+    where
+        F: FnOnce(Connection<tokio::net::tcp::OwnedReadHalf, tokio::net::tcp::OwnedWriteHalf, CodecKind>) -> Fut
+            + Send
+            + 'static,
+        Fut: std::future::Future<Output = ()> + Send,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let transport = listener.accept().await.unwrap();
+            serve(Connection::with_codec(transport, CodecKind::default())).await;
+        });
+        addr
+    }
+
+    /// 在线用户 bob 的密钥对
+    fn bob() -> Identity {
+        Identity::from_secret_bytes([9; 32])
+    }
+
+    /// 完成 Join 握手，返回客户端使用的用户名和公钥
+    async fn accept_join(
+        conn: &mut Connection<tokio::net::tcp::OwnedReadHalf, tokio::net::tcp::OwnedWriteHalf, CodecKind>,
+    ) -> (String, Vec<u8>) {
+        let ClientMessage::Join { username, public_key, .. } = conn.recv().await.unwrap() else {
+            panic!("expected Join");
+        };
+        conn.send(&ServerMessage::Welcome {
+            user_id: 7,
+            compression: None,
+            topic: Some("release day".to_string()),
+        })
+        .await
+        .unwrap();
+        // 在线用户列表分两帧发送
+        conn.send(&ServerMessage::OnlineUsers {
+            users: vec![OnlineUser {
+                username: "bob".to_string(),
+                role: Role::Operator,
+                public_key: Some(bob().public_key().to_vec()),
+            }],
+            more: true,
+        })
+        .await
+        .unwrap();
+        conn.send(&ServerMessage::OnlineUsers {
+            users: vec![OnlineUser { username: username.clone(), role: Role::Member, public_key: public_key.clone() }],
+            more: false,
+        })
+        .await
+        .unwrap();
+        (username, public_key.expect("client should publish a key"))
+    }
+
+    #[tokio::test]
+    async fn test_connect_chat_and_disconnect() {
+        let addr = fake_server(|mut conn| async move {
+            let (username, _) = accept_join(&mut conn).await;
+            let ClientMessage::Chat { content, reply_to } = conn.recv().await.unwrap() else {
+                panic!("expected Chat");
+            };
+            conn.send(&ServerMessage::ChatBroadcast {
+                message_id: 1,
+                username,
+                content,
+                timestamp: 0,
+                reply_to,
+                mentions: vec![],
+            })
+            .await
+            .unwrap();
+            assert_eq!(conn.recv::<ClientMessage>().await.unwrap(), ClientMessage::Leave);
+        })
+        .await;
+
+        // 全角字符规范化后与服务端保存的名字一致
+        let mut session = ChatSession::connect(SessionConfig::new(addr, "ａｌｉｃｅ")).await.unwrap();
+        assert_eq!(session.user_id(), 7);
+        assert_eq!(session.username(), "alice");
+        let online: Vec<_> = session.online_users().iter().map(|u| (u.username.as_str(), u.role)).collect();
+        assert_eq!(online, [("bob", Role::Operator), ("alice", Role::Member)]);
+        assert_eq!(
+            session.online_users()[1].public_key.as_deref(),
+            Some(&session.identity().public_key()[..])
+        );
+        assert_eq!(session.topic(), Some("release day"));
+
+        session.send_chat("hello", Some(3)).await.unwrap();
+        assert_eq!(
+            session.next_event().await,
+            Some(SessionEvent::ChatMessage {
+                message_id: 1,
+                username: "alice".to_string(),
+                content: "hello".to_string(),
+                timestamp: 0,
+                reply_to: Some(3),
+                mentions: vec![],
+            })
+        );
+
+        session.disconnect().await.unwrap();
+        assert_eq!(
+            session.next_event().await,
+            Some(SessionEvent::Disconnected {
+                reason: DisconnectReason::Requested
+            })
+        );
+        assert_eq!(session.next_event().await, None);
+        assert!(matches!(session.send_chat("late", None).await, Err(SessionError::Closed)));
+    }
+
+    #[tokio::test]
+    async fn test_join_rejected() {
+        let addr = fake_server(|mut conn| async move {
+            let _: ClientMessage = conn.recv().await.unwrap();
+            conn.send(&ServerMessage::error(ErrorCode::UsernameTaken, "taken"))
+                .await
+                .unwrap();
+        })
+        .await;
+
+        let result = ChatSession::connect(SessionConfig::new(addr, "alice")).await;
+        assert!(matches!(
+            result,
+            Err(SessionError::Rejected { code: ErrorCode::UsernameTaken, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_direct_messages() {
+        let addr = fake_server(|mut conn| async move {
+            let (username, alice_key) = accept_join(&mut conn).await;
+            let ClientMessage::EncryptedDirect { to, nonce, ciphertext } = conn.recv().await.unwrap() else {
+                panic!("expected EncryptedDirect");
+            };
+            assert_eq!(to, "Bob");
+            assert_eq!(bob().decrypt(&alice_key, &nonce, &ciphertext).unwrap(), b"secret");
+
+            // 回显给发送者
+            conn.send(&ServerMessage::EncryptedDirect {
+                message_id: 1,
+                from: username.clone(),
+                to: "bob".to_string(),
+                nonce: nonce.clone(),
+                ciphertext: ciphertext.clone(),
+                timestamp: 0,
+            })
+            .await
+            .unwrap();
+            let reflected = ServerMessage::EncryptedDirect {
+                message_id: 4,
+                from: "bob".to_string(),
+                to: username.clone(),
+                nonce,
+                ciphertext,
+                timestamp: 0,
+            };
+
+            // bob 的回复，以及一条被篡改的消息
+            let (nonce, mut ciphertext) = bob().encrypt(&alice_key, b"hello alice").unwrap();
+            for message_id in [2, 3] {
+                conn.send(&ServerMessage::EncryptedDirect {
+                    message_id,
+                    from: "bob".to_string(),
+                    to: username.clone(),
+                    nonce: nonce.clone(),
+                    ciphertext: ciphertext.clone(),
+                    timestamp: 0,
+                })
+                .await
+                .unwrap();
+                ciphertext[0] ^= 1;
+            }
+            // 把 alice 自己的密文冒充成 bob 发来的消息
+            conn.send(&reflected).await.unwrap();
+            let _ = conn.recv::<ClientMessage>().await;
+        })
+        .await;
+
+        let mut session = ChatSession::connect(SessionConfig::new(addr, "alice")).await.unwrap();
+        session.handle().send_direct("carol", "hi").await.unwrap();
+        assert_eq!(
+            session.next_event().await,
+            Some(SessionEvent::DirectFailed {
+                to: "carol".to_string(),
+                error: E2eError::NoPublicKey { username: "carol".to_string() },
+            })
+        );
+
+        session.handle().send_direct("Bob", "secret").await.unwrap();
+        assert!(matches!(
+            session.next_event().await,
+            Some(SessionEvent::DirectMessage { content, encrypted: true, .. }) if content == "secret"
+        ));
+        assert!(matches!(
+            session.next_event().await,
+            Some(SessionEvent::DirectMessage { from, content, encrypted: true, .. })
+                if from == "bob" && content == "hello alice"
+        ));
+        assert!(matches!(
+            session.next_event().await,
+            Some(SessionEvent::UndecryptableMessage { message_id: 3, .. })
+        ));
+        assert!(matches!(
+            session.next_event().await,
+            Some(SessionEvent::UndecryptableMessage { message_id: 4, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_access_key_sent_with_join() {
+        let addr = fake_server(|mut conn| async move {
+            let ClientMessage::Join { access_key, .. } = conn.recv().await.unwrap() else {
+                panic!("expected Join");
+            };
+            assert_eq!(access_key.as_deref(), Some("expired"));
+            conn.send(&ServerMessage::error(ErrorCode::InviteRequired, "invite required"))
+                .await
+                .unwrap();
+        })
+        .await;
+
+        let config = SessionConfig::new(addr, "alice").with_access_key("expired");
+        assert!(matches!(
+            ChatSession::connect(config).await,
+            Err(SessionError::Rejected { code: ErrorCode::InviteRequired, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let addr = fake_server(|mut conn| async move {
+            accept_join(&mut conn).await;
+            let ClientMessage::Search { query, from_user, before, limit, .. } = conn.recv().await.unwrap() else {
+                panic!("expected Search");
+            };
+            assert_eq!((query.as_str(), from_user.as_deref(), before, limit), ("deploy", Some("bob"), Some(50), 20));
+            let hit = SearchHit {
+                message_id: 12,
+                username: "bob".to_string(),
+                content: "deploy done".to_string(),
+                timestamp: 1234567890,
+                action: false,
+            };
+            conn.send(&ServerMessage::SearchResults { before, hits: vec![hit], next_before: Some(12) })
+                .await
+                .unwrap();
+        })
+        .await;
+
+        let mut session = ChatSession::connect(SessionConfig::new(addr, "alice")).await.unwrap();
+        let request = SearchRequest {
+            from_user: Some("bob".to_string()),
+            before: Some(50),
+            ..SearchRequest::new("deploy")
+        };
+        session.handle().search(request).await.unwrap();
+        let Some(SessionEvent::SearchResults { before, hits, next_before }) = session.next_event().await else {
+            panic!("expected SearchResults");
+        };
+        assert_eq!((before, next_before), (Some(50), Some(12)));
+        assert_eq!(hits[0].content, "deploy done");
+    }
+
+    #[tokio::test]
+    async fn test_read_receipts() {
+        let addr = fake_server(|mut conn| async move {
+            accept_join(&mut conn).await;
+            let ClientMessage::MarkRead { up_to } = conn.recv().await.unwrap() else {
+                panic!("expected MarkRead");
+            };
+            let receipt = ReadReceipt { username: "alice".to_string(), up_to };
+            conn.send(&ServerMessage::ReadReceipts { receipts: vec![receipt] }).await.unwrap();
+        })
+        .await;
+
+        let mut session = ChatSession::connect(SessionConfig::new(addr, "alice")).await.unwrap();
+        session.handle().mark_read(7).await.unwrap();
+        let Some(SessionEvent::ReadReceipts { receipts }) = session.next_event().await else {
+            panic!("expected ReadReceipts");
+        };
+        assert_eq!((receipts[0].username.as_str(), receipts[0].up_to), ("alice", 7));
+    }
+
+    #[tokio::test]
+    async fn test_upload() {
+        let addr = fake_server(|mut conn| async move {
+            accept_join(&mut conn).await;
+            let ClientMessage::FileOffer { transfer_id, size, .. } = conn.recv().await.unwrap() else {
+                panic!("expected FileOffer");
+            };
+            conn.send(&ServerMessage::FileAccepted { transfer_id }).await.unwrap();
+            let mut received = 0;
+            loop {
+                match conn.recv().await.unwrap() {
+                    ClientMessage::FileChunk { data, .. } => received += data.len() as u64,
+                    ClientMessage::FileComplete { .. } => break,
+                    other => panic!("unexpected {:?}", other),
+                }
+            }
+            assert_eq!(received, size);
+            conn.send(&ServerMessage::FileComplete { transfer_id }).await.unwrap();
+        })
+        .await;
+
+        let mut session = ChatSession::connect(SessionConfig::new(addr, "alice")).await.unwrap();
+        let data = vec![42u8; 10_000];
+        let transfer_id = session.handle().upload("a.bin", data).await.unwrap();
+
+        let mut events = Vec::new();
+        while let Some(event) = session.next_event().await {
+            let done = matches!(event, SessionEvent::UploadComplete { .. });
+            events.push(event);
+            if done {
+                break;
+            }
+        }
+        assert_eq!(
+            events.first(),
+            Some(&SessionEvent::TransferStarted {
+                transfer_id,
+                name: "a.bin".to_string(),
+                direction: TransferDirection::Upload,
+                total: 10_000,
+            })
+        );
+        assert_eq!(events.last(), Some(&SessionEvent::UploadComplete { transfer_id }));
+    }
+}
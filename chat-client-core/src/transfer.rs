@@ -0,0 +1,281 @@
+//! 文件传输状态
+
+use std::collections::{HashMap, VecDeque};
+
+use protocol::{chunk_checksum, ClientMessage, MAX_CONCURRENT_DOWNLOADS, MAX_CONCURRENT_UPLOADS};
+
+use crate::event::TransferError;
+
+/// 进行中的上传
+struct Upload {
+    data: Vec<u8>,
+    next_index: u32,
+    sent: usize,
+    /// 服务端是否已接受上传
+    accepted: bool,
+    /// 是否已发送 FileComplete，等待服务端确认
+    finished: bool,
+}
+
+/// 进行中的下载
+struct Download {
+    file_id: u64,
+    size: u64,
+    data: Vec<u8>,
+    next_index: u32,
+}
+
+/// 会话任务中的文件传输管理
+///
+/// 传输 ID 由 `SessionHandle` 分配，这里只记录各传输的进度。
+pub(crate) struct TransferManager {
+    uploads: HashMap<u32, Upload>,
+    downloads: HashMap<u32, Download>,
+    /// 尚未发出的上传请求（FileOffer）和下载请求（FileDownload）
+    ///
+    /// 服务器限制每个连接同时进行的传输数，超出的请求在这里排队，有传输结束后再发出。
+    queued: VecDeque<ClientMessage>,
+    /// 上传分块大小（取决于连接的序列化格式）
+    chunk_size: usize,
+}
+
+impl TransferManager {
+    pub(crate) fn new(chunk_size: usize) -> Self {
+        Self {
+            uploads: HashMap::new(),
+            downloads: HashMap::new(),
+            queued: VecDeque::new(),
+            chunk_size,
+        }
+    }
+
+    /// 登记新的上传
+    pub(crate) fn start_upload(&mut self, transfer_id: u32, data: Vec<u8>) {
+        self.uploads.insert(
+            transfer_id,
+            Upload {
+                data,
+                next_index: 0,
+                sent: 0,
+                accepted: false,
+                finished: false,
+            },
+        );
+    }
+
+    /// 服务端接受了上传
+    pub(crate) fn accept_upload(&mut self, transfer_id: u32) {
+        if let Some(upload) = self.uploads.get_mut(&transfer_id) {
+            upload.accepted = true;
+        }
+    }
+
+    /// 是否有待发送的上传分块
+    pub(crate) fn has_pending_chunks(&self) -> bool {
+        self.uploads.values().any(|u| u.accepted && !u.finished)
+    }
+
+    /// 取出下一条要发送的上传消息，返回 (传输 ID, 消息, 已发送字节数)
+    ///
+    /// 所有分块发送完后返回 FileComplete。
+    pub(crate) fn next_upload_message(&mut self) -> Option<(u32, ClientMessage, u64)> {
+        let (&transfer_id, upload) = self
+            .uploads
+            .iter_mut()
+            .find(|(_, u)| u.accepted && !u.finished)?;
+
+        if upload.sent >= upload.data.len() {
+            upload.finished = true;
+            return Some((
+                transfer_id,
+                ClientMessage::FileComplete { transfer_id },
+                upload.sent as u64,
+            ));
+        }
+
+        let end = (upload.sent + self.chunk_size).min(upload.data.len());
+        let data = upload.data[upload.sent..end].to_vec();
+        let index = upload.next_index;
+        upload.next_index += 1;
+        upload.sent = end;
+
+        let msg = ClientMessage::FileChunk {
+            transfer_id,
+            index,
+            checksum: chunk_checksum(&data),
+            data,
+        };
+        Some((transfer_id, msg, end as u64))
+    }
+
+    /// 登记新的下载
+    pub(crate) fn start_download(&mut self, transfer_id: u32, file_id: u64, size: u64) {
+        self.downloads.insert(
+            transfer_id,
+            Download {
+                file_id,
+                size,
+                data: Vec::with_capacity(size as usize),
+                next_index: 0,
+            },
+        );
+    }
+
+    /// 接收下载分块，返回已接收字节数
+    pub(crate) fn receive_chunk(
+        &mut self,
+        transfer_id: u32,
+        index: u32,
+        data: &[u8],
+        checksum: u32,
+    ) -> Result<u64, TransferError> {
+        let download = self
+            .downloads
+            .get_mut(&transfer_id)
+            .ok_or(TransferError::Unknown)?;
+        if index != download.next_index {
+            return Err(TransferError::OutOfOrder { index });
+        }
+        if chunk_checksum(data) != checksum {
+            return Err(TransferError::ChecksumMismatch { index });
+        }
+        if download.data.len() + data.len() > download.size as usize {
+            return Err(TransferError::SizeMismatch);
+        }
+        download.data.extend_from_slice(data);
+        download.next_index += 1;
+        Ok(download.data.len() as u64)
+    }
+
+    /// 是否为进行中的上传
+    pub(crate) fn is_upload(&self, transfer_id: u32) -> bool {
+        self.uploads.contains_key(&transfer_id)
+    }
+
+    /// 完成下载，返回文件 ID 和文件内容
+    pub(crate) fn finish_download(&mut self, transfer_id: u32) -> Result<(u64, Vec<u8>), TransferError> {
+        let download = self
+            .downloads
+            .remove(&transfer_id)
+            .ok_or(TransferError::Unknown)?;
+        if download.data.len() as u64 != download.size {
+            return Err(TransferError::Incomplete);
+        }
+        Ok((download.file_id, download.data))
+    }
+
+    /// 移除传输（完成、取消或失败）
+    pub(crate) fn remove(&mut self, transfer_id: u32) {
+        self.uploads.remove(&transfer_id);
+        self.downloads.remove(&transfer_id);
+        self.queued.retain(|msg| request_transfer_id(msg) != Some(transfer_id));
+    }
+
+    /// 排队一个上传或下载请求，由 `next_request` 在不超过同时传输上限时取出发送
+    pub(crate) fn queue_request(&mut self, msg: ClientMessage) {
+        self.queued.push_back(msg);
+    }
+
+    /// 是否有可以发出的请求
+    pub(crate) fn has_ready_request(&self) -> bool {
+        self.queued.iter().any(|msg| self.has_capacity(msg))
+    }
+
+    /// 取出下一个可以发出的请求
+    pub(crate) fn next_request(&mut self) -> Option<ClientMessage> {
+        let pos = self.queued.iter().position(|msg| self.has_capacity(msg))?;
+        self.queued.remove(pos)
+    }
+
+    /// 请求发出后同类传输数是否仍在服务器的上限之内
+    fn has_capacity(&self, msg: &ClientMessage) -> bool {
+        let queued = |upload: bool| {
+            self.queued
+                .iter()
+                .filter(|m| matches!(m, ClientMessage::FileOffer { .. }) == upload)
+                .count()
+        };
+        if matches!(msg, ClientMessage::FileOffer { .. }) {
+            self.uploads.len() - queued(true) < MAX_CONCURRENT_UPLOADS
+        } else {
+            self.downloads.len() - queued(false) < MAX_CONCURRENT_DOWNLOADS
+        }
+    }
+}
+
+/// 上传或下载请求的传输 ID
+fn request_transfer_id(msg: &ClientMessage) -> Option<u32> {
+    match msg {
+        ClientMessage::FileOffer { transfer_id, .. } | ClientMessage::FileDownload { transfer_id, .. } => {
+            Some(*transfer_id)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_download_rejects_bad_chunks() {
+        let mut transfers = TransferManager::new(4);
+        transfers.start_download(1, 7, 4);
+
+        let data = [1u8, 2];
+        assert_eq!(
+            transfers.receive_chunk(1, 1, &data, chunk_checksum(&data)),
+            Err(TransferError::OutOfOrder { index: 1 })
+        );
+        assert_eq!(
+            transfers.receive_chunk(1, 0, &data, 0),
+            Err(TransferError::ChecksumMismatch { index: 0 })
+        );
+        assert_eq!(transfers.receive_chunk(1, 0, &data, chunk_checksum(&data)), Ok(2));
+        assert_eq!(transfers.finish_download(1), Err(TransferError::Incomplete));
+        assert_eq!(transfers.finish_download(1), Err(TransferError::Unknown));
+    }
+
+    #[test]
+    fn test_requests_wait_for_a_free_slot() {
+        let mut transfers = TransferManager::new(4);
+        let count = MAX_CONCURRENT_DOWNLOADS as u32 + 1;
+        for transfer_id in 0..count {
+            transfers.start_download(transfer_id, u64::from(transfer_id), 0);
+            transfers.queue_request(ClientMessage::FileDownload { transfer_id, file_id: u64::from(transfer_id) });
+        }
+        transfers.start_upload(count, vec![0; 6]);
+        transfers.queue_request(ClientMessage::FileOffer {
+            transfer_id: count,
+            name: "a.txt".to_string(),
+            size: 6,
+            mime: "text/plain".to_string(),
+        });
+
+        let sent: Vec<u32> = std::iter::from_fn(|| transfers.next_request())
+            .filter_map(|msg| request_transfer_id(&msg))
+            .collect();
+        assert_eq!(sent, vec![0, 1, 2, 3, count]);
+        assert!(!transfers.has_ready_request());
+
+        // 一个下载结束后才发出排队的请求；排队中取消的请求不再发出
+        transfers.finish_download(0).unwrap();
+        assert!(transfers.has_ready_request());
+        transfers.remove(count - 1);
+        assert!(!transfers.has_ready_request());
+    }
+
+    #[test]
+    fn test_upload_chunks_then_complete() {
+        let mut transfers = TransferManager::new(4);
+        transfers.start_upload(3, vec![0; 6]);
+        assert!(!transfers.has_pending_chunks());
+
+        transfers.accept_upload(3);
+        let sent: Vec<u64> = std::iter::from_fn(|| transfers.next_upload_message())
+            .map(|(_, _, sent)| sent)
+            .collect();
+        assert_eq!(sent, vec![4, 6, 6]);
+        assert!(!transfers.has_pending_chunks());
+    }
+}
@@ -0,0 +1,200 @@
+# 客户端界面文本（英文）
+
+app-title = Chat Room
+title-mentioned = 🔔 ({ $count }) Someone mentioned you
+system-user = System
+time-just-now = just now
+
+## 顶部面板
+heading = 💬 Chat Room
+status-disconnected = ● Disconnected
+status-connecting = Connecting...
+status-connected = ● Connected
+toggle-users = 👥 Users
+toggle-search = Search message history
+mentions-hover = Someone mentioned you, click to clear
+
+## 输入区
+transfer-cancel-hover = Cancel transfer
+upload-label = File:
+upload-hint = Enter a file path, or drop a file onto the window
+upload-button = ⬆ Upload
+upload-hover = Upload file
+reply-preview = ↪ Replying to { $user }: { $preview }
+reply-unknown = ↪ Replying to a message
+reply-cancel-hover = Cancel reply
+input-hint = Type a message, press Enter to send...
+send-button = Send
+
+## 登录表单
+login-server = Server:
+login-username = Username:
+login-username-hint = letters, digits, _ or -
+login-access-key = Key:
+login-access-key-hint = password / invite / role key
+login-codec-hover = Serialization format (must match the server)
+login-profile = Profile:
+profile-none = (none)
+profile-name-hint = profile name
+profile-save = Save
+profile-save-hover = Save the server, username and format under this name
+profile-remember-key = Remember key
+profile-delete = Delete
+profile-auto-connect = Connect on startup
+tab-new-hover = Open a connection to another server
+tab-close-hover = Close this tab and disconnect
+connect-button = 🔗 Connect
+
+## 用户列表
+users-heading = Online users
+users-online = { $count } online
+user-self = 👤 { $user } (me)
+
+## 消息区
+disconnect-button = 🔌 Disconnect
+auto-scroll = Auto-scroll
+jump-unread = Jump to { $count } unread
+unread-divider = New messages
+quote-missing = ┃ Original message is not in local history
+direct-offline = received while offline
+save-button = 💾 Save
+reply-button = ↩ Reply
+seen-by-count = 👁 { $count }
+seen-by = Seen by { $users }
+
+## 终端界面
+tui-messages = Messages (PgUp/PgDn to scroll)
+tui-mentions = 🔔 { $count } mentions
+tui-input-chat = Message (Enter to send, /upload PATH, /quit, Esc to exit)
+tui-input-username = Username for { $addr } (Enter to connect, Esc to exit)
+
+## 系统消息
+sys-connected = Connected to server
+sys-log-loaded = Loaded { $count } messages from the local chat log
+sys-user-joined = { $user } joined the chat
+sys-user-left = { $user } left the chat
+sys-transfer-failed = Transfer of { $name } failed: { $reason }
+sys-disconnected = Disconnected: { $reason }
+sys-file-saved = File saved to { $path }
+sys-exported = Chat exported to { $path }
+sys-user-renamed = { $old } is now known as { $new }
+sys-topic-changed = { $user } changed the topic to: { $topic }
+sys-role-changed = { $by } made { $user } { $role }
+sys-topic = Topic: { $topic }
+sys-no-topic = No topic is set
+sys-invite-created = Invite code: { $code } (valid until revoked)
+sys-invite-revoked = Invite { $code } revoked
+sys-invites = { $count } active invites: { $codes }
+sys-no-invites = No active invites
+sys-who = { $count } online: { $users }
+sys-offline-queued = { $user } is offline, the message will be delivered when they return
+sys-undecryptable = An encrypted message from { $from } to { $to } could not be decrypted
+sys-direct-failed = Could not send encrypted message to { $user }: { $reason }
+sys-key-changed = ⚠ The encryption key of { $user } has changed. Verify their fingerprint before sharing anything sensitive
+sys-key-verified = Marked the key of { $user } as verified
+sys-help = Available commands:
+
+## 连接与错误
+disconnect-normal = Disconnected normally
+connect-failed = Connection failed: { $error }
+join-failed = Join failed: { $reason }
+no-welcome = Protocol error: no Welcome received
+server-shutdown = Server shutting down: { $message }
+save-failed = Failed to save file: { $error }
+export-failed = Failed to export chat: { $error }
+username-too-long = Username cannot exceed { $max } characters
+
+## 搜索
+search-heading = Search history
+search-hint = Keywords
+search-from = From
+search-anyone = anyone
+search-since = Since
+search-until = Until
+search-button = Search
+search-more = Load more
+search-no-results = No messages found
+search-invalid-date = Dates must look like 2024-05-31
+search-jump-hover = Click to jump to the message
+search-not-loaded = This message is older than the loaded history
+
+## 导出
+menu-export = Export chat…
+menu-read-receipts = Send read receipts
+menu-read-receipts-hover = Let others see which messages you have read. When off, you still see their receipts
+export-heading = Export chat
+export-include-system = Include system messages
+export-button = Export to downloads
+export-title = Chat transcript: { $server }
+
+## 加密私聊
+dm-no-key = { $user } is offline or has no encryption key
+dm-invalid-key = The recipient's encryption key is invalid
+dm-random-failed = Random number generator failed: { $error }
+dm-too-long = Message too long, maximum is { $max } bytes
+dm-decrypt-failed = Decryption failed
+key-verified = Key verified
+key-unverified = Key not verified yet, click the name to compare fingerprints
+key-changed = The key of { $user } has changed since you last saw it
+verify-hover = Compare encryption key fingerprints
+verify-title = Verify { $user }
+verify-hint = Compare these fingerprints with { $user } over another channel (in person, by phone). If they match, nobody can read your direct messages in between.
+verify-own = Your fingerprint
+verify-theirs = Fingerprint of { $user }
+verify-button = Mark as verified
+
+## 文件传输
+transfer-cancelled = Cancelled
+transfer-unknown = Unknown transfer
+transfer-out-of-order = Chunks received out of order
+transfer-checksum = Checksum mismatch in chunk { $index }
+transfer-size-mismatch = File size does not match the offer
+transfer-incomplete = File is incomplete
+file-path-invalid = Invalid file path
+file-read-failed = Cannot read file: { $error }
+file-too-large = File too large, maximum is { $max } MB
+
+## 服务器错误码
+error-io = I/O error
+error-serialization = Malformed message
+error-version-mismatch = Protocol version mismatch
+error-frame-too-large = Message too large
+error-decompression = Decompression failed
+error-connection-timeout = Connection timed out
+error-connection-closed = Connection closed
+error-username-empty = Username cannot be empty
+error-username-too-long = Username is too long
+error-username-invalid-chars = Username may only contain letters, digits, underscores and hyphens
+error-username-mixed-script = Username mixes scripts that look alike (e.g. Latin and Cyrillic)
+error-message-empty = Message cannot be empty
+error-message-too-long = Message is too long
+error-file-name-invalid = Invalid file name
+error-file-too-large = File too large
+error-chunk-too-large = File chunk too large
+error-checksum-mismatch = File chunk checksum mismatch
+error-invalid-public-key = Invalid encryption key
+error-invalid-encrypted-message = Malformed encrypted message
+error-username-taken = Username is taken or too similar to an online user, please choose another one
+error-server-busy = Server is busy, please try again later
+error-join-timeout = Join timed out
+error-not-joined = Not joined yet
+error-already-joined = Already joined the chat
+error-rate-limited = You are sending messages too quickly
+error-banned = You are banned from this server
+error-unknown-command = Unknown command, type /help to see the available commands
+error-invalid-command = Invalid command arguments, type /help for usage
+error-user-not-found = That user is not online
+error-permission-denied = You do not have permission to do that
+error-kicked = You were kicked from the chat
+error-password-required = This room requires a password, or the password is wrong
+error-invite-required = This room is invite-only and the invite code is missing or invalid
+error-search-unavailable = This server does not keep message history, so search is unavailable
+error-too-many-transfers = Too many transfers in progress, wait for one to finish
+error-reply-not-found = The message you replied to does not exist
+error-mime-type-invalid = Invalid file type
+error-recipient-busy = The recipient is not receiving messages right now, your message was not delivered
+
+role-owner = owner
+role-operator = operator
+role-member = member
+role-guest = guest
@@ -0,0 +1,200 @@
+# 客户端界面文本（简体中文）
+
+app-title = 聊天室
+title-mentioned = 🔔 ({ $count }) 有人提到了你
+system-user = 系统
+time-just-now = 刚刚
+
+## 顶部面板
+heading = 💬 聊天室
+status-disconnected = ● 未连接
+status-connecting = 连接中...
+status-connected = ● 已连接
+toggle-users = 👥 用户列表
+toggle-search = 搜索历史消息
+mentions-hover = 有人 @ 了你，点击清除
+
+## 输入区
+transfer-cancel-hover = 取消传输
+upload-label = 文件:
+upload-hint = 输入文件路径，或直接拖放文件到窗口
+upload-button = ⬆ 上传
+upload-hover = 上传文件
+reply-preview = ↪ 回复 { $user }: { $preview }
+reply-unknown = ↪ 回复一条消息
+reply-cancel-hover = 取消回复
+input-hint = 输入消息，按 Enter 发送...
+send-button = 发送
+
+## 登录表单
+login-server = 服务器:
+login-username = 用户名:
+login-username-hint = 文字、数字、_ 或 -
+login-access-key = 密钥:
+login-access-key-hint = 密码 / 邀请码 / 角色密钥
+login-codec-hover = 序列化格式（须与服务器一致）
+login-profile = 配置:
+profile-none = （无）
+profile-name-hint = 配置名
+profile-save = 保存
+profile-save-hover = 以此名字保存服务器、用户名和序列化格式
+profile-remember-key = 记住密码
+profile-delete = 删除
+profile-auto-connect = 启动时自动连接
+tab-new-hover = 连接另一个服务器
+tab-close-hover = 关闭标签页并断开连接
+connect-button = 🔗 连接
+
+## 用户列表
+users-heading = 在线用户
+users-online = { $count } 人在线
+user-self = 👤 { $user } (我)
+
+## 消息区
+disconnect-button = 🔌 断开连接
+auto-scroll = 自动滚动
+jump-unread = 跳到 { $count } 条未读
+unread-divider = 新消息
+quote-missing = ┃ 原消息不在本地历史中
+direct-offline = 离线时收到
+save-button = 💾 保存
+reply-button = ↩ 回复
+seen-by-count = 👁 { $count }
+seen-by = 已读：{ $users }
+
+## 终端界面
+tui-messages = 消息（PgUp/PgDn 滚动）
+tui-mentions = 🔔 { $count } 条提及
+tui-input-chat = 消息（Enter 发送，/upload 路径 上传，/quit 或 Esc 退出）
+tui-input-username = 连接 { $addr } 的用户名（Enter 连接，Esc 退出）
+
+## 系统消息
+sys-connected = 已连接到服务器
+sys-log-loaded = 已从本地聊天记录载入 { $count } 条消息
+sys-user-joined = { $user } 加入了聊天室
+sys-user-left = { $user } 离开了聊天室
+sys-transfer-failed = 文件 { $name } 传输失败: { $reason }
+sys-disconnected = 已断开连接: { $reason }
+sys-file-saved = 文件已保存到 { $path }
+sys-exported = 聊天记录已导出到 { $path }
+sys-user-renamed = { $old } 改名为 { $new }
+sys-topic-changed = { $user } 将话题改为: { $topic }
+sys-role-changed = { $by } 将 { $user } 设为{ $role }
+sys-topic = 话题: { $topic }
+sys-no-topic = 尚未设置话题
+sys-invite-created = 邀请码: { $code }（撤销前有效）
+sys-invite-revoked = 邀请码 { $code } 已撤销
+sys-invites = { $count } 个有效邀请码: { $codes }
+sys-no-invites = 没有有效的邀请码
+sys-who = { $count } 人在线: { $users }
+sys-offline-queued = { $user } 不在线，消息将在对方上线时送达
+sys-undecryptable = 无法解密 { $from } 发给 { $to } 的加密消息
+sys-direct-failed = 无法向 { $user } 发送加密消息: { $reason }
+sys-key-changed = ⚠ { $user } 的加密密钥已变化，发送敏感内容前请先核对指纹
+sys-key-verified = 已将 { $user } 的密钥标记为已验证
+sys-help = 可用命令:
+
+## 连接与错误
+disconnect-normal = 正常断开
+connect-failed = 连接失败: { $error }
+join-failed = 加入失败: { $reason }
+no-welcome = 协议错误: 未收到 Welcome
+server-shutdown = 服务器关闭: { $message }
+save-failed = 保存文件失败: { $error }
+export-failed = 导出聊天记录失败: { $error }
+username-too-long = 用户名不能超过 { $max } 个字符
+
+## 搜索
+search-heading = 搜索历史
+search-hint = 关键词
+search-from = 发送者
+search-anyone = 任何人
+search-since = 开始日期
+search-until = 结束日期
+search-button = 搜索
+search-more = 加载更多
+search-no-results = 没有找到消息
+search-invalid-date = 日期格式应为 2024-05-31
+search-jump-hover = 点击跳转到该消息
+search-not-loaded = 该消息早于已加载的历史
+
+## 导出
+menu-export = 导出聊天记录…
+menu-read-receipts = 发送已读回执
+menu-read-receipts-hover = 让其他人看到你读到了哪条消息。关闭后仍能看到别人的已读状态
+export-heading = 导出聊天记录
+export-include-system = 包含系统消息
+export-button = 导出到下载目录
+export-title = 聊天记录: { $server }
+
+## 加密私聊
+dm-no-key = { $user } 不在线或没有加密密钥
+dm-invalid-key = 收件人的加密密钥无效
+dm-random-failed = 随机数生成失败: { $error }
+dm-too-long = 消息过长，最多 { $max } 字节
+dm-decrypt-failed = 解密失败
+key-verified = 密钥已验证
+key-unverified = 密钥尚未验证，点击用户名核对指纹
+key-changed = { $user } 的密钥与上次见到的不同
+verify-hover = 核对加密密钥指纹
+verify-title = 验证 { $user }
+verify-hint = 通过其他渠道（当面、电话）与 { $user } 核对以下指纹。一致时，中间人无法读取你们的私聊。
+verify-own = 你的指纹
+verify-theirs = { $user } 的指纹
+verify-button = 标记为已验证
+
+## 文件传输
+transfer-cancelled = 已取消
+transfer-unknown = 未知的传输
+transfer-out-of-order = 分块顺序错误
+transfer-checksum = 分块 { $index } 校验失败
+transfer-size-mismatch = 文件大小与声明不符
+transfer-incomplete = 文件不完整
+file-path-invalid = 无效的文件路径
+file-read-failed = 无法读取文件: { $error }
+file-too-large = 文件过大，最大 { $max } MB
+
+## 服务器错误码
+error-io = IO 错误
+error-serialization = 消息格式错误
+error-version-mismatch = 协议版本不匹配
+error-frame-too-large = 消息过大
+error-decompression = 解压失败
+error-connection-timeout = 连接超时
+error-connection-closed = 连接已关闭
+error-username-empty = 用户名不能为空
+error-username-too-long = 用户名过长
+error-username-invalid-chars = 用户名只能包含文字、数字、下划线和连字符
+error-username-mixed-script = 用户名混用了容易混淆的文字（如拉丁字母与西里尔字母）
+error-message-empty = 消息不能为空
+error-message-too-long = 消息过长
+error-file-name-invalid = 文件名无效
+error-file-too-large = 文件过大
+error-chunk-too-large = 文件分块过大
+error-checksum-mismatch = 文件分块校验失败
+error-invalid-public-key = 加密密钥无效
+error-invalid-encrypted-message = 加密消息格式错误
+error-username-taken = 用户名已被占用或与在线用户过于相似，请换一个用户名
+error-server-busy = 服务器繁忙，请稍后重试
+error-join-timeout = 加入超时
+error-not-joined = 尚未加入聊天室
+error-already-joined = 已经加入聊天室
+error-rate-limited = 发送过于频繁
+error-banned = 你已被封禁
+error-unknown-command = 未知命令，输入 /help 查看可用命令
+error-invalid-command = 命令参数错误，输入 /help 查看用法
+error-user-not-found = 该用户不在线
+error-permission-denied = 你没有执行此操作的权限
+error-kicked = 你已被踢出聊天室
+error-password-required = 此聊天室需要密码，或密码错误
+error-invite-required = 此聊天室仅限邀请，邀请码缺失或无效
+error-search-unavailable = 此服务器未保存消息历史，无法搜索
+error-too-many-transfers = 同时进行的传输过多，请等待其他传输完成
+error-reply-not-found = 回复的消息不存在
+error-mime-type-invalid = 文件类型无效
+error-recipient-busy = 对方暂时无法接收消息，消息未送达
+
+role-owner = 所有者
+role-operator = 管理员
+role-member = 成员
+role-guest = 访客
@@ -0,0 +1,479 @@
+//! 聊天室终端客户端
+//!
+//! 基于 ratatui/crossterm 的终端界面，适合在 SSH 或无图形环境中使用。
+//! 网络部分与图形客户端共用 `ChatClient`。
+//!
+//! 用法: chat-tui [addr] [--user NAME] [--key PASSWORD_OR_INVITE_OR_ROLE_KEY] [--codec bincode|postcard|msgpack|json] [--lang LOCALE]
+//!               [--log-days DAYS] [--log-encrypted] [--profile NAME]
+//!
+//! 有默认连接配置时启动即用它连接，命令行参数覆盖配置中的值。
+//! 未连接时在输入行输入用户名并回车即可连接；已连接时回车发送消息，
+//! `/quit` 退出，`/upload PATH` 上传文件，PageUp/PageDown 滚动消息。
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Result;
+use chat_client::chat_log::parse_log_days;
+use chat_client::client::{ChatClient, ChatMessage, ConnectionState, MessageKind};
+use chat_client::format::{format_size, format_timestamp, truncate_preview};
+use chat_client::profiles::Profiles;
+use chat_client::transfer::TransferDirection;
+use i18n::t;
+use protocol::Role;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+/// 每次轮询终端事件的等待时间
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// PageUp/PageDown 滚动的行数
+const PAGE_SCROLL: usize = 10;
+
+fn main() -> Result<()> {
+    let mut client = ChatClient::new();
+    let mut auto_connect = false;
+    let mut lang = None;
+
+    let profiles = Profiles::load();
+    if let Some(profile) = profiles.default_profile() {
+        client.apply_profile(profile);
+        auto_connect = true;
+    }
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--user" => {
+                client.username = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--user requires a value"))?;
+                auto_connect = true;
+            }
+            "--codec" => {
+                client.codec = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--codec requires a value"))?
+                    .parse()
+                    .map_err(|e: String| anyhow::anyhow!(e))?;
+            }
+            "--key" => {
+                client.access_key = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--key requires a value"))?;
+            }
+            "--lang" => {
+                lang = Some(args.next().ok_or_else(|| anyhow::anyhow!("--lang requires a value"))?);
+            }
+            "--log-days" => client.log_retention_days = parse_log_days(args.next())?,
+            "--log-encrypted" => client.log_encrypted = true,
+            "--profile" => {
+                let name = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--profile requires a value"))?;
+                let profile = profiles
+                    .get(&name)
+                    .ok_or_else(|| anyhow::anyhow!("no saved profile named {}", name))?;
+                client.apply_profile(profile);
+                auto_connect = true;
+            }
+            _ => client.server_addr = arg,
+        }
+    }
+    chat_client::init_locale(lang.as_deref());
+
+    // 日志会破坏终端画面，终端客户端不初始化 tracing
+    let mut app = TuiApp::new(client);
+    if auto_connect {
+        app.client.connect();
+    }
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+/// 单行编辑器，光标按字符计
+#[derive(Default)]
+struct LineEditor {
+    text: String,
+    cursor: usize,
+}
+
+impl LineEditor {
+    fn byte_index(&self) -> usize {
+        self.text
+            .char_indices()
+            .nth(self.cursor)
+            .map_or(self.text.len(), |(i, _)| i)
+    }
+
+    fn insert(&mut self, c: char) {
+        let index = self.byte_index();
+        self.text.insert(index, c);
+        self.cursor += 1;
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            let index = self.byte_index();
+            self.text.remove(index);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.text.chars().count() {
+            let index = self.byte_index();
+            self.text.remove(index);
+        }
+    }
+
+    fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    fn right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.text.chars().count());
+    }
+
+    fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    fn end(&mut self) {
+        self.cursor = self.text.chars().count();
+    }
+
+    /// 取出内容并清空
+    fn take(&mut self) -> String {
+        self.cursor = 0;
+        std::mem::take(&mut self.text)
+    }
+
+    /// 光标前文本的显示宽度
+    fn cursor_width(&self) -> usize {
+        Span::raw(&self.text[..self.byte_index()]).width()
+    }
+}
+
+/// 终端界面状态
+struct TuiApp {
+    client: ChatClient,
+    editor: LineEditor,
+    /// 距离消息底部的滚动行数，0 表示跟随最新消息
+    scroll_from_bottom: usize,
+    should_quit: bool,
+}
+
+impl TuiApp {
+    fn new(client: ChatClient) -> Self {
+        Self {
+            client,
+            editor: LineEditor::default(),
+            scroll_from_bottom: 0,
+            should_quit: false,
+        }
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        while !self.should_quit {
+            self.client.poll_events();
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if event::poll(POLL_INTERVAL)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press {
+                        self.handle_key(key);
+                    }
+                }
+            }
+        }
+        self.client.disconnect();
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') | KeyCode::Char('d') if ctrl => self.should_quit = true,
+            KeyCode::Char('u') if ctrl => {
+                self.editor.take();
+            }
+            KeyCode::Char(c) => self.editor.insert(c),
+            KeyCode::Backspace => self.editor.backspace(),
+            KeyCode::Delete => self.editor.delete(),
+            KeyCode::Left => self.editor.left(),
+            KeyCode::Right => self.editor.right(),
+            KeyCode::Home => self.editor.home(),
+            KeyCode::End => self.editor.end(),
+            KeyCode::PageUp => self.scroll_from_bottom += PAGE_SCROLL,
+            KeyCode::PageDown => {
+                self.scroll_from_bottom = self.scroll_from_bottom.saturating_sub(PAGE_SCROLL)
+            }
+            KeyCode::Esc => self.should_quit = true,
+            KeyCode::Enter => self.submit(),
+            _ => {}
+        }
+    }
+
+    /// 处理回车：未连接时以输入作为用户名连接，已连接时发送消息或执行本地命令
+    fn submit(&mut self) {
+        let line = self.editor.take();
+        let line = line.trim();
+
+        if !self.client.is_connected() {
+            if !line.is_empty() {
+                self.client.username = line.to_string();
+            }
+            if !self.client.username.is_empty()
+                && matches!(self.client.state, ConnectionState::Disconnected)
+            {
+                self.client.connect();
+            }
+            return;
+        }
+
+        if line == "/quit" {
+            self.should_quit = true;
+        } else if let Some(path) = line.strip_prefix("/upload ") {
+            self.client.upload_file(path.trim().into());
+        } else if !line.is_empty() {
+            self.client.input_text = line.to_string();
+            self.client.send_message();
+            self.scroll_from_bottom = 0;
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let transfers = self.client.transfers.len() as u16;
+        let [status_area, body_area, transfer_area, input_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(3),
+            Constraint::Length(transfers),
+            Constraint::Length(3),
+        ])
+        .areas(frame.area());
+
+        self.draw_status(frame, status_area);
+
+        if self.client.is_connected() {
+            let [messages_area, users_area] =
+                Layout::horizontal([Constraint::Min(20), Constraint::Length(24)]).areas(body_area);
+            self.draw_messages(frame, messages_area);
+            self.draw_users(frame, users_area);
+        } else {
+            self.draw_messages(frame, body_area);
+        }
+
+        self.draw_transfers(frame, transfer_area);
+        self.draw_input(frame, input_area);
+    }
+
+    fn draw_status(&self, frame: &mut Frame, area: Rect) {
+        let mut spans = vec![Span::raw(t!("app-title")).bold(), Span::raw(" │ ")];
+        match &self.client.state {
+            ConnectionState::Disconnected => {
+                spans.push(Span::raw(t!("status-disconnected")).fg(Color::DarkGray))
+            }
+            ConnectionState::Connecting => {
+                spans.push(Span::raw(t!("status-connecting")).fg(Color::Yellow))
+            }
+            ConnectionState::Connected { username, .. } => {
+                spans.push(Span::raw(t!("status-connected")).fg(Color::Green));
+                spans.push(Span::raw(format!(" │ {} @ {}", username, self.client.server_addr)));
+                if let Some(topic) = &self.client.topic {
+                    spans.push(Span::raw(format!(" │ 📌 {}", truncate_preview(topic))).fg(Color::LightYellow));
+                }
+            }
+        }
+        if self.client.unread_mentions > 0 {
+            spans.push(Span::raw(" │ "));
+            spans.push(
+                Span::raw(t!("tui-mentions", count = self.client.unread_mentions))
+                    .fg(Color::Black)
+                    .bg(Color::Yellow),
+            );
+        }
+        if let Some(err) = &self.client.error_message {
+            spans.push(Span::raw(" │ "));
+            spans.push(Span::raw(format!("⚠ {}", err)).fg(Color::Red));
+        }
+        frame.render_widget(Line::from(spans), area);
+    }
+
+    fn draw_messages(&mut self, frame: &mut Frame, area: Rect) {
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(t!("tui-messages"));
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let by_id = self.client.messages_by_id();
+        let lines: Vec<Line> = self
+            .client
+            .messages
+            .iter()
+            .flat_map(|msg| message_lines(&by_id, msg))
+            .collect();
+        let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false });
+
+        // 从底部计算滚动位置，滚动超出顶部时夹紧
+        let total = paragraph.line_count(inner.width);
+        let max_scroll = total.saturating_sub(inner.height as usize);
+        self.scroll_from_bottom = self.scroll_from_bottom.min(max_scroll);
+        let offset = (max_scroll - self.scroll_from_bottom).min(u16::MAX as usize) as u16;
+        frame.render_widget(paragraph.scroll((offset, 0)), inner);
+
+        // 此时已经处理过所有新消息
+        if self.scroll_from_bottom == 0 {
+            self.client.clear_mentions();
+        }
+    }
+
+    fn draw_users(&self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .client
+            .online_users
+            .iter()
+            .map(|user| {
+                let name = format!("{}{}", role_prefix(user.role), user.username);
+                if user.username == self.client.username {
+                    ListItem::new(t!("user-self", user = name)).fg(Color::Cyan)
+                } else {
+                    ListItem::new(format!("👤 {}", name))
+                }
+            })
+            .collect();
+        let title = t!("users-online", count = self.client.online_users.len());
+        frame.render_widget(
+            List::new(items).block(Block::default().borders(Borders::ALL).title(title)),
+            area,
+        );
+    }
+
+    fn draw_transfers(&self, frame: &mut Frame, area: Rect) {
+        let lines: Vec<Line> = self
+            .client
+            .transfers
+            .iter()
+            .map(|transfer| {
+                let icon = match transfer.direction {
+                    TransferDirection::Upload => "⬆",
+                    TransferDirection::Download => "⬇",
+                };
+                Line::from(format!(
+                    "{} {} {:>3.0}% ({} / {})",
+                    icon,
+                    transfer.name,
+                    transfer.progress() * 100.0,
+                    format_size(transfer.transferred),
+                    format_size(transfer.total)
+                ))
+                .fg(Color::Blue)
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(lines), area);
+    }
+
+    fn draw_input(&self, frame: &mut Frame, area: Rect) {
+        let title = if self.client.is_connected() {
+            t!("tui-input-chat")
+        } else {
+            t!("tui-input-username", addr = self.client.server_addr.as_str())
+        };
+        let block = Block::default().borders(Borders::ALL).title(title);
+        let inner = block.inner(area);
+
+        // 光标超出宽度时水平滚动
+        let cursor = self.editor.cursor_width() as u16;
+        let scroll = cursor.saturating_sub(inner.width.saturating_sub(1));
+        frame.render_widget(
+            Paragraph::new(self.editor.text.as_str()).scroll((0, scroll)).block(block),
+            area,
+        );
+        frame.set_cursor_position((inner.x + cursor - scroll, inner.y));
+    }
+}
+
+/// 单条消息的显示行（引用预览 + 正文），`by_id` 用于查找被回复的消息
+fn message_lines<'a>(by_id: &HashMap<u64, &ChatMessage>, msg: &'a ChatMessage) -> Vec<Line<'a>> {
+    let time = Span::raw(format!("[{}] ", format_timestamp(msg.timestamp))).fg(Color::DarkGray);
+
+    if msg.is_system {
+        return vec![Line::from(vec![
+            time,
+            Span::styled(
+                msg.content.as_str(),
+                Style::default().fg(Color::Gray).add_modifier(Modifier::ITALIC),
+            ),
+        ])];
+    }
+
+    let mut lines = Vec::new();
+    if let Some(parent_id) = msg.reply_to {
+        let quote = match by_id.get(&parent_id) {
+            Some(parent) => format!("  ┃ {}: {}", parent.username, truncate_preview(&parent.content)),
+            None => format!("  {}", t!("quote-missing")),
+        };
+        lines.push(Line::from(quote).fg(Color::DarkGray));
+    }
+
+    let (name, content) = match &msg.kind {
+        MessageKind::Chat => (format!("{}: ", msg.username), Span::raw(msg.content.as_str())),
+        MessageKind::Action => (format!("* {} ", msg.username), Span::raw(msg.content.as_str()).italic()),
+        MessageKind::Direct { to, encrypted, offline } => (
+            format!(
+                "{}{} → {}{}: ",
+                if *encrypted { "🔒 " } else { "" },
+                msg.username,
+                to,
+                if *offline { format!(" ({})", t!("direct-offline")) } else { String::new() },
+            ),
+            Span::raw(msg.content.as_str()).fg(Color::LightMagenta),
+        ),
+    };
+    let mut spans = vec![
+        time,
+        Span::raw(name).bold().fg(username_color(&msg.username)),
+        content,
+    ];
+    if let Some(attachment) = &msg.attachment {
+        spans.push(Span::raw(format!(" ({})", format_size(attachment.size))).fg(Color::DarkGray));
+    }
+    let line = Line::from(spans);
+    lines.push(if msg.mentions_me {
+        line.bg(Color::Rgb(70, 55, 25))
+    } else {
+        line
+    });
+    lines
+}
+
+/// 根据用户名选择终端颜色
+fn username_color(username: &str) -> Color {
+    const COLORS: [Color; 6] = [
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+    ];
+    let hash: u32 = username.bytes().fold(0u32, |acc, b| acc.wrapping_add(b as u32));
+    COLORS[hash as usize % COLORS.len()]
+}
+
+/// IRC 风格的角色前缀
+fn role_prefix(role: Role) -> &'static str {
+    match role {
+        Role::Owner => "~",
+        Role::Operator => "@",
+        Role::Member | Role::Guest => "",
+    }
+}
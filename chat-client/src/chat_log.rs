@@ -0,0 +1,329 @@
+//! 本地聊天记录
+//!
+//! 收到的消息按服务器追加到数据目录下 `chat-client/logs/<服务器>-<地址摘要>.jsonl`，每行一条 JSON，
+//! 连接服务器时读取最近的记录，因此即使服务器不保存历史，客户端也能看到以前的消息。
+//! 超过保留天数的记录在打开时删除。记录文件只有当前用户可读；端到端加密的私聊默认不写入，
+//! 以免明文落盘。
+//!
+//! 每个服务器的已读位置保存在 `chat-client/read_positions` 中，下次连接时据此标出未读消息。
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::client::{ChatMessage, MessageKind};
+use crate::keys::{data_dir, private_options, write_private};
+
+/// 默认保留天数
+pub const DEFAULT_RETENTION_DAYS: u32 = 30;
+
+/// 已读位置文件名
+const READ_POSITIONS_FILE: &str = "read_positions";
+
+/// 记录文件名中服务器地址摘要的字节数
+const ADDRESS_HASH_BYTES: usize = 4;
+
+/// 最后一条已读消息的位置
+///
+/// 先比较时间戳再比较消息 ID：服务器不保存历史时重启后消息 ID 从头编号，只比较 ID 会把新消息误判为已读。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ReadPosition {
+    pub timestamp: u64,
+    pub message_id: u64,
+}
+
+impl ReadPosition {
+    /// 消息的位置，系统消息没有位置
+    pub fn of(msg: &ChatMessage) -> Option<Self> {
+        Some(Self {
+            timestamp: msg.timestamp,
+            message_id: msg.id?,
+        })
+    }
+}
+
+/// 一个服务器的聊天记录文件
+pub struct ChatLog {
+    server: String,
+    path: PathBuf,
+    file: File,
+}
+
+impl ChatLog {
+    /// 打开（或创建）服务器的聊天记录，删除 `retention_days` 天以前的记录，返回记录和保留的消息
+    pub fn open(server: &str, retention_days: u32) -> std::io::Result<(Self, Vec<ChatMessage>)> {
+        Self::open_at(server, log_path(server)?, retention_days)
+    }
+
+    /// 打开指定路径的记录文件
+    fn open_at(server: &str, path: PathBuf, retention_days: u32) -> std::io::Result<(Self, Vec<ChatMessage>)> {
+        fs::create_dir_all(path.parent().expect("log path has a parent"))?;
+        restrict_permissions(&path)?;
+
+        let cutoff = unix_timestamp().saturating_sub(u64::from(retention_days) * 24 * 60 * 60);
+        let (mut messages, mut expired) = match File::open(&path) {
+            Ok(file) => read_lines(file)?,
+            Err(_) => (Vec::new(), false),
+        };
+        let before = messages.len();
+        messages.retain(|msg| msg.timestamp >= cutoff);
+        expired |= messages.len() != before;
+        if expired {
+            let mut content = String::new();
+            for msg in &messages {
+                content.push_str(&serde_json::to_string(msg)?);
+                content.push('\n');
+            }
+            write_private(&path, content.as_bytes())?;
+        }
+
+        let file = private_options().append(true).open(&path)?;
+        let log = Self {
+            server: server.to_string(),
+            path,
+            file,
+        };
+        Ok((log, messages))
+    }
+
+    /// 记录对应的服务器地址
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    /// 追加一条消息，失败时返回带文件路径的错误说明
+    pub fn append(&mut self, msg: &ChatMessage) -> Result<(), String> {
+        let mut line = serde_json::to_string(msg).map_err(|e| e.to_string())?;
+        line.push('\n');
+        self.file
+            .write_all(line.as_bytes())
+            .map_err(|e| format!("{}: {}", self.path.display(), e))
+    }
+}
+
+/// 消息是否写入本地记录：系统消息不写入，加密私聊只在 `log_encrypted` 时写入
+pub(crate) fn should_log(msg: &ChatMessage, log_encrypted: bool) -> bool {
+    let encrypted = matches!(msg.kind, MessageKind::Direct { encrypted: true, .. });
+    !msg.is_system && (!encrypted || log_encrypted)
+}
+
+/// 读取服务器的全部聊天记录（不删除过期记录），没有记录时返回空列表
+pub fn read(server: &str) -> std::io::Result<Vec<ChatMessage>> {
+    read_path(&log_path(server)?)
+}
+
+fn read_path(path: &Path) -> std::io::Result<Vec<ChatMessage>> {
+    match File::open(path) {
+        Ok(file) => Ok(read_lines(file)?.0),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// 逐行解析记录，返回解析出的消息和是否有被丢弃的行
+///
+/// 写入中断留下的残行、损坏的字节等无法按 UTF-8 或 JSON 解析的行直接丢弃，不影响其余记录。
+fn read_lines(file: impl Read) -> std::io::Result<(Vec<ChatMessage>, bool)> {
+    let mut messages = Vec::new();
+    let mut skipped = false;
+    for line in BufReader::new(file).split(b'\n') {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        match std::str::from_utf8(&line).ok().and_then(|line| serde_json::from_str(line).ok()) {
+            Some(msg) => messages.push(msg),
+            None => skipped = true,
+        }
+    }
+    Ok((messages, skipped))
+}
+
+/// 记录文件路径
+///
+/// `file_name` 会把不同的地址（如 `a:b` 和 `a_b`）变成同一个名字，因此再加上原始地址的摘要。
+/// 旧版本只按 `file_name` 命名的记录在这里改名沿用。
+fn log_path(server: &str) -> std::io::Result<PathBuf> {
+    let dir = data_dir()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no data directory"))?
+        .join("logs");
+    let digest = Sha256::digest(server.as_bytes());
+    let hash: String = digest[..ADDRESS_HASH_BYTES].iter().map(|b| format!("{:02x}", b)).collect();
+    let path = dir.join(format!("{}-{}.jsonl", file_name(server), hash));
+    let legacy = dir.join(format!("{}.jsonl", file_name(server)));
+    if !path.exists() && legacy.exists() {
+        fs::rename(&legacy, &path)?;
+    }
+    Ok(path)
+}
+
+/// 把旧版本以默认权限创建的记录文件改为只有当前用户可读
+fn restrict_permissions(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    if path.exists() {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// 读取服务器的已读位置
+pub fn load_read_position(server: &str) -> Option<ReadPosition> {
+    read_positions().into_iter().find(|(s, _)| s == server).map(|(_, pos)| pos)
+}
+
+/// 保存服务器的已读位置，失败时只记录日志
+pub fn save_read_position(server: &str, position: ReadPosition) {
+    let Some(path) = data_dir().map(|dir| dir.join(READ_POSITIONS_FILE)) else {
+        return;
+    };
+    // 每次重新读取文件，其他标签页保存的位置不会被覆盖
+    let mut positions = read_positions();
+    positions.retain(|(s, _)| s != server);
+    positions.push((server.to_string(), position));
+    let content: String = positions
+        .iter()
+        .map(|(s, pos)| format!("{}\t{}\t{}\n", s, pos.timestamp, pos.message_id))
+        .collect();
+    if let Err(e) = write_private(&path, content.as_bytes()) {
+        warn!("Failed to save read position to {}: {}", path.display(), e);
+    }
+}
+
+/// 文件每行一条记录：`服务器\t时间戳\t消息 ID`，损坏的行被忽略
+fn read_positions() -> Vec<(String, ReadPosition)> {
+    let Some(content) = data_dir().and_then(|dir| fs::read_to_string(dir.join(READ_POSITIONS_FILE)).ok()) else {
+        return Vec::new();
+    };
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let server = fields.next()?;
+            let timestamp = fields.next()?.parse().ok()?;
+            let message_id = fields.next()?.parse().ok()?;
+            Some((server.to_string(), ReadPosition { timestamp, message_id }))
+        })
+        .collect()
+}
+
+/// 解析 `--log-days` 参数
+pub fn parse_log_days(value: Option<String>) -> anyhow::Result<u32> {
+    value
+        .ok_or_else(|| anyhow::anyhow!("--log-days requires a value"))?
+        .parse()
+        .map_err(|_| anyhow::anyhow!("--log-days expects a number of days"))
+}
+
+/// 将服务器地址转换为可用作文件名的字符串（如 `127.0.0.1_8080`）
+pub(crate) fn file_name(server: &str) -> String {
+    server
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect()
+}
+
+fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: u64, timestamp: u64, kind: MessageKind) -> ChatMessage {
+        ChatMessage {
+            id: Some(id),
+            username: "alice".to_string(),
+            content: format!("message {}", id),
+            timestamp,
+            is_system: false,
+            reply_to: None,
+            mentions_me: false,
+            attachment: None,
+            kind,
+        }
+    }
+
+    fn temp_log(name: &str) -> PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("chat-log-test-{}", std::process::id()))
+            .join(format!("{}.jsonl", name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn ids(messages: &[ChatMessage]) -> Vec<Option<u64>> {
+        messages.iter().map(|m| m.id).collect()
+    }
+
+    #[test]
+    fn test_retention_and_bad_lines() {
+        let path = temp_log("retention");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let now = unix_timestamp();
+        let old = message(1, now - 31 * 24 * 60 * 60, MessageKind::Chat);
+        let recent = message(2, now - 60, MessageKind::Action);
+        let mut content = serde_json::to_vec(&old).unwrap();
+        content.extend_from_slice(b"\n\xff\xfe not utf-8\n{\"truncated\":\n");
+        content.extend_from_slice(&serde_json::to_vec(&recent).unwrap());
+        content.push(b'\n');
+        fs::write(&path, content).unwrap();
+
+        let (mut log, messages) = ChatLog::open_at("test", path.clone(), 30).unwrap();
+        assert_eq!(ids(&messages), vec![Some(2)]);
+        assert_eq!(messages[0].kind, MessageKind::Action);
+
+        // 过期和损坏的行被删除，新消息追加在后面
+        log.append(&message(3, now, MessageKind::Chat)).unwrap();
+        assert_eq!(ids(&read_path(&path).unwrap()), vec![Some(2), Some(3)]);
+        let (_, messages) = ChatLog::open_at("test", path.clone(), 30).unwrap();
+        assert_eq!(ids(&messages), vec![Some(2), Some(3)]);
+
+        assert!(read_path(&path.with_extension("missing")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_encrypted_direct_messages_not_logged() {
+        let direct = |encrypted| MessageKind::Direct {
+            to: "bob".to_string(),
+            encrypted,
+            offline: false,
+        };
+        let encrypted = message(1, 0, direct(true));
+        assert!(!should_log(&encrypted, false));
+        assert!(should_log(&encrypted, true));
+        assert!(should_log(&message(2, 0, direct(false)), false));
+        assert!(should_log(&message(3, 0, MessageKind::Chat), false));
+
+        let system = ChatMessage {
+            id: None,
+            is_system: true,
+            ..message(4, 0, MessageKind::Chat)
+        };
+        assert!(!should_log(&system, true));
+    }
+
+    #[test]
+    fn test_reads_direct_messages_without_offline_flag() {
+        let line = r#"{"id":1,"username":"alice","content":"hi","timestamp":0,"is_system":false,"reply_to":null,"mentions_me":true,"attachment":null,"kind":{"Direct":{"to":"bob","encrypted":false}}}"#;
+        let (messages, skipped) = read_lines(line.as_bytes()).unwrap();
+        assert!(!skipped);
+        assert_eq!(
+            messages[0].kind,
+            MessageKind::Direct {
+                to: "bob".to_string(),
+                encrypted: false,
+                offline: false,
+            }
+        );
+    }
+}
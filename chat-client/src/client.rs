@@ -1,28 +1,72 @@
 //! 聊天客户端核心实现
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
 use std::sync::mpsc as std_mpsc;
+use std::sync::Arc;
 use std::thread;
 
+use chat_client_core::{
+    e2e, ChatSession, DisconnectReason, E2eError, Identity, SearchRequest, SessionConfig, SessionError, SessionEvent,
+    TransferError,
+};
+use i18n::t;
 use protocol::{
-    ClientMessage, Connection, ProtocolError, ServerMessage, TcpTransport, Transport,
-    TransportConfig, CONNECT_TIMEOUT, HEARTBEAT_INTERVAL, MAX_USERNAME_LEN,
+    is_image_mime, normalize_username, username_key, CodecKind, CommandReply, ErrorCode, OnlineUser, ProtocolError,
+    ReadReceipt, Role, SearchHit,
 };
+use serde::{Deserialize, Serialize};
 use tokio::runtime::Runtime;
 use tokio::sync::mpsc;
-use tokio::time::interval;
-use tracing::{debug, info, warn};
+use tracing::warn;
+
+use crate::chat_log::{self, ChatLog, ReadPosition, DEFAULT_RETENTION_DAYS};
+use crate::export::{self, ExportOptions};
+use crate::format::parse_date;
+use crate::keys::{self, KeyStatus, KnownKeys};
+use crate::profiles::Profile;
+use crate::transfer::Transfer;
 
 /// 消息历史上限
 const MAX_MESSAGES: usize = 1000;
 
+/// 自动下载用于预览的图片大小上限
+const AUTO_PREVIEW_MAX_SIZE: u64 = 2 * 1024 * 1024;
+
 /// UI 发送给网络线程的命令
 #[derive(Debug)]
 pub enum UiCommand {
     /// 连接服务器
-    Connect { addr: String, username: String },
+    Connect {
+        addr: String,
+        username: String,
+        codec: CodecKind,
+        access_key: Option<String>,
+        identity: Option<Identity>,
+    },
     /// 发送聊天消息
-    SendChat { content: String },
+    SendChat {
+        content: String,
+        reply_to: Option<u64>,
+    },
+    /// 发送加密私聊
+    SendDirect { to: String, content: String },
+    /// 上传文件
+    UploadFile { path: PathBuf },
+    /// 下载文件
+    DownloadFile {
+        file_id: u64,
+        name: String,
+        size: u64,
+    },
+    /// 取消传输
+    CancelTransfer { transfer_id: u32 },
+    /// 搜索历史消息
+    Search(SearchRequest),
+    /// 告诉服务器已读到哪条消息
+    MarkRead { up_to: u64 },
+    /// 让服务器清除已读位置
+    ClearRead,
     /// 断开连接
     Disconnect,
 }
@@ -33,33 +77,94 @@ pub enum NetworkEvent {
     /// 连接成功
     Connected {
         user_id: u32,
-        online_users: Vec<String>,
+        online_users: Vec<OnlineUser>,
+        topic: Option<String>,
     },
     /// 连接失败
     ConnectFailed { reason: String },
-    /// 收到聊天消息
-    ChatMessage {
-        username: String,
-        content: String,
-        timestamp: u64,
-    },
-    /// 用户加入
-    UserJoined { username: String },
-    /// 用户离开
-    UserLeft { username: String },
-    /// 错误消息
+    /// 命令执行失败（如无法读取要上传的文件）
     Error { message: String },
-    /// 连接断开
-    Disconnected { reason: String },
+    /// 会话事件
+    Session(SessionEvent),
 }
 
 /// 聊天消息记录
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
+    /// 服务端分配的消息 ID（系统消息为 None）
+    pub id: Option<u64>,
     pub username: String,
     pub content: String,
     pub timestamp: u64,
     pub is_system: bool,
+    /// 回复的消息 ID
+    pub reply_to: Option<u64>,
+    /// 是否 @提及 了本地用户（私聊给本地用户的消息也算）
+    pub mentions_me: bool,
+    /// 附带的文件
+    pub attachment: Option<Attachment>,
+    /// 消息种类
+    pub kind: MessageKind,
+}
+
+/// 消息种类
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MessageKind {
+    /// 普通聊天消息
+    Chat,
+    /// `/me` 动作消息
+    Action,
+    /// 私聊消息，`to` 为收件人，`encrypted` 表示是否端到端加密，
+    /// `offline` 表示是否是离线期间收到、加入后才投递的私聊
+    Direct {
+        to: String,
+        encrypted: bool,
+        #[serde(default)]
+        offline: bool,
+    },
+}
+
+/// 消息附带的文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub file_id: u64,
+    pub name: String,
+    pub size: u64,
+    pub mime: String,
+}
+
+/// 历史消息搜索
+#[derive(Debug, Default)]
+pub struct Search {
+    /// 关键词输入框内容
+    pub query: String,
+    /// 发送者输入框内容（为空表示不限）
+    pub from_user: String,
+    /// 起止日期输入框内容（`YYYY-MM-DD`，为空表示不限）
+    pub since: String,
+    pub until: String,
+    /// 已加载的结果，从新到旧排列
+    pub hits: Vec<SearchHit>,
+    /// 是否正在等待结果
+    pub pending: bool,
+    /// 输入错误或服务器返回的错误
+    pub error: Option<String>,
+    /// 最近一次搜索的条件，用于加载下一页
+    request: Option<SearchRequest>,
+    /// 下一页的起始位置
+    next_before: Option<u64>,
+}
+
+impl Search {
+    /// 是否还有更多结果
+    pub fn has_more(&self) -> bool {
+        self.next_before.is_some()
+    }
+
+    /// 是否已经发出过搜索请求
+    pub fn request_sent(&self) -> bool {
+        self.request.is_some()
+    }
 }
 
 /// 客户端状态
@@ -77,19 +182,63 @@ pub struct ChatClient {
     /// 聊天消息历史（使用 VecDeque 提高删除效率）
     pub messages: VecDeque<ChatMessage>,
     /// 在线用户列表
-    pub online_users: Vec<String>,
+    pub online_users: Vec<OnlineUser>,
     /// 发送命令到网络线程（使用 std::sync::mpsc，因为 UI 线程是同步的）
     cmd_tx: std_mpsc::Sender<UiCommand>,
     /// 接收网络事件（使用 std::sync::mpsc，因为 UI 线程是同步的）
     event_rx: std_mpsc::Receiver<NetworkEvent>,
     /// 输入框内容
     pub input_text: String,
+    /// 正在回复的消息 ID
+    pub reply_to: Option<u64>,
+    /// 未查看的 @提及 数量
+    pub unread_mentions: usize,
+    /// 最后一条已读消息的位置（没有已读消息时为 None）
+    last_read: Option<ReadPosition>,
+    /// `last_read` 所属的服务器
+    read_server: String,
+    /// 是否把已读位置告诉服务器（其他用户会看到）
+    send_read_receipts: bool,
+    /// 本次连接最后发送的已读消息 ID，0 表示已请求服务器清除已读位置
+    receipt_sent: Option<u64>,
+    /// 其他用户的已读位置，按用户名比较键索引
+    read_receipts: HashMap<String, ReadReceipt>,
+    /// 尚未收全的 `/who` 回复
+    who_pending: Vec<String>,
+    /// 进行中的文件传输
+    pub transfers: Vec<Transfer>,
+    /// 已下载的文件内容: file_id -> 数据
+    pub downloaded: HashMap<u64, Arc<[u8]>>,
+    /// 下载完成后需要保存到磁盘的文件
+    pending_saves: HashSet<u64>,
+    /// 正在下载的文件
+    downloading: HashSet<u64>,
+    /// 待上传文件路径输入框内容
+    pub upload_path: String,
     /// 服务器地址
     pub server_addr: String,
     /// 用户名
     pub username: String,
+    /// 序列化格式（须与服务器一致）
+    pub codec: CodecKind,
+    /// 聊天室密码或邀请码（为空表示不需要）
+    pub access_key: String,
     /// 错误消息
     pub error_message: Option<String>,
+    /// 聊天室话题
+    pub topic: Option<String>,
+    /// 历史消息搜索
+    pub search: Search,
+    /// 本地聊天记录保留天数，0 表示不保存
+    pub log_retention_days: u32,
+    /// 是否把解密后的端到端加密私聊写入本地聊天记录
+    pub log_encrypted: bool,
+    /// 当前服务器的本地聊天记录
+    log: Option<ChatLog>,
+    /// 加密私聊使用的密钥对（无法生成时为 None，由会话临时生成）
+    identity: Option<Identity>,
+    /// 见过的其他用户公钥
+    known_keys: KnownKeys,
 }
 
 impl ChatClient {
@@ -142,9 +291,31 @@ impl ChatClient {
             cmd_tx,
             event_rx,
             input_text: String::new(),
+            reply_to: None,
+            unread_mentions: 0,
+            last_read: None,
+            read_server: String::new(),
+            send_read_receipts: true,
+            receipt_sent: None,
+            read_receipts: HashMap::new(),
+            who_pending: Vec::new(),
+            transfers: Vec::new(),
+            downloaded: HashMap::new(),
+            pending_saves: HashSet::new(),
+            downloading: HashSet::new(),
+            upload_path: String::new(),
             server_addr: "127.0.0.1:8080".to_string(),
             username: String::new(),
+            codec: CodecKind::default(),
+            access_key: String::new(),
             error_message: None,
+            topic: None,
+            search: Search::default(),
+            log_retention_days: DEFAULT_RETENTION_DAYS,
+            log_encrypted: false,
+            log: None,
+            identity: keys::load_identity(),
+            known_keys: KnownKeys::load(),
         }
     }
 
@@ -160,54 +331,413 @@ impl ChatClient {
 
     fn handle_event(&mut self, event: NetworkEvent) {
         match event {
-            NetworkEvent::Connected { user_id, online_users } => {
+            NetworkEvent::Connected { user_id, online_users, topic } => {
                 if let ConnectionState::Connecting = &self.state {
                     let username = self.username.clone();
                     self.state = ConnectionState::Connected { user_id, username };
                     self.error_message = None;
                     // 使用服务端返回的在线用户列表
                     self.online_users = online_users;
-                    self.add_system_message("已连接到服务器".to_string());
+                    self.read_receipts.clear();
+                    self.who_pending.clear();
+                    self.receipt_sent = None;
+                    self.open_log();
+                    self.load_read_position();
+                    self.add_system_message(t!("sys-connected"));
+                    let users: Vec<_> = self
+                        .online_users
+                        .iter()
+                        .filter_map(|u| Some((u.username.clone(), u.public_key.clone()?)))
+                        .collect();
+                    for (username, public_key) in users {
+                        self.observe_key(&username, &public_key);
+                    }
+                    if let Some(topic) = &topic {
+                        self.add_system_message(t!("sys-topic", topic = topic.as_str()));
+                    }
+                    self.topic = topic;
                 }
             }
             NetworkEvent::ConnectFailed { reason } => {
                 self.state = ConnectionState::Disconnected;
                 self.error_message = Some(reason);
             }
-            NetworkEvent::ChatMessage {
+            NetworkEvent::Error { message } => {
+                self.error_message = Some(message);
+            }
+            NetworkEvent::Session(event) => self.handle_session_event(event),
+        }
+    }
+
+    fn handle_session_event(&mut self, event: SessionEvent) {
+        match event {
+            SessionEvent::ChatMessage {
+                message_id,
+                username,
+                content,
+                timestamp,
+                reply_to,
+                mentions,
+            } => {
+                let mentions_me = username != self.username && mentions.contains(&self.username);
+                if mentions_me {
+                    self.unread_mentions += 1;
+                }
+                self.add_message(ChatMessage {
+                    id: Some(message_id),
+                    username,
+                    content,
+                    timestamp,
+                    is_system: false,
+                    reply_to,
+                    mentions_me,
+                    attachment: None,
+                    kind: MessageKind::Chat,
+                });
+            }
+            SessionEvent::Motd { message } => {
+                for line in message.lines() {
+                    self.add_system_message(format!("📢 {}", line));
+                }
+            }
+            SessionEvent::ActionMessage {
+                message_id,
                 username,
                 content,
                 timestamp,
             } => {
                 self.add_message(ChatMessage {
+                    id: Some(message_id),
                     username,
                     content,
                     timestamp,
                     is_system: false,
+                    reply_to: None,
+                    mentions_me: false,
+                    attachment: None,
+                    kind: MessageKind::Action,
                 });
             }
-            NetworkEvent::UserJoined { username } => {
-                if !self.online_users.contains(&username) {
-                    self.online_users.push(username.clone());
+            SessionEvent::DirectMessage {
+                message_id,
+                from,
+                to,
+                content,
+                timestamp,
+                encrypted,
+                offline,
+            } => {
+                let mentions_me = from != self.username;
+                if mentions_me {
+                    self.unread_mentions += 1;
                 }
-                self.add_system_message(format!("{} 加入了聊天室", username));
+                self.add_message(ChatMessage {
+                    id: Some(message_id),
+                    username: from,
+                    content,
+                    timestamp,
+                    is_system: false,
+                    reply_to: None,
+                    mentions_me,
+                    attachment: None,
+                    kind: MessageKind::Direct { to, encrypted, offline },
+                });
             }
-            NetworkEvent::UserLeft { username } => {
-                self.online_users.retain(|u| u != &username);
-                self.add_system_message(format!("{} 离开了聊天室", username));
+            SessionEvent::UndecryptableMessage { from, to, .. } => {
+                self.add_system_message(t!("sys-undecryptable", from = from, to = to));
             }
-            NetworkEvent::Error { message } => {
-                self.error_message = Some(message);
+            SessionEvent::DirectFailed { to, error } => {
+                self.add_system_message(t!("sys-direct-failed", user = to, reason = e2e_error_text(&error)));
+            }
+            SessionEvent::UserJoined {
+                username,
+                role,
+                public_key,
+            } => {
+                self.add_system_message(t!("sys-user-joined", user = username.as_str()));
+                if let Some(key) = &public_key {
+                    self.observe_key(&username, key);
+                }
+                if !self.online_users.iter().any(|u| u.username == username) {
+                    self.online_users.push(OnlineUser {
+                        username,
+                        role,
+                        public_key,
+                    });
+                }
+            }
+            SessionEvent::UserLeft { username } => {
+                self.online_users.retain(|u| u.username != username);
+                self.add_system_message(t!("sys-user-left", user = username));
+            }
+            SessionEvent::FileShared {
+                file_id,
+                username,
+                name,
+                size,
+                mime,
+                timestamp,
+            } => {
+                // 小图片自动下载以显示预览
+                if is_image_mime(&mime) && size <= AUTO_PREVIEW_MAX_SIZE {
+                    self.download_file(file_id, &name, size);
+                }
+                self.add_message(ChatMessage {
+                    id: None,
+                    username,
+                    content: format!("📎 {}", name),
+                    timestamp,
+                    is_system: false,
+                    reply_to: None,
+                    mentions_me: false,
+                    attachment: Some(Attachment {
+                        file_id,
+                        name,
+                        size,
+                        mime,
+                    }),
+                    kind: MessageKind::Chat,
+                });
+            }
+            SessionEvent::TransferStarted {
+                transfer_id,
+                name,
+                direction,
+                total,
+            } => {
+                self.transfers.push(Transfer {
+                    transfer_id,
+                    name,
+                    direction,
+                    transferred: 0,
+                    total,
+                });
+            }
+            SessionEvent::TransferProgress {
+                transfer_id,
+                transferred,
+            } => {
+                if let Some(t) = self.transfers.iter_mut().find(|t| t.transfer_id == transfer_id) {
+                    t.transferred = transferred;
+                }
+            }
+            SessionEvent::UploadComplete { transfer_id } => {
+                self.transfers.retain(|t| t.transfer_id != transfer_id);
+            }
+            SessionEvent::DownloadComplete {
+                transfer_id,
+                file_id,
+                data,
+            } => {
+                self.transfers.retain(|t| t.transfer_id != transfer_id);
+                self.downloading.remove(&file_id);
+                self.downloaded.insert(file_id, data.into());
+                if self.pending_saves.remove(&file_id) {
+                    self.save_file(file_id);
+                }
+            }
+            SessionEvent::TransferFailed { transfer_id, error } => {
+                if let Some(pos) = self.transfers.iter().position(|t| t.transfer_id == transfer_id) {
+                    let transfer = self.transfers.remove(pos);
+                    let reason = transfer_error_text(&error);
+                    self.add_system_message(t!("sys-transfer-failed", name = transfer.name, reason = reason));
+                }
+            }
+            SessionEvent::UserRenamed {
+                old_username,
+                new_username,
+            } => {
+                for user in self.online_users.iter_mut().filter(|u| u.username == old_username) {
+                    user.username = new_username.clone();
+                }
+                if let Some(mut receipt) = self.read_receipts.remove(&username_key(&old_username)) {
+                    receipt.username = new_username.clone();
+                    self.read_receipts.insert(username_key(&new_username), receipt);
+                }
+                if old_username == self.username {
+                    self.username = new_username.clone();
+                    if let ConnectionState::Connected { username, .. } = &mut self.state {
+                        *username = new_username.clone();
+                    }
+                }
+                self.add_system_message(t!("sys-user-renamed", old = old_username, new = new_username));
+            }
+            SessionEvent::RoleChanged { username, role, by } => {
+                for user in self.online_users.iter_mut().filter(|u| u.username == username) {
+                    user.role = role;
+                }
+                self.add_system_message(t!("sys-role-changed", user = username, role = role_text(role), by = by));
+            }
+            SessionEvent::TopicChanged { topic, username } => {
+                self.add_system_message(t!("sys-topic-changed", user = username, topic = topic.as_str()));
+                self.topic = Some(topic);
+            }
+            SessionEvent::CommandReply { reply } => self.handle_command_reply(reply),
+            SessionEvent::ReadReceipts { receipts } => {
+                for receipt in receipts {
+                    let key = username_key(&receipt.username);
+                    if receipt.up_to == 0 {
+                        self.read_receipts.remove(&key);
+                    } else {
+                        self.read_receipts.insert(key, receipt);
+                    }
+                }
+            }
+            SessionEvent::SearchResults { before, hits, next_before } => {
+                // 忽略过期请求的结果
+                let current = self.search.request.as_ref().map(|r| r.before);
+                if current == Some(before) {
+                    if before.is_none() {
+                        self.search.hits.clear();
+                    }
+                    self.search.hits.extend(hits);
+                    self.search.next_before = next_before;
+                    self.search.pending = false;
+                }
+            }
+            SessionEvent::ServerError { code: code @ ErrorCode::SearchUnavailable, message } => {
+                self.search.error = Some(server_error_text(code, message));
+                self.search.pending = false;
+            }
+            // 命令错误显示在消息列表中，其他错误显示在状态区域
+            SessionEvent::ServerError {
+                code:
+                    code @ (ErrorCode::UnknownCommand
+                    | ErrorCode::InvalidCommand
+                    | ErrorCode::UserNotFound
+                    | ErrorCode::PermissionDenied),
+                message,
+            } => {
+                self.add_system_message(server_error_text(code, message));
             }
-            NetworkEvent::Disconnected { reason } => {
+            SessionEvent::ServerError { code, message } => {
+                self.error_message = Some(server_error_text(code, message));
+            }
+            SessionEvent::Disconnected { reason } => {
                 self.state = ConnectionState::Disconnected;
                 self.online_users.clear();
-                self.add_system_message(format!("已断开连接: {}", reason));
+                self.topic = None;
+                self.reply_to = None;
+                self.search.pending = false;
+                self.transfers.clear();
+                self.downloading.clear();
+                self.pending_saves.clear();
+                let reason = disconnect_reason_text(&reason);
+                self.add_system_message(t!("sys-disconnected", reason = reason));
+            }
+        }
+    }
+
+    fn handle_command_reply(&mut self, reply: CommandReply) {
+        match reply {
+            CommandReply::Who { users, more } => {
+                self.who_pending.extend(users);
+                if !more {
+                    let users = std::mem::take(&mut self.who_pending);
+                    self.add_system_message(t!("sys-who", count = users.len(), users = users.join(", ")));
+                }
+            }
+            CommandReply::Topic { topic: Some(topic) } => {
+                self.add_system_message(t!("sys-topic", topic = topic.as_str()));
+                self.topic = Some(topic);
+            }
+            CommandReply::Topic { topic: None } => {
+                self.add_system_message(t!("sys-no-topic"));
+                self.topic = None;
+            }
+            CommandReply::InviteCreated { code } => {
+                self.add_system_message(t!("sys-invite-created", code = code));
+            }
+            CommandReply::InviteRevoked { code } => {
+                self.add_system_message(t!("sys-invite-revoked", code = code));
+            }
+            CommandReply::Invites { codes } if codes.is_empty() => {
+                self.add_system_message(t!("sys-no-invites"));
+            }
+            CommandReply::Invites { codes } => {
+                self.add_system_message(t!("sys-invites", count = codes.len(), codes = codes.join(", ")));
+            }
+            CommandReply::OfflineQueued { to } => {
+                self.add_system_message(t!("sys-offline-queued", user = to));
+            }
+            CommandReply::Help { commands } => {
+                self.add_system_message(t!("sys-help"));
+                for command in commands {
+                    self.add_system_message(format!("{} — {}", command.usage, command.description));
+                }
+            }
+        }
+    }
+
+    /// 记录用户的公钥，与之前见过的不同时发出警告
+    fn observe_key(&mut self, username: &str, public_key: &[u8]) {
+        if username == self.username {
+            return;
+        }
+        if self.known_keys.observe(&self.server_addr, username, public_key) == KeyStatus::Changed {
+            self.add_system_message(t!("sys-key-changed", user = username));
+        }
+    }
+
+    /// 自己公钥的指纹
+    pub fn own_fingerprint(&self) -> Option<String> {
+        self.identity.as_ref().map(Identity::fingerprint)
+    }
+
+    /// 在线用户公钥的指纹
+    pub fn fingerprint_of(&self, username: &str) -> Option<String> {
+        self.online_users
+            .iter()
+            .find(|u| u.username == username)
+            .and_then(|u| u.public_key.as_deref())
+            .map(e2e::fingerprint)
+    }
+
+    /// 用户公钥的信任状态，未见过时返回 None
+    pub fn key_status(&self, username: &str) -> Option<KeyStatus> {
+        self.known_keys.status(&self.server_addr, username)
+    }
+
+    /// 核对指纹后将用户的公钥标记为已验证
+    pub fn verify_key(&mut self, username: &str) {
+        self.known_keys.verify(&self.server_addr, username);
+        self.add_system_message(t!("sys-key-verified", user = username));
+    }
+
+    /// 打开当前服务器的本地聊天记录，并用其中最近的消息替换消息列表
+    ///
+    /// 重新连接同一服务器时保留内存中的消息。
+    fn open_log(&mut self) {
+        if self.log.as_ref().is_some_and(|log| log.server() == self.server_addr) {
+            return;
+        }
+        self.log = None;
+        if self.log_retention_days == 0 {
+            return;
+        }
+        match ChatLog::open(&self.server_addr, self.log_retention_days) {
+            Ok((log, history)) => {
+                let skip = history.len().saturating_sub(MAX_MESSAGES);
+                self.messages = history.into_iter().skip(skip).collect();
+                if !self.messages.is_empty() {
+                    self.add_system_message(t!("sys-log-loaded", count = self.messages.len()));
+                }
+                self.log = Some(log);
             }
+            Err(e) => warn!("Failed to open chat log for {}: {}", self.server_addr, e),
         }
     }
 
     fn add_message(&mut self, msg: ChatMessage) {
+        // 加密私聊的明文默认不落盘
+        if chat_log::should_log(&msg, self.log_encrypted) {
+            if let Some(log) = &mut self.log {
+                if let Err(e) = log.append(&msg) {
+                    warn!("Failed to write chat log, logging disabled: {}", e);
+                    self.log = None;
+                }
+            }
+        }
         // 限制消息历史数量（VecDeque::pop_front 是 O(1)）
         if self.messages.len() >= MAX_MESSAGES {
             self.messages.pop_front();
@@ -221,34 +751,144 @@ impl ChatClient {
             .unwrap()
             .as_secs();
         self.add_message(ChatMessage {
-            username: "系统".to_string(),
+            id: None,
+            username: t!("system-user"),
             content,
             timestamp,
             is_system: true,
+            reply_to: None,
+            mentions_me: false,
+            attachment: None,
+            kind: MessageKind::Chat,
         });
     }
 
-    /// 验证用户名格式
-    pub fn validate_username(&self) -> Result<(), String> {
-        let username = &self.username;
-        if username.is_empty() {
-            return Err("用户名不能为空".to_string());
-        }
-        if username.len() > MAX_USERNAME_LEN {
-            return Err(format!("用户名不能超过 {} 个字符", MAX_USERNAME_LEN));
+    /// 按 ID 查找消息（仅在本地历史中查找）
+    pub fn find_message(&self, id: u64) -> Option<&ChatMessage> {
+        self.messages.iter().rev().find(|m| m.id == Some(id))
+    }
+
+    /// 本地历史中的消息按 ID 索引，绘制消息列表时每帧建一次，用于查找被回复的消息
+    pub fn messages_by_id(&self) -> HashMap<u64, &ChatMessage> {
+        self.messages.iter().filter_map(|m| Some((m.id?, m))).collect()
+    }
+
+    /// 设置回复目标
+    pub fn start_reply(&mut self, id: u64) {
+        self.reply_to = Some(id);
+    }
+
+    /// 取消回复
+    pub fn cancel_reply(&mut self) {
+        self.reply_to = None;
+    }
+
+    /// 清除未查看的 @提及 计数
+    pub fn clear_mentions(&mut self) {
+        self.unread_mentions = 0;
+    }
+
+    /// 是否是未读的其他用户消息
+    fn is_unread(&self, msg: &ChatMessage) -> bool {
+        !msg.is_system && msg.username != self.username && ReadPosition::of(msg) > self.last_read
+    }
+
+    /// 未读消息数量
+    pub fn unread_messages(&self) -> usize {
+        self.messages.iter().filter(|m| self.is_unread(m)).count()
+    }
+
+    /// 第一条未读消息的 ID
+    pub fn first_unread(&self) -> Option<u64> {
+        self.messages.iter().find(|m| self.is_unread(m)).and_then(|m| m.id)
+    }
+
+    /// 把所有消息标为已读，同时清除 @提及 计数
+    ///
+    /// 开启了已读回执时同时告诉服务器。
+    pub fn mark_read(&mut self) {
+        let Some(latest) = self.messages.iter().rev().find_map(ReadPosition::of) else {
+            return;
+        };
+        if Some(latest) > self.last_read {
+            self.last_read = Some(latest);
+            self.unread_mentions = 0;
+            chat_log::save_read_position(&self.read_server, latest);
         }
-        if !username
-            .chars()
-            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        // 重新连接后即使没有新消息也发送一次，服务器重启后会丢失已读位置
+        if self.send_read_receipts
+            && matches!(self.state, ConnectionState::Connected { .. })
+            && Some(latest.message_id) > self.receipt_sent
         {
-            return Err("用户名只能包含字母、数字、下划线和连字符".to_string());
+            self.receipt_sent = Some(latest.message_id);
+            let _ = self.cmd_tx.send(UiCommand::MarkRead { up_to: latest.message_id });
+        }
+    }
+
+    /// 设置是否发送已读回执
+    ///
+    /// 关闭时（包括关闭状态下重新连接后）让服务器清除之前的已读位置，
+    /// 否则它会一直对其他用户可见，之后使用同一名字的用户也会沿用它。
+    pub fn set_send_read_receipts(&mut self, enabled: bool) {
+        self.send_read_receipts = enabled;
+        if !enabled && matches!(self.state, ConnectionState::Connected { .. }) && self.receipt_sent != Some(0) {
+            self.receipt_sent = Some(0);
+            let _ = self.cmd_tx.send(UiCommand::ClearRead);
+        }
+    }
+
+    /// 已读到某条消息的其他用户，按名字排序
+    ///
+    /// 私聊只算收件人，其他消息不算发送者本人和本地用户。
+    pub fn seen_by(&self, msg: &ChatMessage) -> Vec<&str> {
+        let Some(id) = msg.id.filter(|_| !msg.is_system) else {
+            return Vec::new();
+        };
+        let author = username_key(&msg.username);
+        let me = username_key(&self.username);
+        let recipient = match &msg.kind {
+            MessageKind::Direct { to, .. } => Some(username_key(to)),
+            _ => None,
+        };
+        let mut names: Vec<&str> = self
+            .read_receipts
+            .iter()
+            .filter(|(key, receipt)| {
+                receipt.up_to >= id
+                    && **key != author
+                    && **key != me
+                    && recipient.as_ref().is_none_or(|to| *key == to)
+            })
+            .map(|(_, receipt)| receipt.username.as_str())
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// 读取当前服务器的已读位置，从未记录过时把已有的消息（本地聊天记录）视为已读
+    fn load_read_position(&mut self) {
+        if self.read_server == self.server_addr {
+            return;
         }
-        Ok(())
+        self.read_server = self.server_addr.clone();
+        self.last_read = chat_log::load_read_position(&self.server_addr)
+            .or_else(|| self.messages.iter().rev().find_map(ReadPosition::of));
+    }
+
+    /// 验证用户名格式（与服务端使用相同的规则）
+    pub fn validate_username(&self) -> Result<(), String> {
+        protocol::validate_username(&self.username).map_err(|e| match e {
+            ProtocolError::UsernameTooLong { max, .. } => t!("username-too-long", max = max),
+            e => error_code_text(e.code()),
+        })
     }
 
     /// 连接服务器
     pub fn connect(&mut self) {
         if matches!(self.state, ConnectionState::Disconnected) {
+            // 与服务端一致地规范化，保证本地比较（@提及、用户列表）与服务端保存的名字相同
+            self.username = normalize_username(self.username.trim());
+
             // 客户端验证用户名
             if let Err(e) = self.validate_username() {
                 self.error_message = Some(e);
@@ -260,10 +900,32 @@ impl ChatClient {
             let _ = self.cmd_tx.send(UiCommand::Connect {
                 addr: self.server_addr.clone(),
                 username: self.username.clone(),
+                codec: self.codec,
+                access_key: Some(self.access_key.trim().to_string()).filter(|k| !k.is_empty()),
+                identity: self.identity.clone(),
             });
         }
     }
 
+    /// 用保存的配置填写连接信息
+    pub fn apply_profile(&mut self, profile: &Profile) {
+        self.server_addr = profile.server_addr.clone();
+        self.username = profile.username.clone();
+        self.codec = profile.codec;
+        self.access_key = profile.access_key.clone().unwrap_or_default();
+    }
+
+    /// 把当前连接信息保存为配置，`remember_key` 为 false 时不保存聊天室密码
+    pub fn to_profile(&self, name: &str, remember_key: bool) -> Profile {
+        Profile {
+            name: name.to_string(),
+            server_addr: self.server_addr.trim().to_string(),
+            username: self.username.trim().to_string(),
+            codec: self.codec,
+            access_key: Some(self.access_key.trim().to_string()).filter(|k| remember_key && !k.is_empty()),
+        }
+    }
+
     /// 断开连接
     pub fn disconnect(&mut self) {
         if matches!(self.state, ConnectionState::Connected { .. }) {
@@ -272,12 +934,139 @@ impl ChatClient {
     }
 
     /// 发送消息
+    ///
+    /// `/msg <用户> <内容>` 在本地加密后发送，服务器只转发密文。
     pub fn send_message(&mut self) {
         if matches!(self.state, ConnectionState::Connected { .. }) && !self.input_text.is_empty() {
             let content = self.input_text.clone();
             self.input_text.clear();
-            let _ = self.cmd_tx.send(UiCommand::SendChat { content });
+            if let Some((to, content)) = parse_direct(&content) {
+                let _ = self.cmd_tx.send(UiCommand::SendDirect { to, content });
+                return;
+            }
+            let reply_to = self.reply_to.take();
+            let _ = self.cmd_tx.send(UiCommand::SendChat { content, reply_to });
+        }
+    }
+
+    /// 按搜索框中的条件搜索历史消息
+    pub fn search(&mut self) {
+        if !self.is_connected() {
+            return;
+        }
+        let query = self.search.query.trim();
+        if query.is_empty() {
+            return;
+        }
+        // 日期为空表示不限，格式错误时不发送请求
+        let date = |text: &str, end_of_day| match text.trim() {
+            "" => Some(None),
+            text => parse_date(text, end_of_day).map(Some),
+        };
+        let (Some(since), Some(until)) = (date(&self.search.since, false), date(&self.search.until, true)) else {
+            self.search.error = Some(t!("search-invalid-date"));
+            return;
+        };
+        let from_user = self.search.from_user.trim();
+        let request = SearchRequest {
+            from_user: Some(from_user.to_string()).filter(|u| !u.is_empty()),
+            since,
+            until,
+            ..SearchRequest::new(query)
+        };
+        self.send_search(request);
+    }
+
+    /// 加载下一页搜索结果
+    pub fn search_more(&mut self) {
+        if let (Some(request), Some(before)) = (&self.search.request, self.search.next_before) {
+            if !self.search.pending {
+                let request = SearchRequest { before: Some(before), ..request.clone() };
+                self.send_search(request);
+            }
+        }
+    }
+
+    fn send_search(&mut self, request: SearchRequest) {
+        self.search.error = None;
+        self.search.pending = true;
+        self.search.request = Some(request.clone());
+        let _ = self.cmd_tx.send(UiCommand::Search(request));
+    }
+
+    /// 上传文件
+    pub fn upload_file(&mut self, path: PathBuf) {
+        if self.is_connected() {
+            let _ = self.cmd_tx.send(UiCommand::UploadFile { path });
+        }
+    }
+
+    /// 下载文件（已下载或正在下载时忽略）
+    pub fn download_file(&mut self, file_id: u64, name: &str, size: u64) {
+        if !self.is_connected()
+            || self.downloaded.contains_key(&file_id)
+            || !self.downloading.insert(file_id)
+        {
+            return;
         }
+        let _ = self.cmd_tx.send(UiCommand::DownloadFile {
+            file_id,
+            name: name.to_string(),
+            size,
+        });
+    }
+
+    /// 保存文件到下载目录，尚未下载时先下载
+    pub fn save_attachment(&mut self, attachment: &Attachment) {
+        if self.downloaded.contains_key(&attachment.file_id) {
+            self.save_file(attachment.file_id);
+        } else {
+            self.pending_saves.insert(attachment.file_id);
+            self.download_file(attachment.file_id, &attachment.name, attachment.size);
+        }
+    }
+
+    /// 将已下载的文件写入下载目录
+    fn save_file(&mut self, file_id: u64) {
+        let Some(data) = self.downloaded.get(&file_id).cloned() else {
+            return;
+        };
+        let Some(name) = self
+            .messages
+            .iter()
+            .filter_map(|m| m.attachment.as_ref())
+            .find(|a| a.file_id == file_id)
+            .map(|a| a.name.clone())
+        else {
+            return;
+        };
+
+        let dir = dirs::download_dir().unwrap_or_else(|| PathBuf::from("."));
+        let mut path = dir.join(&name);
+        if path.exists() {
+            path = dir.join(format!("{}-{}", file_id, name));
+        }
+
+        match std::fs::write(&path, &data) {
+            Ok(()) => self.add_system_message(t!("sys-file-saved", path = path.display().to_string())),
+            Err(e) => self.error_message = Some(t!("save-failed", error = e.to_string())),
+        }
+    }
+
+    /// 把当前消息列表导出到下载目录
+    pub fn export(&mut self, options: &ExportOptions) {
+        let content = export::export(&self.messages, &self.server_addr, options);
+        let dir = dirs::download_dir().unwrap_or_else(|| PathBuf::from("."));
+        let path = dir.join(export::file_name(&self.server_addr, options.format));
+        match std::fs::write(&path, content) {
+            Ok(()) => self.add_system_message(t!("sys-exported", path = path.display().to_string())),
+            Err(e) => self.error_message = Some(t!("export-failed", error = e.to_string())),
+        }
+    }
+
+    /// 取消传输
+    pub fn cancel_transfer(&mut self, transfer_id: u32) {
+        let _ = self.cmd_tx.send(UiCommand::CancelTransfer { transfer_id });
     }
 
     /// 是否已连接
@@ -299,179 +1088,180 @@ async fn network_loop(
 ) {
     loop {
         // 等待连接命令
-        let (addr, username) = match cmd_rx.recv().await {
-            Some(UiCommand::Connect { addr, username }) => (addr, username),
+        let config = match cmd_rx.recv().await {
+            Some(UiCommand::Connect {
+                addr,
+                username,
+                codec,
+                access_key,
+                identity,
+            }) => {
+                let mut config = SessionConfig::new(addr, username).with_codec(codec);
+                if let Some(key) = access_key {
+                    config = config.with_access_key(key);
+                }
+                if let Some(identity) = identity {
+                    config = config.with_identity(identity);
+                }
+                config
+            }
             Some(_) => continue,
             None => break, // UI 线程已关闭
         };
 
-        // 尝试连接
-        match connect_and_run(&addr, &username, &mut cmd_rx, &event_tx).await {
-            Ok(()) => {
-                let _ = event_tx
-                    .send(NetworkEvent::Disconnected {
-                        reason: "正常断开".to_string(),
-                    })
-                    .await;
-            }
+        let mut session = match ChatSession::connect(config).await {
+            Ok(session) => session,
             Err(e) => {
                 let _ = event_tx
-                    .send(NetworkEvent::Disconnected {
-                        reason: e.to_string(),
+                    .send(NetworkEvent::ConnectFailed {
+                        reason: session_error_text(&e),
                     })
                     .await;
+                continue;
             }
+        };
+        let _ = event_tx
+            .send(NetworkEvent::Connected {
+                user_id: session.user_id(),
+                online_users: session.online_users().to_vec(),
+                topic: session.topic().map(str::to_string),
+            })
+            .await;
+
+        if !run_session(&mut session, &mut cmd_rx, &event_tx).await {
+            break;
         }
     }
 }
 
-/// 连接并运行消息循环
-async fn connect_and_run(
-    addr: &str,
-    username: &str,
+/// 在会话结束前转发 UI 命令和会话事件，UI 线程关闭时返回 false
+async fn run_session(
+    session: &mut ChatSession,
     cmd_rx: &mut mpsc::Receiver<UiCommand>,
     event_tx: &mpsc::Sender<NetworkEvent>,
-) -> anyhow::Result<()> {
-    // 连接服务器
-    let config = TransportConfig {
-        connect_timeout: CONNECT_TIMEOUT,
-        nodelay: true,
-    };
-
-    let transport = match TcpTransport::connect(addr, &config).await {
-        Ok(t) => t,
-        Err(e) => {
-            let _ = event_tx
-                .send(NetworkEvent::ConnectFailed {
-                    reason: format!("连接失败: {}", e),
-                })
-                .await;
-            return Ok(());
-        }
-    };
-
-    info!("Connected to {}", addr);
-    let mut conn = Connection::new(transport);
-
-    // 发送 Join 消息
-    conn.send(&ClientMessage::Join {
-        username: username.to_string(),
-    })
-    .await?;
-
-    // 等待 Welcome 响应
-    match conn.recv::<ServerMessage>().await? {
-        ServerMessage::Welcome { user_id, online_users } => {
-            let _ = event_tx.send(NetworkEvent::Connected { user_id, online_users }).await;
-            info!("Joined as user_id={}", user_id);
-        }
-        ServerMessage::Error { message } => {
-            let _ = event_tx
-                .send(NetworkEvent::ConnectFailed {
-                    reason: format!("加入失败: {}", message),
-                })
-                .await;
-            return Ok(());
-        }
-        _ => {
-            let _ = event_tx
-                .send(NetworkEvent::ConnectFailed {
-                    reason: "协议错误: 未收到 Welcome".to_string(),
-                })
-                .await;
-            return Ok(());
-        }
-    }
-
-    // 分离读写
-    let (mut reader, mut writer) = conn.split();
-
-    // 心跳定时器
-    let mut heartbeat = interval(HEARTBEAT_INTERVAL);
-    heartbeat.tick().await; // 跳过第一次立即触发
-
+) -> bool {
+    let handle = session.handle().clone();
     loop {
         tokio::select! {
-            // 接收服务器消息
-            result = reader.recv::<ServerMessage>() => {
-                match result {
-                    Ok(msg) => {
-                        match msg {
-                            ServerMessage::ChatBroadcast { username, content, timestamp } => {
-                                let _ = event_tx.send(NetworkEvent::ChatMessage {
-                                    username,
-                                    content,
-                                    timestamp,
-                                }).await;
-                            }
-                            ServerMessage::UserJoined { username } => {
-                                let _ = event_tx.send(NetworkEvent::UserJoined { username }).await;
-                            }
-                            ServerMessage::UserLeft { username } => {
-                                let _ = event_tx.send(NetworkEvent::UserLeft { username }).await;
-                            }
-                            ServerMessage::Error { message } => {
-                                let _ = event_tx.send(NetworkEvent::Error { message }).await;
-                            }
-                            ServerMessage::Pong => {
-                                debug!("Received pong");
-                            }
-                            ServerMessage::Welcome { .. } => {
-                                // 忽略重复的 Welcome
-                            }
-                            ServerMessage::Shutdown { message } => {
-                                info!("Server shutdown: {}", message);
-                                let _ = event_tx.send(NetworkEvent::Disconnected {
-                                    reason: format!("服务器关闭: {}", message),
-                                }).await;
-                                return Ok(());
-                            }
-                        }
-                    }
-                    Err(ProtocolError::ConnectionClosed) => {
-                        info!("Server closed connection");
-                        break;
-                    }
-                    Err(e) => {
-                        warn!("Receive error: {}", e);
-                        break;
-                    }
+            event = session.next_event() => {
+                let Some(event) = event else {
+                    return true;
+                };
+                let disconnected = matches!(event, SessionEvent::Disconnected { .. });
+                let _ = event_tx.send(NetworkEvent::Session(event)).await;
+                if disconnected {
+                    return true;
                 }
             }
 
-            // 心跳
-            _ = heartbeat.tick() => {
-                if let Err(e) = writer.send(&ClientMessage::Ping).await {
-                    warn!("Failed to send ping: {}", e);
-                    break;
-                }
-                debug!("Sent ping");
-            }
-
-            // 处理 UI 命令（直接 await，不再轮询）
+            // 处理 UI 命令（会话已结束时返回的 Closed 错误忽略，随后会收到 Disconnected）
             cmd = cmd_rx.recv() => {
-                match cmd {
-                    Some(UiCommand::SendChat { content }) => {
-                        if let Err(e) = writer.send(&ClientMessage::Chat { content }).await {
-                            warn!("Failed to send chat: {}", e);
-                            break; // 现在正确退出外层 loop
-                        }
-                    }
-                    Some(UiCommand::Disconnect) => {
-                        let _ = writer.send(&ClientMessage::Leave).await;
-                        return Ok(());
+                let result = match cmd {
+                    Some(UiCommand::SendChat { content, reply_to }) => handle.send_chat(content, reply_to).await,
+                    Some(UiCommand::SendDirect { to, content }) => handle.send_direct(to, content).await,
+                    Some(UiCommand::UploadFile { path }) => handle.upload_file(&path).await.map(|_| ()),
+                    Some(UiCommand::DownloadFile { file_id, name, size }) => {
+                        handle.download(file_id, name, size).await.map(|_| ())
                     }
+                    Some(UiCommand::CancelTransfer { transfer_id }) => handle.cancel_transfer(transfer_id).await,
+                    Some(UiCommand::Search(request)) => handle.search(request).await,
+                    Some(UiCommand::MarkRead { up_to }) => handle.mark_read(up_to).await,
+                    Some(UiCommand::ClearRead) => handle.clear_read().await,
+                    Some(UiCommand::Disconnect) => handle.disconnect().await,
                     Some(UiCommand::Connect { .. }) => {
                         // 已连接，忽略
+                        Ok(())
                     }
                     None => {
                         // 通道关闭，退出
-                        break;
+                        let _ = handle.disconnect().await;
+                        return false;
+                    }
+                };
+                match result {
+                    Ok(()) | Err(SessionError::Closed) => {}
+                    Err(e) => {
+                        let _ = event_tx.send(NetworkEvent::Error { message: session_error_text(&e) }).await;
                     }
                 }
             }
         }
     }
+}
+
+/// 会话错误的本地化描述
+fn session_error_text(error: &SessionError) -> String {
+    match error {
+        SessionError::Connect(e) => t!("connect-failed", error = e.to_string()),
+        SessionError::Rejected { code, message } => {
+            t!("join-failed", reason = server_error_text(*code, message.clone()))
+        }
+        SessionError::UnexpectedResponse => t!("no-welcome"),
+        SessionError::File(e) => t!("file-read-failed", error = e.to_string()),
+        SessionError::InvalidFileName => t!("file-path-invalid"),
+        SessionError::FileTooLarge { max, .. } => t!("file-too-large", max = max / 1024 / 1024),
+        SessionError::Protocol(e) => error_code_text(e.code()),
+        SessionError::E2e(e) => e2e_error_text(e),
+        SessionError::Closed => t!("disconnect-normal"),
+    }
+}
+
+/// 加密私聊错误的本地化描述
+fn e2e_error_text(error: &E2eError) -> String {
+    match error {
+        E2eError::NoPublicKey { username } => t!("dm-no-key", user = username.as_str()),
+        E2eError::InvalidPublicKey => t!("dm-invalid-key"),
+        E2eError::Random(e) => t!("dm-random-failed", error = e.as_str()),
+        E2eError::TooLong { max, .. } => t!("dm-too-long", max = max),
+        E2eError::Decrypt => t!("dm-decrypt-failed"),
+    }
+}
+
+/// 解析 `/msg <用户> <内容>`，命令名不区分大小写
+fn parse_direct(input: &str) -> Option<(String, String)> {
+    let (command, rest) = input.split_once(char::is_whitespace)?;
+    if !command.eq_ignore_ascii_case("/msg") {
+        return None;
+    }
+    let (to, content) = rest.trim_start().split_once(char::is_whitespace)?;
+    let content = content.trim();
+    (!content.is_empty()).then(|| (to.to_string(), content.to_string()))
+}
+
+/// 传输失败原因的本地化描述
+fn transfer_error_text(error: &TransferError) -> String {
+    match error {
+        TransferError::Cancelled => t!("transfer-cancelled"),
+        // 服务器给出的原因已按服务器语言本地化
+        TransferError::Server(reason) => reason.clone(),
+        TransferError::Unknown => t!("transfer-unknown"),
+        TransferError::OutOfOrder { .. } => t!("transfer-out-of-order"),
+        TransferError::ChecksumMismatch { index } => t!("transfer-checksum", index = index),
+        TransferError::SizeMismatch => t!("transfer-size-mismatch"),
+        TransferError::Incomplete => t!("transfer-incomplete"),
+    }
+}
+
+/// 断开原因的本地化描述
+fn disconnect_reason_text(reason: &DisconnectReason) -> String {
+    match reason {
+        DisconnectReason::Requested | DisconnectReason::ConnectionClosed => t!("disconnect-normal"),
+        DisconnectReason::ServerShutdown { message } => t!("server-shutdown", message = message),
+        DisconnectReason::Error(error) => error.clone(),
+    }
+}
+
+/// 角色的本地化名称
+pub fn role_text(role: Role) -> String {
+    t!(&format!("role-{}", role.name()))
+}
+
+/// 错误码的本地化描述
+fn error_code_text(code: ErrorCode) -> String {
+    t!(&format!("error-{}", code.name()))
+}
 
-    Ok(())
+/// 服务器错误的描述：优先显示服务器附带的说明（可能包含具体原因），没有时按错误码本地化
+fn server_error_text(code: ErrorCode, message: Option<String>) -> String {
+    message.unwrap_or_else(|| error_code_text(code))
 }
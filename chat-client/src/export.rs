@@ -0,0 +1,318 @@
+//! 聊天记录导出
+//!
+//! 把消息导出为 Markdown、独立的 HTML 页面或 JSON Lines，便于附在事故报告等文档中。
+//! 图形界面导出当前消息列表，`chat-client export` 子命令导出本地聊天记录。
+
+use std::fmt;
+use std::str::FromStr;
+
+use i18n::t;
+use serde::Serialize;
+
+use crate::chat_log;
+use crate::client::{ChatMessage, MessageKind};
+use crate::format::{format_date, format_size, format_timestamp};
+
+/// 导出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Markdown,
+    Html,
+    JsonLines,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [ExportFormat::Markdown, ExportFormat::Html, ExportFormat::JsonLines];
+
+    /// 文件扩展名
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::JsonLines => "jsonl",
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ExportFormat::Markdown => "markdown",
+            ExportFormat::Html => "html",
+            ExportFormat::JsonLines => "jsonl",
+        })
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "html" => Ok(ExportFormat::Html),
+            "jsonl" | "json" => Ok(ExportFormat::JsonLines),
+            other => Err(format!("unknown export format: {} (expected markdown, html or jsonl)", other)),
+        }
+    }
+}
+
+/// 导出选项
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// 只导出这个时间（Unix 时间戳，含）之后的消息
+    pub since: Option<u64>,
+    /// 只导出这个时间（含）之前的消息
+    pub until: Option<u64>,
+    /// 是否包含系统消息
+    pub include_system: bool,
+}
+
+impl ExportOptions {
+    fn includes(&self, msg: &ChatMessage) -> bool {
+        (self.include_system || !msg.is_system)
+            && self.since.is_none_or(|since| msg.timestamp >= since)
+            && self.until.is_none_or(|until| msg.timestamp <= until)
+    }
+}
+
+/// 按选项导出消息，`server` 用作标题
+pub fn export<'a>(
+    messages: impl IntoIterator<Item = &'a ChatMessage>,
+    server: &str,
+    options: &ExportOptions,
+) -> String {
+    let messages: Vec<&ChatMessage> = messages.into_iter().filter(|m| options.includes(m)).collect();
+    match options.format {
+        ExportFormat::Markdown => to_markdown(&messages, server),
+        ExportFormat::Html => to_html(&messages, server),
+        ExportFormat::JsonLines => to_json_lines(&messages),
+    }
+}
+
+/// 导出文件名，如 `chat-127.0.0.1_8080-20240531-142300.md`
+pub fn file_name(server: &str, format: ExportFormat) -> String {
+    let now = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    format!("chat-{}-{}.{}", chat_log::file_name(server), now, format.extension())
+}
+
+/// 消息发送者一栏，如 `alice`、`alice → bob`
+fn sender(msg: &ChatMessage) -> String {
+    match &msg.kind {
+        MessageKind::Direct { to, encrypted, offline } => {
+            let offline = if *offline { format!(" ({})", t!("direct-offline")) } else { String::new() };
+            format!("{} → {}{}{}", msg.username, to, if *encrypted { " 🔒" } else { "" }, offline)
+        }
+        _ => msg.username.clone(),
+    }
+}
+
+fn attachment_text(msg: &ChatMessage) -> Option<String> {
+    msg.attachment
+        .as_ref()
+        .map(|a| format!("📎 {} ({})", a.name, format_size(a.size)))
+}
+
+fn to_markdown(messages: &[&ChatMessage], server: &str) -> String {
+    let mut out = format!("# {}\n", t!("export-title", server = server));
+    let mut date = String::new();
+    for msg in messages {
+        let day = format_date(msg.timestamp);
+        if day != date {
+            out.push_str(&format!("\n## {}\n\n", day));
+            date = day;
+        }
+        let time = format_timestamp(msg.timestamp);
+        let content = markdown_escape(&msg.content);
+        let line = if msg.is_system {
+            format!("`{}` _{}_", time, content)
+        } else if msg.kind == MessageKind::Action {
+            format!("`{}` _* {} {}_", time, markdown_escape(&msg.username), content)
+        } else {
+            format!("`{}` **{}:** {}", time, markdown_escape(&sender(msg)), content)
+        };
+        out.push_str(&line);
+        if let Some(attachment) = attachment_text(msg) {
+            out.push_str(&format!(" {}", markdown_escape(&attachment)));
+        }
+        out.push_str("  \n");
+    }
+    out
+}
+
+/// 转义 Markdown 标记字符，消息按原文显示；换行保留为硬换行
+fn markdown_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|' | '~' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("  \n"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+const HTML_STYLE: &str = "body{font-family:sans-serif;max-width:50em;margin:2em auto;color:#222}\
+h2{font-size:1em;color:#666;border-bottom:1px solid #ddd}\
+.msg{margin:.3em 0;white-space:pre-wrap}\
+.time{color:#999;font-family:monospace;margin-right:.5em}\
+.user{font-weight:bold}\
+.system{color:#888;font-style:italic}\
+.action{font-style:italic}\
+.direct{color:#7a4fc9}\
+.file{color:#666}";
+
+fn to_html(messages: &[&ChatMessage], server: &str) -> String {
+    let title = html_escape(&t!("export-title", server = server));
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>{HTML_STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n"
+    );
+    let mut date = String::new();
+    for msg in messages {
+        let day = format_date(msg.timestamp);
+        if day != date {
+            out.push_str(&format!("<h2>{}</h2>\n", html_escape(&day)));
+            date = day;
+        }
+        let time = html_escape(&format_timestamp(msg.timestamp));
+        let content = html_escape(&msg.content);
+        let body = if msg.is_system {
+            format!("<span class=\"system\">{}</span>", content)
+        } else if msg.kind == MessageKind::Action {
+            format!("<span class=\"action\">* {} {}</span>", html_escape(&msg.username), content)
+        } else {
+            let class = if matches!(msg.kind, MessageKind::Direct { .. }) { "user direct" } else { "user" };
+            format!("<span class=\"{}\">{}:</span> {}", class, html_escape(&sender(msg)), content)
+        };
+        out.push_str(&format!("<div class=\"msg\"><span class=\"time\">{}</span>{}", time, body));
+        if let Some(attachment) = attachment_text(msg) {
+            out.push_str(&format!(" <span class=\"file\">{}</span>", html_escape(&attachment)));
+        }
+        out.push_str("</div>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn html_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// JSON Lines 的一行：消息本身加上格式化的时间
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    time: String,
+    #[serde(flatten)]
+    message: &'a ChatMessage,
+}
+
+fn to_json_lines(messages: &[&ChatMessage]) -> String {
+    let mut out = String::new();
+    for msg in messages {
+        let record = JsonRecord {
+            time: format_timestamp(msg.timestamp),
+            message: msg,
+        };
+        // ChatMessage 只含字符串和数字，序列化不会失败
+        out.push_str(&serde_json::to_string(&record).expect("chat message serializes to JSON"));
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: u64, username: &str, content: &str, timestamp: u64) -> ChatMessage {
+        ChatMessage {
+            id: Some(id),
+            username: username.to_string(),
+            content: content.to_string(),
+            timestamp,
+            is_system: false,
+            reply_to: None,
+            mentions_me: false,
+            attachment: None,
+            kind: MessageKind::Chat,
+        }
+    }
+
+    fn options(format: ExportFormat) -> ExportOptions {
+        ExportOptions {
+            format,
+            ..ExportOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_html_escaping() {
+        let messages = [
+            message(1, "<b>mallory</b>", "<script>alert('x' & \"y\")</script>", 1_700_000_000),
+            ChatMessage {
+                kind: MessageKind::Direct {
+                    to: "bob&co".to_string(),
+                    encrypted: false,
+                    offline: false,
+                },
+                ..message(2, "alice", "a > b", 1_700_000_060)
+            },
+        ];
+        let html = export(&messages, "<server>", &options(ExportFormat::Html));
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("<b>"));
+        assert!(!html.contains("<server>"));
+        assert!(html.contains("&lt;script&gt;alert(&#39;x&#39; &amp; &quot;y&quot;)&lt;/script&gt;"));
+        assert!(html.contains("&lt;b&gt;mallory&lt;/b&gt;:</span>"));
+        assert!(html.contains("alice → bob&amp;co:</span> a &gt; b"));
+    }
+
+    #[test]
+    fn test_time_range() {
+        let messages = [
+            message(1, "alice", "first", 100),
+            message(2, "alice", "second", 200),
+            message(3, "alice", "third", 300),
+            ChatMessage {
+                id: None,
+                is_system: true,
+                ..message(0, "system", "notice", 200)
+            },
+        ];
+        let exported = |since, until, include_system| {
+            let options = ExportOptions {
+                since,
+                until,
+                include_system,
+                ..options(ExportFormat::JsonLines)
+            };
+            export(&messages, "server", &options)
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["content"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(exported(None, None, false), ["first", "second", "third"]);
+        // 起止时间都包含在内
+        assert_eq!(exported(Some(200), None, false), ["second", "third"]);
+        assert_eq!(exported(None, Some(200), false), ["first", "second"]);
+        assert_eq!(exported(Some(200), Some(200), true), ["second", "notice"]);
+        assert!(exported(Some(301), None, true).is_empty());
+    }
+}
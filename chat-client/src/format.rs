@@ -0,0 +1,85 @@
+//! 各前端共用的文本格式化
+
+use i18n::t;
+
+/// 格式化文件大小
+pub fn format_size(bytes: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = KB * 1024;
+    if bytes >= MB {
+        format!("{:.1} MB", bytes as f64 / MB as f64)
+    } else if bytes >= KB {
+        format!("{:.1} KB", bytes as f64 / KB as f64)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+/// 截断引用预览文本
+pub fn truncate_preview(content: &str) -> String {
+    const MAX_PREVIEW_CHARS: usize = 40;
+    let mut chars = content.chars();
+    let preview: String = chars.by_ref().take(MAX_PREVIEW_CHARS).collect();
+    if chars.next().is_some() {
+        format!("{}…", preview)
+    } else {
+        preview
+    }
+}
+
+/// 格式化时间戳
+pub fn format_timestamp(timestamp: u64) -> String {
+    use chrono::{Local, TimeZone};
+
+    // 使用本地时区
+    match Local.timestamp_opt(timestamp as i64, 0) {
+        chrono::LocalResult::Single(dt) => {
+            let now = Local::now();
+            let duration = now.signed_duration_since(dt);
+
+            if duration.num_seconds() < 60 && duration.num_seconds() >= 0 {
+                return t!("time-just-now");
+            }
+
+            dt.format("%H:%M:%S").to_string()
+        }
+        _ => {
+            // 回退到简单格式
+            format!("{}", timestamp)
+        }
+    }
+}
+
+/// 格式化带日期的时间戳，用于可能是几天前的消息（如搜索结果）
+pub fn format_date_time(timestamp: u64) -> String {
+    use chrono::{Local, TimeZone};
+
+    match Local.timestamp_opt(timestamp as i64, 0) {
+        chrono::LocalResult::Single(dt) => dt.format("%Y-%m-%d %H:%M").to_string(),
+        _ => format!("{}", timestamp),
+    }
+}
+
+/// 格式化日期（`YYYY-MM-DD`），用于按天分组
+pub fn format_date(timestamp: u64) -> String {
+    use chrono::{Local, TimeZone};
+
+    match Local.timestamp_opt(timestamp as i64, 0) {
+        chrono::LocalResult::Single(dt) => dt.format("%Y-%m-%d").to_string(),
+        _ => format!("{}", timestamp),
+    }
+}
+
+/// 解析 `YYYY-MM-DD` 格式的本地日期，返回当天开始（`end_of_day` 为 true 时为结束）的 Unix 时间戳
+pub fn parse_date(text: &str, end_of_day: bool) -> Option<u64> {
+    use chrono::{Local, NaiveDate, NaiveTime, TimeZone};
+
+    let date = NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").ok()?;
+    let time = if end_of_day {
+        NaiveTime::from_hms_opt(23, 59, 59)?
+    } else {
+        NaiveTime::MIN
+    };
+    let dt = Local.from_local_datetime(&date.and_time(time)).earliest()?;
+    Some(dt.timestamp().max(0) as u64)
+}
@@ -0,0 +1,211 @@
+//! 加密私聊的本地密钥
+//!
+//! 自己的私钥和见过的公钥保存在数据目录下的 `chat-client` 目录中。
+//! 公钥按首次使用信任（TOFU）：第一次见到某个用户的公钥时记下，之后公钥变化时发出警告，
+//! 直到用户通过其他渠道核对指纹并重新标记为已验证。
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use chat_client_core::Identity;
+use protocol::username_key;
+use tracing::warn;
+
+/// 私钥文件名
+const IDENTITY_FILE: &str = "identity.key";
+
+/// 已知公钥文件名
+const KNOWN_KEYS_FILE: &str = "known_keys";
+
+/// 客户端数据目录
+pub(crate) fn data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("chat-client"))
+}
+
+/// 读取保存的密钥对，不存在时生成并保存
+///
+/// 无法读写数据目录时使用临时密钥，其他用户每次连接都会看到密钥变更。
+pub fn load_identity() -> Option<Identity> {
+    let path = data_dir()?.join(IDENTITY_FILE);
+    if let Ok(bytes) = fs::read(&path) {
+        match <[u8; 32]>::try_from(bytes.as_slice()) {
+            Ok(secret) => return Some(Identity::from_secret_bytes(secret)),
+            Err(_) => warn!("Ignoring malformed identity file {}", path.display()),
+        }
+    }
+
+    let identity = match Identity::generate() {
+        Ok(identity) => identity,
+        Err(e) => {
+            warn!("Failed to generate identity: {}", e);
+            return None;
+        }
+    };
+    if let Err(e) = write_private(&path, &identity.secret_bytes()) {
+        warn!("Failed to save identity to {}: {}", path.display(), e);
+    }
+    Some(identity)
+}
+
+/// 写入只有当前用户可读的文件
+pub(crate) fn write_private(path: &PathBuf, data: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = private_options();
+    options.write(true).truncate(true);
+    options.open(path)?.write_all(data)
+}
+
+/// 新建文件时只有当前用户可读的打开选项（已有文件的权限不变）
+pub(crate) fn private_options() -> fs::OpenOptions {
+    let mut options = fs::OpenOptions::new();
+    options.create(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+}
+
+/// 公钥的信任状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyStatus {
+    /// 首次见到后未变化，但尚未核对指纹
+    Unverified,
+    /// 已核对指纹
+    Verified,
+    /// 与之前记录的公钥不同，尚未重新核对
+    Changed,
+}
+
+impl KeyStatus {
+    fn name(self) -> &'static str {
+        match self {
+            KeyStatus::Unverified => "unverified",
+            KeyStatus::Verified => "verified",
+            KeyStatus::Changed => "changed",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [KeyStatus::Unverified, KeyStatus::Verified, KeyStatus::Changed]
+            .into_iter()
+            .find(|s| s.name() == name)
+    }
+}
+
+/// 记录的公钥
+#[derive(Debug, Clone)]
+struct KnownKey {
+    key: Vec<u8>,
+    status: KeyStatus,
+}
+
+/// 见过的公钥，按 (服务器地址, 用户名比较键) 索引
+///
+/// 文件每行一条记录：`服务器\t用户名\t十六进制公钥\t状态`。
+#[derive(Debug, Default)]
+pub struct KnownKeys {
+    path: Option<PathBuf>,
+    entries: HashMap<(String, String), KnownKey>,
+}
+
+impl KnownKeys {
+    /// 从数据目录读取，文件不存在或损坏的行被忽略
+    pub fn load() -> Self {
+        let mut keys = Self {
+            path: data_dir().map(|dir| dir.join(KNOWN_KEYS_FILE)),
+            entries: HashMap::new(),
+        };
+        keys.reload();
+        keys
+    }
+
+    /// 重新读取文件，修改前调用，以免覆盖其他会话（多个标签页）记下的公钥
+    fn reload(&mut self) {
+        // 没有数据目录时只保存在内存中
+        let Some(path) = &self.path else {
+            return;
+        };
+        self.entries.clear();
+        let Ok(content) = fs::read_to_string(path) else {
+            return;
+        };
+        for line in content.lines() {
+            let fields: Vec<_> = line.split('\t').collect();
+            let [server, username, key, status] = fields[..] else {
+                continue;
+            };
+            if let (Some(key), Some(status)) = (decode_hex(key), KeyStatus::from_name(status)) {
+                self.entries.insert((server.to_string(), username.to_string()), KnownKey { key, status });
+            }
+        }
+    }
+
+    /// 记录见到的公钥并返回其状态；与记录不同时改记新公钥并标记为已变更
+    pub fn observe(&mut self, server: &str, username: &str, key: &[u8]) -> KeyStatus {
+        self.reload();
+        let id = (server.to_string(), username_key(username));
+        let status = match self.entries.get(&id) {
+            Some(known) if known.key == key => return known.status,
+            Some(_) => KeyStatus::Changed,
+            None => KeyStatus::Unverified,
+        };
+        self.entries.insert(id, KnownKey { key: key.to_vec(), status });
+        self.save();
+        status
+    }
+
+    /// 公钥的状态，未记录时返回 None
+    pub fn status(&self, server: &str, username: &str) -> Option<KeyStatus> {
+        self.entries
+            .get(&(server.to_string(), username_key(username)))
+            .map(|known| known.status)
+    }
+
+    /// 核对指纹后标记为已验证
+    pub fn verify(&mut self, server: &str, username: &str) {
+        self.reload();
+        if let Some(known) = self.entries.get_mut(&(server.to_string(), username_key(username))) {
+            known.status = KeyStatus::Verified;
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let mut content = String::new();
+        for ((server, username), known) in &self.entries {
+            content.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                server,
+                username,
+                encode_hex(&known.key),
+                known.status.name()
+            ));
+        }
+        if let Err(e) = write_private(path, content.as_bytes()) {
+            warn!("Failed to save known keys to {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,31 @@
+//! 聊天室客户端库
+//!
+//! 网络线程、客户端状态和格式化工具，由图形界面 (chat-client) 和终端界面 (chat-tui) 共用。
+
+pub mod chat_log;
+pub mod client;
+pub mod export;
+pub mod format;
+pub mod keys;
+pub mod profiles;
+pub mod settings;
+pub mod transfer;
+
+use i18n::Catalog;
+
+/// 界面消息目录
+pub const CATALOGS: &[Catalog] = &[
+    Catalog {
+        locale: "en-US",
+        source: include_str!("../locales/en-US.ftl"),
+    },
+    Catalog {
+        locale: "zh-CN",
+        source: include_str!("../locales/zh-CN.ftl"),
+    },
+];
+
+/// 根据 `--lang` 参数、`CHAT_LANG` 环境变量或系统设置初始化界面语言
+pub fn init_locale(lang: Option<&str>) {
+    i18n::init(i18n::Localizer::new(&i18n::detect_locale(lang), CATALOGS));
+}
@@ -2,10 +2,15 @@
 //!
 //! 基于 egui 的图形化客户端
 
-mod client;
 mod ui;
 
 use anyhow::Result;
+use std::io::Write;
+
+use chat_client::chat_log::{self, parse_log_days, DEFAULT_RETENTION_DAYS};
+use chat_client::export::ExportOptions;
+use chat_client::format::parse_date;
+use i18n::t;
 use tracing_subscriber::EnvFilter;
 use ui::ChatApp;
 
@@ -15,10 +20,34 @@ fn main() -> Result<()> {
         .with_env_filter(
             EnvFilter::from_default_env()
                 .add_directive("chat_client=debug".parse()?)
-                .add_directive("protocol=debug".parse()?),
+                .add_directive("protocol=debug".parse()?)
+                .add_directive("i18n=warn".parse()?),
         )
         .init();
 
+    // 用法: chat-client [--lang LOCALE] [--log-days DAYS] [--log-encrypted]
+    //       chat-client export SERVER [--format markdown|html|jsonl] [--since DATE] [--until DATE] [--output FILE] [--lang LOCALE]
+    // 界面语言未指定时按 CHAT_LANG 环境变量和系统设置决定；本地聊天记录保留天数为 0 时不保存；
+    // 加密私聊只有指定 --log-encrypted 时才写入本地聊天记录
+    let mut args = std::env::args().skip(1).peekable();
+    if args.next_if_eq("export").is_some() {
+        return export(args);
+    }
+    let mut lang = None;
+    let mut log_days = DEFAULT_RETENTION_DAYS;
+    let mut log_encrypted = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--lang" => {
+                lang = Some(args.next().ok_or_else(|| anyhow::anyhow!("--lang requires a value"))?);
+            }
+            "--log-days" => log_days = parse_log_days(args.next())?,
+            "--log-encrypted" => log_encrypted = true,
+            _ => {}
+        }
+    }
+    chat_client::init_locale(lang.as_deref());
+
     // 运行 GUI
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -28,11 +57,44 @@ fn main() -> Result<()> {
     };
 
     eframe::run_native(
-        "聊天室",
+        &t!("app-title"),
         options,
-        Box::new(|cc| Ok(Box::new(ChatApp::new(cc)))),
+        Box::new(move |cc| Ok(Box::new(ChatApp::new(cc, log_days, log_encrypted)))),
     )
     .map_err(|e| anyhow::anyhow!("eframe error: {}", e))?;
 
     Ok(())
 }
+
+/// `export` 子命令：把本地聊天记录导出到文件或标准输出
+fn export(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut server = None;
+    let mut options = ExportOptions::default();
+    let mut output = None;
+    let mut lang = None;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow::anyhow!("{} requires a value", arg));
+        match arg.as_str() {
+            "--format" => options.format = value()?.parse().map_err(|e: String| anyhow::anyhow!(e))?,
+            "--since" => options.since = Some(parse_date_arg(&value()?, false)?),
+            "--until" => options.until = Some(parse_date_arg(&value()?, true)?),
+            "--output" => output = Some(value()?),
+            "--lang" => lang = Some(value()?),
+            _ => server = Some(arg),
+        }
+    }
+    let server = server.ok_or_else(|| anyhow::anyhow!("usage: chat-client export SERVER [--format markdown|html|jsonl] [--since DATE] [--until DATE] [--output FILE]"))?;
+    chat_client::init_locale(lang.as_deref());
+
+    let messages = chat_log::read(&server)?;
+    let content = chat_client::export::export(&messages, &server, &options);
+    match output {
+        Some(path) => std::fs::write(&path, content).map_err(|e| anyhow::anyhow!("cannot write {}: {}", path, e))?,
+        None => std::io::stdout().write_all(content.as_bytes())?,
+    }
+    Ok(())
+}
+
+fn parse_date_arg(text: &str, end_of_day: bool) -> Result<u64> {
+    parse_date(text, end_of_day).ok_or_else(|| anyhow::anyhow!("invalid date {}, expected YYYY-MM-DD", text))
+}
@@ -0,0 +1,181 @@
+//! 保存的连接配置
+//!
+//! 每个配置记录服务器地址、用户名、序列化格式和可选的聊天室密码（或邀请码），
+//! 保存在数据目录下的 `chat-client/profiles.json` 中。文件可能含有密码，因此只有当前用户可读。
+//! 可以把一个配置设为默认，客户端启动时自动用它连接。
+
+use std::fs;
+use std::path::PathBuf;
+
+use protocol::CodecKind;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::keys::{data_dir, write_private};
+
+/// 配置文件名
+const PROFILES_FILE: &str = "profiles.json";
+
+/// 一个连接配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub server_addr: String,
+    pub username: String,
+    /// 序列化格式（须与服务器一致）
+    #[serde(with = "codec_name")]
+    pub codec: CodecKind,
+    /// 保存的聊天室密码或邀请码
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_key: Option<String>,
+}
+
+/// 所有连接配置
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Profiles {
+    /// 启动时自动连接的配置名
+    #[serde(default)]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Profiles {
+    /// 从数据目录读取，文件不存在或损坏时返回空列表
+    pub fn load() -> Self {
+        let path = data_dir().map(|dir| dir.join(PROFILES_FILE));
+        let mut profiles = path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|content| match serde_json::from_str::<Profiles>(&content) {
+                Ok(profiles) => Some(profiles),
+                Err(e) => {
+                    warn!("Ignoring malformed profiles file: {}", e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        profiles.path = path;
+        profiles
+    }
+
+    /// 按名字查找配置
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    /// 启动时自动连接的配置
+    pub fn default_profile(&self) -> Option<&Profile> {
+        self.get(self.default.as_deref()?)
+    }
+
+    /// 保存配置，同名配置被替换
+    pub fn save_profile(&mut self, profile: Profile) {
+        match self.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+        self.save();
+    }
+
+    /// 删除配置
+    pub fn remove(&mut self, name: &str) {
+        self.profiles.retain(|p| p.name != name);
+        if self.default.as_deref() == Some(name) {
+            self.default = None;
+        }
+        self.save();
+    }
+
+    /// 设置或取消启动时自动连接的配置
+    pub fn set_default(&mut self, name: Option<&str>) {
+        self.default = name.map(str::to_string);
+        self.save();
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let content = serde_json::to_string_pretty(self).expect("profiles serialize to JSON");
+        if let Err(e) = write_private(path, content.as_bytes()) {
+            warn!("Failed to save profiles to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// 序列化格式按名字保存，如 `"bincode"`
+mod codec_name {
+    use protocol::{Codec, CodecKind};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(codec: &CodecKind, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(codec.name())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<CodecKind, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protocol::Codec;
+
+    use super::*;
+
+    fn profile(name: &str, codec: CodecKind) -> Profile {
+        Profile {
+            name: name.to_string(),
+            server_addr: "127.0.0.1:8080".to_string(),
+            username: "alice".to_string(),
+            codec,
+            access_key: None,
+        }
+    }
+
+    #[test]
+    fn test_serde_codec_name() {
+        let mut profiles = Profiles::default();
+        for &codec in CodecKind::ALL {
+            profiles.save_profile(profile(codec.name(), codec));
+        }
+        profiles.profiles[0].access_key = Some("secret".to_string());
+        profiles.set_default(Some(CodecKind::Json.name()));
+
+        let json = serde_json::to_string(&profiles).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["profiles"][0]["codec"], CodecKind::ALL[0].name());
+        assert_eq!(value["profiles"][0]["access_key"], "secret");
+        assert!(value["profiles"][1].get("access_key").is_none());
+
+        let loaded: Profiles = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.profiles, profiles.profiles);
+        assert_eq!(loaded.default_profile().unwrap().codec, CodecKind::Json);
+
+        let unknown = r#"{"profiles":[{"name":"x","server_addr":"a","username":"u","codec":"xml"}]}"#;
+        assert!(serde_json::from_str::<Profiles>(unknown).is_err());
+        // 缺少的字段使用默认值
+        let empty: Profiles = serde_json::from_str("{}").unwrap();
+        assert!(empty.profiles.is_empty() && empty.default.is_none());
+    }
+
+    #[test]
+    fn test_remove_clears_default() {
+        let mut profiles = Profiles::default();
+        profiles.save_profile(profile("home", CodecKind::Bincode));
+        profiles.save_profile(profile("work", CodecKind::Json));
+        profiles.set_default(Some("work"));
+
+        profiles.remove("home");
+        assert_eq!(profiles.default.as_deref(), Some("work"));
+        profiles.remove("work");
+        assert_eq!(profiles.default, None);
+        assert!(profiles.default_profile().is_none());
+        assert!(profiles.profiles.is_empty());
+    }
+}
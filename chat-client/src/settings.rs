@@ -0,0 +1,70 @@
+//! 客户端设置
+//!
+//! 保存在数据目录下的 `chat-client/settings.json` 中，对所有服务器生效。
+
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::keys::data_dir;
+
+/// 设置文件名
+const SETTINGS_FILE: &str = "settings.json";
+
+/// 客户端设置
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Settings {
+    /// 是否发送已读回执，关闭后其他用户看不到自己读到了哪里
+    #[serde(default = "default_true")]
+    pub send_read_receipts: bool,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            send_read_receipts: true,
+            path: None,
+        }
+    }
+}
+
+impl Settings {
+    /// 从数据目录读取，文件不存在或损坏时使用默认设置
+    pub fn load() -> Self {
+        let path = data_dir().map(|dir| dir.join(SETTINGS_FILE));
+        let mut settings = path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|content| match serde_json::from_str::<Settings>(&content) {
+                Ok(settings) => Some(settings),
+                Err(e) => {
+                    warn!("Ignoring malformed settings file: {}", e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        settings.path = path;
+        settings
+    }
+
+    /// 写回设置文件，失败时只记录日志
+    pub fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let content = serde_json::to_string_pretty(self).expect("settings serialize to JSON");
+        if let Err(e) = fs::create_dir_all(path.parent().expect("settings path has a parent"))
+            .and_then(|()| fs::write(path, content))
+        {
+            warn!("Failed to save settings to {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
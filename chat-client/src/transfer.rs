@@ -0,0 +1,24 @@
+//! 文件传输状态
+
+pub use chat_client_core::TransferDirection;
+
+/// UI 显示的传输进度
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    pub transfer_id: u32,
+    pub name: String,
+    pub direction: TransferDirection,
+    pub transferred: u64,
+    pub total: u64,
+}
+
+impl Transfer {
+    /// 传输进度（0.0 ~ 1.0）
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.transferred as f32 / self.total as f32
+        }
+    }
+}
@@ -1,30 +1,503 @@
 //! egui 界面实现
 
+use std::path::PathBuf;
+
 use eframe::egui;
+use i18n::t;
+use protocol::{is_image_mime, Codec, Role};
+
+use chat_client::client::{role_text, Attachment, ChatClient, ConnectionState, MessageKind};
+use chat_client::export::{ExportFormat, ExportOptions};
+use chat_client::format::{format_date_time, format_size, format_timestamp, parse_date, truncate_preview};
+use chat_client::keys::KeyStatus;
+use chat_client::profiles::{Profile, Profiles};
+use chat_client::settings::Settings;
+use chat_client::transfer::TransferDirection;
+
+/// @提及 高亮背景色
+const MENTION_HIGHLIGHT: egui::Color32 = egui::Color32::from_rgb(70, 55, 25);
+
+/// 搜索结果跳转到的消息的背景色
+const SEARCH_HIGHLIGHT: egui::Color32 = egui::Color32::from_rgb(40, 60, 90);
 
-use crate::client::{ChatClient, ConnectionState};
+/// “未读”分隔线颜色
+const UNREAD_DIVIDER_COLOR: egui::Color32 = egui::Color32::from_rgb(230, 90, 90);
 
-/// 聊天室应用
+/// 私聊消息正文颜色
+const DIRECT_MESSAGE_COLOR: egui::Color32 = egui::Color32::from_rgb(200, 170, 255);
+
+/// 聊天室应用，每个标签页是一个独立的服务器会话
 pub struct ChatApp {
+    /// 标签页（至少一个）
+    tabs: Vec<Tab>,
+    /// 当前标签页
+    active: usize,
+    /// 新标签页使用的本地聊天记录保留天数
+    log_retention_days: u32,
+    /// 新标签页是否把加密私聊写入本地聊天记录
+    log_encrypted: bool,
+    /// 是否显示在线用户列表
+    show_users: bool,
+    /// 当前窗口标题（避免每帧重复发送视口命令）
+    window_title: String,
+    /// 已请求过用户注意的 @提及 数量
+    notified_mentions: usize,
+    /// 导出窗口（未打开时为 None）
+    export: Option<ExportForm>,
+    /// 保存的连接配置
+    profiles: Profiles,
+    /// 对所有标签页生效的设置
+    settings: Settings,
+}
+
+/// 一个标签页：一个服务器连接和它的界面状态
+struct Tab {
     client: ChatClient,
     /// 是否自动滚动到底部
     auto_scroll: bool,
-    /// 是否显示在线用户列表
-    show_users: bool,
+    /// 上一帧消息列表是否滚动到了底部
+    at_bottom: bool,
+    /// 在这条消息前画“未读”分隔线
+    unread_divider: Option<u64>,
+    /// 是否显示文件上传输入框
+    show_upload: bool,
+    /// 正在核对公钥指纹的用户
+    verify_user: Option<String>,
+    /// 是否显示搜索面板
+    show_search: bool,
+    /// 从搜索结果跳转到的消息（高亮显示）
+    highlighted: Option<u64>,
+    /// 下一帧需要滚动到的消息
+    scroll_to: Option<u64>,
+    /// 展开全文的搜索结果（不在本地消息历史中时）
+    expanded_hit: Option<u64>,
+    /// 配置名输入框内容（选择配置时填入其名字）
+    profile_name: String,
+    /// 保存配置时是否一并保存聊天室密码
+    remember_key: bool,
+}
+
+/// 导出窗口的输入
+#[derive(Default)]
+struct ExportForm {
+    format: ExportFormat,
+    /// 起止日期输入框内容（`YYYY-MM-DD`，为空表示不限）
+    since: String,
+    until: String,
+    include_system: bool,
 }
 
 impl ChatApp {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    /// `log_retention_days` 为本地聊天记录保留天数，0 表示不保存；`log_encrypted` 表示是否记录加密私聊
+    pub fn new(cc: &eframe::CreationContext<'_>, log_retention_days: u32, log_encrypted: bool) -> Self {
         // 加载中文字体
         setup_fonts(&cc.egui_ctx);
 
         // 设置深色主题
         cc.egui_ctx.set_visuals(egui::Visuals::dark());
 
+        // 图片预览
+        egui_extras::install_image_loaders(&cc.egui_ctx);
+
+        // 有默认配置时自动连接
+        let profiles = Profiles::load();
+        let settings = Settings::load();
+        let mut tab = Tab::new(log_retention_days, log_encrypted);
+        if let Some(profile) = profiles.default_profile() {
+            tab.apply_profile(profile);
+            tab.client.connect();
+        }
+
         Self {
-            client: ChatClient::new(),
-            auto_scroll: true,
+            tabs: vec![tab],
+            active: 0,
+            log_retention_days,
+            log_encrypted,
             show_users: true,
+            window_title: t!("app-title"),
+            notified_mentions: 0,
+            export: None,
+            profiles,
+            settings,
+        }
+    }
+
+    /// 根据所有标签页的未读消息和 @提及 更新窗口标题，有新 @提及 且窗口失焦时闪烁提醒
+    fn update_window_title(&mut self, ctx: &egui::Context) {
+        let unread: usize = self.tabs.iter().map(|tab| tab.client.unread_messages()).sum();
+        let mentions: usize = self.tabs.iter().map(|tab| tab.client.unread_mentions).sum();
+        let focused = ctx.input(|i| i.viewport().focused.unwrap_or(true));
+
+        if mentions > self.notified_mentions && !focused {
+            ctx.send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(
+                egui::UserAttentionType::Informational,
+            ));
+        }
+        self.notified_mentions = mentions;
+
+        let title = if mentions > 0 && !focused && ((ctx.input(|i| i.time) * 2.0) as u64).is_multiple_of(2) {
+            t!("title-mentioned", count = mentions)
+        } else if unread > 0 {
+            format!("({}) {}", unread, t!("app-title"))
+        } else {
+            t!("app-title")
+        };
+
+        if title != self.window_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+    }
+
+    /// 标签页栏：切换、新建和关闭标签页，显示每个会话的连接状态和未读消息数
+    fn show_tab_bar(&mut self, ctx: &egui::Context) {
+        let mut select = None;
+        let mut close = None;
+        let mut add = false;
+        egui::TopBottomPanel::top("tab_bar")
+            .frame(egui::Frame::new().fill(egui::Color32::from_rgb(22, 22, 30)).inner_margin(egui::vec2(8.0, 4.0)))
+            .show(ctx, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    for (index, tab) in self.tabs.iter().enumerate() {
+                        let client = &tab.client;
+                        let color = match client.state {
+                            ConnectionState::Disconnected => egui::Color32::GRAY,
+                            ConnectionState::Connecting => egui::Color32::YELLOW,
+                            ConnectionState::Connected { .. } => egui::Color32::GREEN,
+                        };
+                        ui.label(egui::RichText::new("●").color(color));
+                        let mut label = tab.title();
+                        let unread = client.unread_messages();
+                        if unread > 0 {
+                            label = format!("{} ({})", label, unread);
+                        }
+                        let mut text = egui::RichText::new(label);
+                        if client.unread_mentions > 0 {
+                            text = text.color(egui::Color32::from_rgb(255, 200, 80));
+                        }
+                        if ui.selectable_label(index == self.active, text).clicked() {
+                            select = Some(index);
+                        }
+                        if self.tabs.len() > 1 && ui.small_button("✖").on_hover_text(t!("tab-close-hover")).clicked() {
+                            close = Some(index);
+                        }
+                        ui.separator();
+                    }
+                    add = ui.small_button("➕").on_hover_text(t!("tab-new-hover")).clicked();
+                });
+            });
+
+        if let Some(index) = select {
+            // 离开标签页时去掉已读消息前的分隔线
+            self.tabs[self.active].unread_divider = None;
+            self.active = index;
+        }
+        if let Some(index) = close {
+            // 丢弃 ChatClient 即断开连接并结束其网络线程
+            self.tabs.remove(index);
+            if self.active > index || self.active == self.tabs.len() {
+                self.active -= 1;
+            }
+        }
+        if add {
+            self.tabs.push(Tab::new(self.log_retention_days, self.log_encrypted));
+            self.active = self.tabs.len() - 1;
+        }
+    }
+
+    /// 导出聊天记录窗口
+    fn show_export_window(&mut self, ctx: &egui::Context) {
+        let Some(form) = &mut self.export else {
+            return;
+        };
+
+        let mut open = true;
+        let mut options = None;
+        egui::Window::new(t!("export-heading"))
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    for format in ExportFormat::ALL {
+                        let label = match format {
+                            ExportFormat::Markdown => "Markdown",
+                            ExportFormat::Html => "HTML",
+                            ExportFormat::JsonLines => "JSON Lines",
+                        };
+                        ui.radio_value(&mut form.format, format, label);
+                    }
+                });
+                egui::Grid::new("export_range").num_columns(2).show(ui, |ui| {
+                    ui.label(t!("search-since"));
+                    ui.add(egui::TextEdit::singleline(&mut form.since).hint_text("YYYY-MM-DD").desired_width(100.0));
+                    ui.end_row();
+                    ui.label(t!("search-until"));
+                    ui.add(egui::TextEdit::singleline(&mut form.until).hint_text("YYYY-MM-DD").desired_width(100.0));
+                    ui.end_row();
+                });
+                ui.checkbox(&mut form.include_system, t!("export-include-system"));
+
+                // 空日期表示不限，非空但无法解析时提示格式
+                let date = |text: &str, end_of_day| match text.trim() {
+                    "" => Some(None),
+                    text => parse_date(text, end_of_day).map(Some),
+                };
+                let range = date(&form.since, false).zip(date(&form.until, true));
+                if range.is_none() {
+                    ui.label(egui::RichText::new(t!("search-invalid-date")).small().color(egui::Color32::from_rgb(255, 120, 120)));
+                }
+                ui.add_space(4.0);
+                if ui.add_enabled(range.is_some(), egui::Button::new(t!("export-button"))).clicked() {
+                    if let Some((since, until)) = range {
+                        options = Some(ExportOptions {
+                            format: form.format,
+                            since,
+                            until,
+                            include_system: form.include_system,
+                        });
+                    }
+                }
+            });
+
+        if let Some(options) = options {
+            self.tabs[self.active].client.export(&options);
+            self.export = None;
+        } else if !open {
+            self.export = None;
+        }
+    }
+}
+
+impl Tab {
+    fn new(log_retention_days: u32, log_encrypted: bool) -> Self {
+        let mut client = ChatClient::new();
+        client.log_retention_days = log_retention_days;
+        client.log_encrypted = log_encrypted;
+        Self {
+            client,
+            auto_scroll: true,
+            at_bottom: true,
+            unread_divider: None,
+            show_upload: false,
+            verify_user: None,
+            show_search: false,
+            highlighted: None,
+            scroll_to: None,
+            expanded_hit: None,
+            profile_name: String::new(),
+            remember_key: false,
+        }
+    }
+
+    /// 标签页标题：使用的配置名，没有时为服务器地址
+    fn title(&self) -> String {
+        if self.profile_name.trim().is_empty() {
+            self.client.server_addr.clone()
+        } else {
+            self.profile_name.trim().to_string()
+        }
+    }
+
+    /// 用保存的配置填写连接信息
+    fn apply_profile(&mut self, profile: &Profile) {
+        self.client.apply_profile(profile);
+        self.remember_key = profile.access_key.is_some();
+        self.profile_name = profile.name.clone();
+    }
+
+    /// 搜索面板
+    fn show_search_panel(&mut self, ctx: &egui::Context) {
+        egui::SidePanel::left("search_panel")
+            .resizable(true)
+            .default_width(260.0)
+            .min_width(180.0)
+            .frame(egui::Frame::new().fill(egui::Color32::from_rgb(25, 25, 35)).inner_margin(8.0))
+            .show(ctx, |ui| {
+                ui.heading(egui::RichText::new(t!("search-heading")).size(14.0));
+                ui.add_space(4.0);
+
+                let search = &mut self.client.search;
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut search.query)
+                        .hint_text(t!("search-hint"))
+                        .desired_width(f32::INFINITY),
+                );
+                let mut submit = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                egui::Grid::new("search_filters").num_columns(2).show(ui, |ui| {
+                    ui.label(t!("search-from"));
+                    ui.add(egui::TextEdit::singleline(&mut search.from_user).hint_text(t!("search-anyone")));
+                    ui.end_row();
+                    ui.label(t!("search-since"));
+                    ui.add(egui::TextEdit::singleline(&mut search.since).hint_text("YYYY-MM-DD"));
+                    ui.end_row();
+                    ui.label(t!("search-until"));
+                    ui.add(egui::TextEdit::singleline(&mut search.until).hint_text("YYYY-MM-DD"));
+                    ui.end_row();
+                });
+                ui.horizontal(|ui| {
+                    let can_search = !search.query.trim().is_empty();
+                    submit |= ui.add_enabled(can_search, egui::Button::new(t!("search-button"))).clicked();
+                    if search.pending {
+                        ui.spinner();
+                    }
+                });
+                if submit {
+                    self.expanded_hit = None;
+                    self.client.search();
+                }
+
+                let search = &self.client.search;
+                if let Some(err) = &search.error {
+                    ui.label(egui::RichText::new(format!("⚠ {}", err)).color(egui::Color32::from_rgb(255, 100, 100)));
+                } else if search.request_sent() && !search.pending && search.hits.is_empty() {
+                    ui.label(egui::RichText::new(t!("search-no-results")).color(egui::Color32::GRAY));
+                }
+                ui.separator();
+
+                let mut clicked = None;
+                let mut load_more = false;
+                egui::ScrollArea::vertical().auto_shrink([false; 2]).show(ui, |ui| {
+                    for hit in &search.hits {
+                        let header = format!("{} · {}", format_date_time(hit.timestamp), hit.username);
+                        ui.label(egui::RichText::new(header).size(11.0).color(username_color(&hit.username)));
+                        let text = if self.expanded_hit == Some(hit.message_id) {
+                            hit.content.clone()
+                        } else {
+                            truncate_preview(&hit.content)
+                        };
+                        let text = if hit.action { format!("* {} {}", hit.username, text) } else { text };
+                        let label = egui::Label::new(egui::RichText::new(text).color(egui::Color32::from_rgb(220, 220, 230)))
+                            .wrap()
+                            .sense(egui::Sense::click());
+                        if ui.add(label).on_hover_text(t!("search-jump-hover")).clicked() {
+                            clicked = Some(hit.message_id);
+                        }
+                        if self.expanded_hit == Some(hit.message_id) {
+                            ui.label(egui::RichText::new(t!("search-not-loaded")).small().color(egui::Color32::GRAY));
+                        }
+                        ui.add_space(6.0);
+                    }
+                    if search.has_more() && ui.add_enabled(!search.pending, egui::Button::new(t!("search-more"))).clicked() {
+                        load_more = true;
+                    }
+                });
+
+                if let Some(id) = clicked {
+                    // 在本地历史中时跳转到该消息，否则就地展开全文
+                    if self.client.find_message(id).is_some() {
+                        self.auto_scroll = false;
+                        self.highlighted = Some(id);
+                        self.scroll_to = Some(id);
+                        self.expanded_hit = None;
+                    } else {
+                        self.expanded_hit = Some(id);
+                    }
+                }
+                if load_more {
+                    self.client.search_more();
+                }
+            });
+    }
+
+    /// 登录界面的连接配置栏：选择、保存、删除配置和设置自动连接
+    fn show_profile_bar(&mut self, ui: &mut egui::Ui, profiles: &mut Profiles) {
+        ui.horizontal(|ui| {
+            ui.label(t!("login-profile"));
+            let mut selected = None;
+            egui::ComboBox::from_id_salt("profile")
+                .selected_text(if profiles.get(&self.profile_name).is_some() {
+                    self.profile_name.clone()
+                } else {
+                    t!("profile-none")
+                })
+                .width(120.0)
+                .show_ui(ui, |ui| {
+                    for profile in &profiles.profiles {
+                        let label = format!("{} ({}@{})", profile.name, profile.username, profile.server_addr);
+                        if ui.selectable_label(profile.name == self.profile_name, label).clicked() {
+                            selected = Some(profile.clone());
+                        }
+                    }
+                });
+            if let Some(profile) = selected {
+                self.apply_profile(&profile);
+            }
+
+            ui.add(
+                egui::TextEdit::singleline(&mut self.profile_name)
+                    .desired_width(100.0)
+                    .hint_text(t!("profile-name-hint")),
+            );
+            let name = self.profile_name.trim().to_string();
+            if ui
+                .add_enabled(!name.is_empty(), egui::Button::new(t!("profile-save")))
+                .on_hover_text(t!("profile-save-hover"))
+                .clicked()
+            {
+                profiles.save_profile(self.client.to_profile(&name, self.remember_key));
+                self.profile_name = name.clone();
+            }
+            ui.checkbox(&mut self.remember_key, t!("profile-remember-key"));
+
+            if profiles.get(&name).is_some() {
+                if ui.button(t!("profile-delete")).clicked() {
+                    profiles.remove(&name);
+                }
+                let mut auto_connect = profiles.default.as_deref() == Some(name.as_str());
+                if ui.checkbox(&mut auto_connect, t!("profile-auto-connect")).changed() {
+                    profiles.set_default(auto_connect.then_some(name.as_str()));
+                }
+            }
+        });
+    }
+
+    /// 公钥指纹核对窗口
+    fn show_verify_window(&mut self, ctx: &egui::Context) {
+        let Some(username) = self.verify_user.clone() else {
+            return;
+        };
+        let Some(theirs) = self.client.fingerprint_of(&username) else {
+            // 用户已离线或没有公钥
+            self.verify_user = None;
+            return;
+        };
+        let status = self.client.key_status(&username);
+
+        let mut open = true;
+        let mut verified = false;
+        egui::Window::new(t!("verify-title", user = username.as_str()))
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(t!("verify-hint", user = username.as_str()));
+                ui.add_space(6.0);
+                if let Some(own) = self.client.own_fingerprint() {
+                    ui.label(egui::RichText::new(t!("verify-own")).small().color(egui::Color32::GRAY));
+                    ui.label(egui::RichText::new(own).monospace());
+                }
+                ui.label(egui::RichText::new(t!("verify-theirs", user = username.as_str())).small().color(egui::Color32::GRAY));
+                ui.label(egui::RichText::new(theirs).monospace().strong());
+                ui.add_space(6.0);
+                match status {
+                    Some(KeyStatus::Verified) => {
+                        ui.label(egui::RichText::new(t!("key-verified")).color(egui::Color32::GREEN));
+                    }
+                    status => {
+                        if status == Some(KeyStatus::Changed) {
+                            ui.label(egui::RichText::new(t!("key-changed", user = username.as_str())).color(egui::Color32::from_rgb(255, 180, 60)));
+                        }
+                        verified = ui.button(t!("verify-button")).clicked();
+                    }
+                }
+            });
+
+        if verified {
+            self.client.verify_key(&username);
+        }
+        if !open {
+            self.verify_user = None;
         }
     }
 }
@@ -94,8 +567,11 @@ fn setup_fonts(ctx: &egui::Context) {
 
 impl eframe::App for ChatApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // 轮询网络事件，只在有新事件时请求重绘
-        let has_events = self.client.poll_events();
+        // 轮询所有标签页的网络事件，只在有新事件时请求重绘
+        let mut has_events = false;
+        for tab in &mut self.tabs {
+            has_events |= tab.client.poll_events();
+        }
         if has_events {
             ctx.request_repaint();
         } else {
@@ -103,33 +579,96 @@ impl eframe::App for ChatApp {
             ctx.request_repaint_after(std::time::Duration::from_millis(100));
         }
 
+        self.update_window_title(ctx);
+        self.show_export_window(ctx);
+        self.show_tab_bar(ctx);
+
+        // 窗口有焦点且当前标签页滚动到底部时，消息都已看到；否则在第一条未读消息前画分隔线。
+        // 已读回执设置对所有标签页生效
+        let focused = ctx.input(|i| i.viewport().focused.unwrap_or(true));
+        for (index, tab) in self.tabs.iter_mut().enumerate() {
+            tab.client.set_send_read_receipts(self.settings.send_read_receipts);
+            if index == self.active && focused && tab.at_bottom {
+                tab.client.mark_read();
+            } else if let Some(id) = tab.client.first_unread() {
+                tab.unread_divider = Some(id);
+            }
+        }
+        let tab = &mut self.tabs[self.active];
+
+        // 拖放文件到窗口即上传
+        let dropped: Vec<PathBuf> = ctx.input(|i| {
+            i.raw.dropped_files.iter().filter_map(|f| f.path.clone()).collect()
+        });
+        for path in dropped {
+            tab.client.upload_file(path);
+        }
+
         // 顶部面板：连接状态
         egui::TopBottomPanel::top("top_panel")
             .frame(egui::Frame::new().fill(egui::Color32::from_rgb(30, 30, 40)).inner_margin(8.0))
             .show(ctx, |ui| {
                 ui.horizontal(|ui| {
-                    ui.heading(egui::RichText::new("💬 聊天室").color(egui::Color32::WHITE));
+                    ui.heading(egui::RichText::new(t!("heading")).color(egui::Color32::WHITE));
                     ui.separator();
 
-                    match &self.client.state {
+                    match &tab.client.state {
                         ConnectionState::Disconnected => {
-                            ui.label(egui::RichText::new("● 未连接").color(egui::Color32::GRAY));
+                            ui.label(egui::RichText::new(t!("status-disconnected")).color(egui::Color32::GRAY));
                         }
                         ConnectionState::Connecting => {
                             ui.spinner();
-                            ui.label(egui::RichText::new("连接中...").color(egui::Color32::YELLOW));
+                            ui.label(egui::RichText::new(t!("status-connecting")).color(egui::Color32::YELLOW));
                         }
                         ConnectionState::Connected { username, .. } => {
-                            ui.label(egui::RichText::new("● 已连接").color(egui::Color32::GREEN));
+                            ui.label(egui::RichText::new(t!("status-connected")).color(egui::Color32::GREEN));
                             ui.separator();
                             ui.label(egui::RichText::new(format!("👤 {}", username)).color(egui::Color32::WHITE));
+
+                            // 聊天室话题（过长时截断，悬停显示全文）
+                            if let Some(topic) = &tab.client.topic {
+                                ui.separator();
+                                ui.label(
+                                    egui::RichText::new(format!("📌 {}", truncate_preview(topic)))
+                                        .color(egui::Color32::from_rgb(200, 200, 140)),
+                                )
+                                .on_hover_text(topic);
+                            }
                         }
                     }
 
                     // 右侧工具栏
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                        if self.client.is_connected() {
-                            ui.toggle_value(&mut self.show_users, "👥 用户列表");
+                        ui.menu_button("☰", |ui| {
+                            if ui.button(t!("menu-export")).clicked() {
+                                self.export.get_or_insert_with(ExportForm::default);
+                                ui.close();
+                            }
+                            ui.separator();
+                            if ui
+                                .checkbox(&mut self.settings.send_read_receipts, t!("menu-read-receipts"))
+                                .on_hover_text(t!("menu-read-receipts-hover"))
+                                .changed()
+                            {
+                                self.settings.save();
+                            }
+                        });
+
+                        if tab.client.is_connected() {
+                            ui.toggle_value(&mut self.show_users, t!("toggle-users"));
+                            ui.toggle_value(&mut tab.show_search, "🔍").on_hover_text(t!("toggle-search"));
+                        }
+
+                        // @提及 计数，点击清除
+                        if tab.client.unread_mentions > 0 {
+                            let badge = egui::Button::new(
+                                egui::RichText::new(format!("🔔 {}", tab.client.unread_mentions))
+                                    .color(egui::Color32::BLACK),
+                            )
+                            .fill(egui::Color32::from_rgb(255, 200, 80));
+                            if ui.add(badge).on_hover_text(t!("mentions-hover")).clicked() {
+                                tab.client.clear_mentions();
+                            }
                         }
                     });
                 });
@@ -139,71 +678,167 @@ impl eframe::App for ChatApp {
         egui::TopBottomPanel::bottom("bottom_panel")
             .frame(egui::Frame::new().fill(egui::Color32::from_rgb(35, 35, 45)).inner_margin(8.0))
             .show(ctx, |ui| {
-                if self.client.is_connected() {
+                if tab.client.is_connected() {
+                    // 文件传输进度
+                    let mut cancel_transfer = None;
+                    for transfer in &tab.client.transfers {
+                        ui.horizontal(|ui| {
+                            let icon = match transfer.direction {
+                                TransferDirection::Upload => "⬆",
+                                TransferDirection::Download => "⬇",
+                            };
+                            ui.label(format!("{} {}", icon, transfer.name));
+                            ui.add(
+                                egui::ProgressBar::new(transfer.progress())
+                                    .desired_width(200.0)
+                                    .text(format!("{} / {}", format_size(transfer.transferred), format_size(transfer.total))),
+                            );
+                            if ui.small_button("✖").on_hover_text(t!("transfer-cancel-hover")).clicked() {
+                                cancel_transfer = Some(transfer.transfer_id);
+                            }
+                        });
+                    }
+                    if let Some(transfer_id) = cancel_transfer {
+                        tab.client.cancel_transfer(transfer_id);
+                    }
+
+                    // 文件上传输入框
+                    if tab.show_upload {
+                        ui.horizontal(|ui| {
+                            ui.label(t!("upload-label"));
+                            ui.add(
+                                egui::TextEdit::singleline(&mut tab.client.upload_path)
+                                    .hint_text(t!("upload-hint"))
+                                    .desired_width(ui.available_width() - 80.0),
+                            );
+                            let can_upload = !tab.client.upload_path.trim().is_empty();
+                            if ui
+                                .add_enabled(can_upload, egui::Button::new(t!("upload-button")).min_size(egui::vec2(60.0, 24.0)))
+                                .clicked()
+                            {
+                                let path = PathBuf::from(tab.client.upload_path.trim());
+                                tab.client.upload_path.clear();
+                                tab.client.upload_file(path);
+                                tab.show_upload = false;
+                            }
+                        });
+                    }
+
+                    // 回复提示条
+                    if let Some(reply_id) = tab.client.reply_to {
+                        let preview = match tab.client.find_message(reply_id) {
+                            Some(parent) => t!(
+                                "reply-preview",
+                                user = parent.username.as_str(),
+                                preview = truncate_preview(&parent.content)
+                            ),
+                            None => t!("reply-unknown"),
+                        };
+                        let mut cancel = false;
+                        ui.horizontal(|ui| {
+                            ui.label(egui::RichText::new(preview).size(12.0).color(egui::Color32::from_rgb(150, 150, 160)));
+                            if ui.small_button("✖").on_hover_text(t!("reply-cancel-hover")).clicked() {
+                                cancel = true;
+                            }
+                        });
+                        if cancel {
+                            tab.client.cancel_reply();
+                        }
+                    }
+
                     ui.horizontal(|ui| {
                         let response = ui.add(
-                            egui::TextEdit::singleline(&mut self.client.input_text)
-                                .hint_text("输入消息，按 Enter 发送...")
-                                .desired_width(ui.available_width() - 80.0)
+                            egui::TextEdit::singleline(&mut tab.client.input_text)
+                                .hint_text(t!("input-hint"))
+                                .desired_width(ui.available_width() - 120.0)
                                 .frame(true),
                         );
 
                         // 按 Enter 发送
                         if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
-                            self.client.send_message();
+                            tab.client.send_message();
+                            tab.unread_divider = None;
                             response.request_focus();
                         }
 
-                        if ui.add(egui::Button::new("发送").min_size(egui::vec2(60.0, 24.0))).clicked() {
-                            self.client.send_message();
+                        if ui.add(egui::Button::new(t!("send-button")).min_size(egui::vec2(60.0, 24.0))).clicked() {
+                            tab.client.send_message();
+                            tab.unread_divider = None;
                         }
+
+                        ui.toggle_value(&mut tab.show_upload, "📎").on_hover_text(t!("upload-hover"));
                     });
                 } else {
                     // 登录界面
                     ui.vertical(|ui| {
+                        tab.show_profile_bar(ui, &mut self.profiles);
+                        ui.add_space(4.0);
                         ui.horizontal(|ui| {
-                            ui.label("服务器:");
+                            ui.label(t!("login-server"));
                             ui.add(
-                                egui::TextEdit::singleline(&mut self.client.server_addr)
+                                egui::TextEdit::singleline(&mut tab.client.server_addr)
                                     .desired_width(180.0),
                             );
 
                             ui.add_space(16.0);
 
-                            ui.label("用户名:");
+                            ui.label(t!("login-username"));
                             let username_response = ui.add(
-                                egui::TextEdit::singleline(&mut self.client.username)
+                                egui::TextEdit::singleline(&mut tab.client.username)
                                     .desired_width(120.0)
-                                    .hint_text("字母/数字/下划线"),
+                                    .hint_text(t!("login-username-hint")),
+                            );
+
+                            ui.add_space(8.0);
+
+                            ui.label(t!("login-access-key"));
+                            let key_response = ui.add(
+                                egui::TextEdit::singleline(&mut tab.client.access_key)
+                                    .password(true)
+                                    .desired_width(100.0)
+                                    .hint_text(t!("login-access-key-hint")),
                             );
 
                             ui.add_space(8.0);
 
-                            let can_connect = !self.client.username.is_empty()
-                                && !self.client.server_addr.is_empty()
-                                && matches!(self.client.state, ConnectionState::Disconnected);
+                            egui::ComboBox::from_id_salt("codec")
+                                .selected_text(tab.client.codec.name())
+                                .width(80.0)
+                                .show_ui(ui, |ui| {
+                                    for codec in protocol::CodecKind::ALL {
+                                        ui.selectable_value(&mut tab.client.codec, *codec, codec.name());
+                                    }
+                                })
+                                .response
+                                .on_hover_text(t!("login-codec-hover"));
+
+                            ui.add_space(8.0);
+
+                            let can_connect = !tab.client.username.is_empty()
+                                && !tab.client.server_addr.is_empty()
+                                && matches!(tab.client.state, ConnectionState::Disconnected);
 
                             // 按 Enter 连接
-                            if username_response.lost_focus()
+                            if (username_response.lost_focus() || key_response.lost_focus())
                                 && ui.input(|i| i.key_pressed(egui::Key::Enter))
                                 && can_connect
                             {
-                                self.client.connect();
+                                tab.client.connect();
                             }
 
                             if ui
-                                .add_enabled(can_connect, egui::Button::new("🔗 连接").min_size(egui::vec2(70.0, 24.0)))
+                                .add_enabled(can_connect, egui::Button::new(t!("connect-button")).min_size(egui::vec2(70.0, 24.0)))
                                 .clicked()
                             {
-                                self.client.connect();
+                                tab.client.connect();
                             }
 
-                            if matches!(self.client.state, ConnectionState::Connecting) {
+                            if matches!(tab.client.state, ConnectionState::Connecting) {
                                 ui.spinner();
                             }
                         });
 
-                        if let Some(err) = &self.client.error_message {
+                        if let Some(err) = &tab.client.error_message {
                             ui.add_space(4.0);
                             ui.label(egui::RichText::new(format!("⚠ {}", err)).color(egui::Color32::from_rgb(255, 100, 100)));
                         }
@@ -212,42 +847,85 @@ impl eframe::App for ChatApp {
             });
 
         // 右侧面板：在线用户列表
-        if self.client.is_connected() && self.show_users {
+        if tab.client.is_connected() && self.show_users {
             egui::SidePanel::right("users_panel")
                 .resizable(true)
                 .default_width(150.0)
                 .min_width(100.0)
                 .frame(egui::Frame::new().fill(egui::Color32::from_rgb(25, 25, 35)).inner_margin(8.0))
                 .show(ctx, |ui| {
-                    ui.heading(egui::RichText::new("在线用户").size(14.0));
-                    ui.label(egui::RichText::new(format!("{} 人在线", self.client.online_users.len())).small().color(egui::Color32::GRAY));
+                    ui.heading(egui::RichText::new(t!("users-heading")).size(14.0));
+                    ui.label(egui::RichText::new(t!("users-online", count = tab.client.online_users.len())).small().color(egui::Color32::GRAY));
                     ui.separator();
 
                     egui::ScrollArea::vertical().show(ui, |ui| {
-                        for user in &self.client.online_users {
-                            let is_self = self.client.username == *user;
+                        for user in &tab.client.online_users {
+                            let is_self = tab.client.username == user.username;
                             let text = if is_self {
-                                egui::RichText::new(format!("👤 {} (我)", user)).color(egui::Color32::from_rgb(100, 200, 255))
+                                egui::RichText::new(t!("user-self", user = user.username.as_str())).color(egui::Color32::from_rgb(100, 200, 255))
                             } else {
-                                egui::RichText::new(format!("👤 {}", user)).color(username_color(user))
+                                egui::RichText::new(format!("👤 {}", user.username)).color(username_color(&user.username))
                             };
-                            ui.label(text);
+                            ui.horizontal(|ui| {
+                                // 点击其他用户的名字核对公钥指纹
+                                if is_self || user.public_key.is_none() {
+                                    ui.label(text);
+                                } else if ui.add(egui::Label::new(text).sense(egui::Sense::click())).on_hover_text(t!("verify-hover")).clicked() {
+                                    tab.verify_user = Some(user.username.clone());
+                                }
+                                // 普通成员不显示徽章
+                                if let Some(color) = role_badge_color(user.role) {
+                                    ui.label(egui::RichText::new(role_text(user.role)).small().color(color));
+                                }
+                                if !is_self {
+                                    match tab.client.key_status(&user.username) {
+                                        Some(KeyStatus::Verified) => {
+                                            ui.label(egui::RichText::new("✔").color(egui::Color32::GREEN)).on_hover_text(t!("key-verified"));
+                                        }
+                                        Some(KeyStatus::Changed) => {
+                                            ui.label(egui::RichText::new("⚠").color(egui::Color32::from_rgb(255, 180, 60)))
+                                                .on_hover_text(t!("key-changed", user = user.username.as_str()));
+                                        }
+                                        Some(KeyStatus::Unverified) => {
+                                            ui.label(egui::RichText::new("🔑").color(egui::Color32::GRAY)).on_hover_text(t!("key-unverified"));
+                                        }
+                                        None => {}
+                                    }
+                                }
+                            });
                         }
                     });
                 });
         }
 
+        tab.show_verify_window(ctx);
+
+        // 左侧面板：搜索
+        if tab.client.is_connected() && tab.show_search {
+            tab.show_search_panel(ctx);
+        }
+
         // 中间区域：消息列表
         egui::CentralPanel::default()
             .frame(egui::Frame::new().fill(egui::Color32::from_rgb(20, 20, 28)).inner_margin(8.0))
             .show(ctx, |ui| {
                 // 断开按钮和选项
-                if self.client.is_connected() {
+                if tab.client.is_connected() {
                     ui.horizontal(|ui| {
-                        if ui.add(egui::Button::new("🔌 断开连接").fill(egui::Color32::from_rgb(150, 50, 50))).clicked() {
-                            self.client.disconnect();
+                        if ui.add(egui::Button::new(t!("disconnect-button")).fill(egui::Color32::from_rgb(150, 50, 50))).clicked() {
+                            tab.client.disconnect();
+                        }
+                        ui.checkbox(&mut tab.auto_scroll, t!("auto-scroll"));
+
+                        // 跳到第一条未读消息
+                        if let Some(first) = tab.client.first_unread() {
+                            let count = tab.client.unread_messages();
+                            if ui.button(t!("jump-unread", count = count)).clicked() {
+                                tab.auto_scroll = false;
+                                tab.unread_divider = Some(first);
+                                tab.scroll_to = Some(first);
+                            }
                         }
-                        ui.checkbox(&mut self.auto_scroll, "自动滚动");
                     });
                     ui.add_space(4.0);
                     ui.separator();
@@ -255,11 +933,20 @@ impl eframe::App for ChatApp {
                 }
 
                 // 消息滚动区域
-                egui::ScrollArea::vertical()
+                let output = egui::ScrollArea::vertical()
                     .auto_shrink([false; 2])
-                    .stick_to_bottom(self.auto_scroll)
+                    .stick_to_bottom(tab.auto_scroll)
                     .show(ui, |ui| {
-                        for msg in &self.client.messages {
+                        let mut reply_clicked = None;
+                        let mut save_clicked: Option<Attachment> = None;
+                        let by_id = tab.client.messages_by_id();
+                        for msg in &tab.client.messages {
+                            if msg.id.is_some() && msg.id == tab.unread_divider {
+                                ui.horizontal(|ui| {
+                                    ui.label(egui::RichText::new(t!("unread-divider")).size(11.0).color(UNREAD_DIVIDER_COLOR));
+                                    ui.add(egui::Separator::default().horizontal().shrink(0.0));
+                                });
+                            }
                             if msg.is_system {
                                 // 系统消息：居中显示
                                 ui.horizontal(|ui| {
@@ -278,54 +965,136 @@ impl eframe::App for ChatApp {
                                         });
                                 });
                             } else {
-                                // 用户消息
-                                ui.horizontal(|ui| {
-                                    // 时间戳
-                                    let time = format_timestamp(msg.timestamp);
-                                    ui.label(
-                                        egui::RichText::new(format!("[{}]", time))
-                                            .size(11.0)
-                                            .color(egui::Color32::from_rgb(100, 100, 110)),
-                                    );
-
-                                    // 用户名
-                                    ui.label(
-                                        egui::RichText::new(format!("{}:", &msg.username))
-                                            .strong()
-                                            .color(username_color(&msg.username)),
-                                    );
-
-                                    // 消息内容
-                                    ui.label(egui::RichText::new(&msg.content).color(egui::Color32::from_rgb(220, 220, 230)));
+                                // 被回复消息的引用预览
+                                if let Some(parent_id) = msg.reply_to {
+                                    let quote = match by_id.get(&parent_id) {
+                                        Some(parent) => format!("┃ {}: {}", parent.username, truncate_preview(&parent.content)),
+                                        None => t!("quote-missing"),
+                                    };
+                                    ui.horizontal(|ui| {
+                                        ui.add_space(20.0);
+                                        ui.label(
+                                            egui::RichText::new(quote)
+                                                .size(11.0)
+                                                .color(egui::Color32::from_rgb(120, 120, 135)),
+                                        );
+                                    });
+                                }
+
+                                // 用户消息（搜索跳转的目标和 @提及 本地用户时高亮）
+                                let fill = if msg.id.is_some() && msg.id == tab.highlighted {
+                                    SEARCH_HIGHLIGHT
+                                } else if msg.mentions_me {
+                                    MENTION_HIGHLIGHT
+                                } else {
+                                    egui::Color32::TRANSPARENT
+                                };
+                                let frame = egui::Frame::new().fill(fill).corner_radius(4.0).show(ui, |ui| {
+                                    ui.horizontal(|ui| {
+                                        // 时间戳
+                                        let time = format_timestamp(msg.timestamp);
+                                        ui.label(
+                                            egui::RichText::new(format!("[{}]", time))
+                                                .size(11.0)
+                                                .color(egui::Color32::from_rgb(100, 100, 110)),
+                                        );
+
+                                        // 用户名（动作消息以 * 开头，私聊显示收件人）
+                                        let name = match &msg.kind {
+                                            MessageKind::Chat => format!("{}:", msg.username),
+                                            MessageKind::Action => format!("* {}", msg.username),
+                                            MessageKind::Direct { to, encrypted, offline } => {
+                                                let icon = if *encrypted { "🔒" } else { "✉" };
+                                                let offline = if *offline { format!(" ({})", t!("direct-offline")) } else { String::new() };
+                                                format!("{} {} → {}{}:", icon, msg.username, to, offline)
+                                            }
+                                        };
+                                        ui.label(egui::RichText::new(name).strong().color(username_color(&msg.username)));
+
+                                        // 消息内容
+                                        let content = egui::RichText::new(&msg.content);
+                                        let content = match &msg.kind {
+                                            MessageKind::Chat => content.color(egui::Color32::from_rgb(220, 220, 230)),
+                                            MessageKind::Action => content.italics().color(egui::Color32::from_rgb(200, 200, 215)),
+                                            MessageKind::Direct { .. } => content.color(DIRECT_MESSAGE_COLOR),
+                                        };
+                                        ui.label(content);
+
+                                        // 附件大小和保存按钮
+                                        if let Some(attachment) = &msg.attachment {
+                                            ui.label(
+                                                egui::RichText::new(format_size(attachment.size))
+                                                    .size(11.0)
+                                                    .color(egui::Color32::from_rgb(100, 100, 110)),
+                                            );
+                                            if ui.small_button(t!("save-button")).clicked() {
+                                                save_clicked = Some(attachment.clone());
+                                            }
+                                        }
+
+                                        // 自己的消息显示已读人数，悬停列出名字
+                                        if msg.username == tab.client.username {
+                                            let seen_by = tab.client.seen_by(msg);
+                                            if !seen_by.is_empty() {
+                                                ui.label(
+                                                    egui::RichText::new(t!("seen-by-count", count = seen_by.len()))
+                                                        .size(11.0)
+                                                        .color(egui::Color32::from_rgb(100, 100, 110)),
+                                                )
+                                                .on_hover_text(t!("seen-by", users = seen_by.join(", ")));
+                                            }
+                                        }
+
+                                        // 悬停时显示回复按钮
+                                        if let Some(id) = msg.id {
+                                            if ui.ui_contains_pointer() && ui.small_button(t!("reply-button")).clicked() {
+                                                reply_clicked = Some(id);
+                                            }
+                                        }
+                                    });
+
+                                    // 图片内联预览
+                                    if let Some(attachment) = &msg.attachment {
+                                        if is_image_mime(&attachment.mime) {
+                                            if let Some(bytes) = tab.client.downloaded.get(&attachment.file_id) {
+                                                ui.add(
+                                                    egui::Image::from_bytes(
+                                                        format!("bytes://file/{}/{}", attachment.file_id, attachment.name),
+                                                        bytes.clone(),
+                                                    )
+                                                    .max_size(egui::vec2(320.0, 240.0)),
+                                                );
+                                            }
+                                        }
+                                    }
                                 });
+                                if msg.id.is_some() && msg.id == tab.scroll_to {
+                                    frame.response.scroll_to_me(Some(egui::Align::Center));
+                                    tab.scroll_to = None;
+                                }
                             }
                             ui.add_space(2.0);
                         }
+
+                        if let Some(id) = reply_clicked {
+                            tab.client.start_reply(id);
+                        }
+                        if let Some(attachment) = save_clicked {
+                            tab.client.save_attachment(&attachment);
+                        }
                     });
+                tab.at_bottom = output.state.offset.y + output.inner_rect.height() >= output.content_size.y - 4.0;
             });
     }
 }
 
-/// 格式化时间戳
-fn format_timestamp(timestamp: u64) -> String {
-    use chrono::{Local, TimeZone};
-
-    // 使用本地时区
-    match Local.timestamp_opt(timestamp as i64, 0) {
-        chrono::LocalResult::Single(dt) => {
-            let now = Local::now();
-            let duration = now.signed_duration_since(dt);
-
-            if duration.num_seconds() < 60 && duration.num_seconds() >= 0 {
-                return "刚刚".to_string();
-            }
-
-            dt.format("%H:%M:%S").to_string()
-        }
-        _ => {
-            // 回退到简单格式
-            format!("{}", timestamp)
-        }
+/// 角色徽章颜色，普通成员返回 None
+fn role_badge_color(role: Role) -> Option<egui::Color32> {
+    match role {
+        Role::Owner => Some(egui::Color32::from_rgb(255, 200, 80)),
+        Role::Operator => Some(egui::Color32::from_rgb(120, 220, 140)),
+        Role::Member => None,
+        Role::Guest => Some(egui::Color32::GRAY),
     }
 }
 
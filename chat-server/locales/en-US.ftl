@@ -0,0 +1,50 @@
+# 服务端发送给客户端的文本（英文）
+
+server-busy = Server is busy, please try again later
+server-shutting-down = Server is shutting down
+invalid-username = Invalid username: { $error }
+username-taken = Username is taken or too similar to an online user
+join-required = Please send a Join message first
+join-timeout = Join timed out
+already-joined = Already joined the chat
+password-required = This room requires a password
+invite-required = This room is invite-only, a valid invite code is required
+invalid-message = Invalid message: { $error }
+invalid-file = Invalid file: { $error }
+message-too-large = Message is too large once encoded, please shorten it
+reply-not-found = The message you replied to does not exist
+
+transfer-id-in-use = Transfer ID is already in use
+transfer-too-many = Too many transfers in progress, wait for one to finish
+transfer-quota-exceeded = File storage quota exceeded
+transfer-save-failed = Server could not save the file
+transfer-read-failed = Server could not read the file
+transfer-unknown = Unknown transfer
+transfer-chunk-out-of-order = Chunks received out of order
+transfer-checksum-mismatch = Chunk checksum mismatch
+transfer-size-mismatch = File size does not match the offer
+transfer-file-not-found = File not found
+
+unknown-command = Unknown command /{ $name }. Type /help to see the available commands
+command-usage = Usage: { $usage }
+user-not-found = { $user } is not online
+recipient-busy = { $user } is not receiving messages right now, your message was not delivered
+offline-queue-full = Too many messages are waiting for { $user }, try again after they return
+permission-denied = You do not have permission to do that
+kicked = You were kicked by { $by }
+invite-limit = Too many invites (at most { $max }), revoke some first
+invite-failed = Could not generate an invite code
+invite-not-found = No such invite: { $code }
+topic-too-long = Topic is too long (max { $max } bytes)
+search-unavailable = Message history is not enabled on this server
+search-failed = Search failed
+
+cmd-me = Describe an action, e.g. /me waves
+cmd-nick = Change your username
+cmd-who = List online users
+cmd-topic = Show the room topic, or set it
+cmd-msg = Send a private message
+cmd-kick = Disconnect a user with a lower role
+cmd-role = Change the role of a user with a lower role until they disconnect
+cmd-invite = Create a reusable invite code, list them, or revoke one
+cmd-help = Show the available commands
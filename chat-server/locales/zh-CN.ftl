@@ -0,0 +1,50 @@
+# 服务端发送给客户端的文本（简体中文）
+
+server-busy = 服务器繁忙，请稍后重试
+server-shutting-down = 服务器正在关闭
+invalid-username = 无效的用户名: { $error }
+username-taken = 用户名已被占用或与在线用户过于相似
+join-required = 请先发送 Join 消息
+join-timeout = 加入超时
+already-joined = 已经加入聊天室
+password-required = 此聊天室需要密码
+invite-required = 此聊天室仅限邀请，需要有效的邀请码
+invalid-message = 消息无效: { $error }
+invalid-file = 文件无效: { $error }
+message-too-large = 消息编码后过大，请缩短后重试
+reply-not-found = 回复的消息不存在
+
+transfer-id-in-use = 传输 ID 已被占用
+transfer-too-many = 同时进行的传输过多，请等待其他传输完成
+transfer-quota-exceeded = 文件存储配额不足
+transfer-save-failed = 服务器无法保存文件
+transfer-read-failed = 服务器无法读取文件
+transfer-unknown = 未知的传输
+transfer-chunk-out-of-order = 分块顺序错误
+transfer-checksum-mismatch = 分块校验失败
+transfer-size-mismatch = 文件大小与声明不符
+transfer-file-not-found = 文件不存在
+
+unknown-command = 未知命令 /{ $name }，输入 /help 查看可用命令
+command-usage = 用法: { $usage }
+user-not-found = { $user } 不在线
+recipient-busy = { $user } 暂时无法接收消息，消息未送达
+offline-queue-full = 等待 { $user } 接收的私聊过多，请在对方上线后再发送
+permission-denied = 你没有执行此操作的权限
+kicked = 你已被 { $by } 踢出
+invite-limit = 邀请码过多（最多 { $max } 个），请先撤销一些
+invite-failed = 无法生成邀请码
+invite-not-found = 邀请码不存在: { $code }
+topic-too-long = 话题过长（最多 { $max } 字节）
+search-unavailable = 此服务器未启用消息历史
+search-failed = 搜索失败
+
+cmd-me = 发送动作，例如 /me 挥手
+cmd-nick = 修改用户名
+cmd-who = 列出在线用户
+cmd-topic = 查看或设置聊天室话题
+cmd-msg = 发送私聊消息
+cmd-kick = 断开角色低于你的用户
+cmd-role = 修改角色低于你的用户的角色，断开连接后失效
+cmd-invite = 签发（可重复使用，直到撤销）、列出或撤销邀请码
+cmd-help = 显示可用命令
@@ -0,0 +1,156 @@
+//! 聊天室准入
+//!
+//! 根据 `RoomMode` 检查 Join 携带的密码或邀请码。邀请码在撤销前可重复使用，
+//! 最多同时存在 [`MAX_INVITES`] 个，只保存在内存中，服务器重启后失效。
+
+use std::collections::BTreeSet;
+
+use i18n::t;
+use protocol::{ErrorCode, Role};
+use tokio::sync::RwLock;
+
+use crate::config::RoomMode;
+
+/// 邀请码的随机字节数（编码为两倍长度的十六进制字符串）
+const INVITE_CODE_BYTES: usize = 8;
+
+/// 同时有效的邀请码数量上限，`/invite list` 的回复须能放进一帧
+pub(crate) const MAX_INVITES: usize = 100;
+
+/// 签发邀请码失败的原因
+#[derive(Debug)]
+pub(crate) enum InviteError {
+    /// 有效的邀请码已达上限
+    LimitReached,
+    /// 无法生成随机数
+    Random(getrandom::Error),
+}
+
+/// 准入检查与邀请码
+pub(crate) struct Access {
+    mode: RoomMode,
+    invites: RwLock<BTreeSet<String>>,
+}
+
+impl Access {
+    pub(crate) fn new(mode: RoomMode) -> Self {
+        Self {
+            mode,
+            invites: RwLock::new(BTreeSet::new()),
+        }
+    }
+
+    /// 检查能否加入，失败时返回错误码和说明
+    ///
+    /// `key_role` 是访问密钥作为角色密钥对应的角色。角色不低于成员的密钥已经证明了身份，
+    /// 持有者不需要密码或邀请码；访客密钥不能代替它们。
+    pub(crate) async fn check(&self, access_key: Option<&str>, key_role: Option<Role>) -> Result<(), (ErrorCode, String)> {
+        if key_role.is_some_and(|role| role >= Role::Member) {
+            return Ok(());
+        }
+        match &self.mode {
+            RoomMode::Public => Ok(()),
+            RoomMode::Password(password) => match access_key {
+                Some(key) if constant_time_eq(key.as_bytes(), password.as_bytes()) => Ok(()),
+                _ => Err((ErrorCode::PasswordRequired, t!("password-required"))),
+            },
+            RoomMode::InviteOnly => match access_key {
+                Some(code) if self.invites.read().await.contains(code) => Ok(()),
+                _ => Err((ErrorCode::InviteRequired, t!("invite-required"))),
+            },
+        }
+    }
+
+    /// 签发新的邀请码，有效的邀请码已达 [`MAX_INVITES`] 个时失败
+    pub(crate) async fn create_invite(&self) -> Result<String, InviteError> {
+        let mut invites = self.invites.write().await;
+        if invites.len() >= MAX_INVITES {
+            return Err(InviteError::LimitReached);
+        }
+        let mut bytes = [0u8; INVITE_CODE_BYTES];
+        getrandom::fill(&mut bytes).map_err(InviteError::Random)?;
+        let code: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        invites.insert(code.clone());
+        Ok(code)
+    }
+
+    /// 撤销邀请码，不存在时返回 false
+    pub(crate) async fn revoke_invite(&self, code: &str) -> bool {
+        self.invites.write().await.remove(code)
+    }
+
+    /// 有效的邀请码（按字典序）
+    pub(crate) async fn invites(&self) -> Vec<String> {
+        self.invites.read().await.iter().cloned().collect()
+    }
+}
+
+/// 比较密码，耗时与第一个不同字节的位置无关
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_public_room() {
+        let access = Access::new(RoomMode::Public);
+        assert!(access.check(None, None).await.is_ok());
+        assert!(access.check(Some("anything"), Some(Role::Guest)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_password() {
+        let access = Access::new(RoomMode::Password("secret".to_string()));
+        assert!(access.check(Some("secret"), None).await.is_ok());
+        for key in [None, Some(""), Some("Secret"), Some("secret ")] {
+            let (code, _) = access.check(key, None).await.unwrap_err();
+            assert_eq!(code, ErrorCode::PasswordRequired, "{:?}", key);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invites() {
+        let access = Access::new(RoomMode::InviteOnly);
+        let (code, _) = access.check(None, None).await.unwrap_err();
+        assert_eq!(code, ErrorCode::InviteRequired);
+
+        let invite = access.create_invite().await.unwrap();
+        assert_eq!(invite.len(), INVITE_CODE_BYTES * 2);
+        assert_eq!(access.invites().await, vec![invite.clone()]);
+        // 邀请码在撤销前可以反复使用
+        assert!(access.check(Some(&invite), None).await.is_ok());
+        assert!(access.check(Some(&invite), None).await.is_ok());
+
+        assert!(access.revoke_invite(&invite).await);
+        assert!(!access.revoke_invite(&invite).await);
+        assert!(access.check(Some(&invite), None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_role_key_bypass() {
+        for mode in [RoomMode::InviteOnly, RoomMode::Password("secret".to_string())] {
+            let access = Access::new(mode.clone());
+            // 访客密钥不能代替密码或邀请码
+            assert!(access.check(Some("guest-key"), Some(Role::Guest)).await.is_err(), "{:?}", mode);
+            for role in [Role::Member, Role::Operator, Role::Owner] {
+                assert!(access.check(Some("role-key"), Some(role)).await.is_ok(), "{:?} {:?}", mode, role);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invite_limit() {
+        let access = Access::new(RoomMode::InviteOnly);
+        for _ in 0..MAX_INVITES {
+            access.create_invite().await.unwrap();
+        }
+        assert!(matches!(access.create_invite().await, Err(InviteError::LimitReached)));
+
+        let code = access.invites().await.remove(0);
+        assert!(access.revoke_invite(&code).await);
+        assert!(access.create_invite().await.is_ok());
+    }
+}
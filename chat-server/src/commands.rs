@@ -0,0 +1,558 @@
+//! 斜杠命令
+//!
+//! 以 `/` 开头的聊天消息在服务端作为命令执行，不会广播；
+//! 以 `//` 开头的消息去掉一个斜杠后按普通消息发送。
+
+use i18n::t;
+use protocol::{
+    normalize_username, validate_username, CommandHelp, CommandReply, ErrorCode, Role,
+    ServerMessage, MAX_TOPIC_LEN,
+};
+use tracing::{debug, info, warn};
+
+use crate::access::{InviteError, MAX_INVITES};
+use crate::offline::QueueError;
+use crate::roles::{self, Permission};
+use crate::server::{unix_timestamp, BroadcastMsg, Broadcaster, Direct, SharedState, UserRef};
+
+/// 命令种类
+#[derive(Clone, Copy, Debug)]
+enum CommandKind {
+    Me,
+    Nick,
+    Who,
+    Topic,
+    Msg,
+    Kick,
+    Role,
+    Invite,
+    Help,
+}
+
+/// 注册的命令
+struct CommandSpec {
+    name: &'static str,
+    usage: &'static str,
+    /// 说明文本的消息 ID
+    description_id: &'static str,
+    /// 执行命令所需的权限（None 表示所有人可用）
+    permission: Option<Permission>,
+    kind: CommandKind,
+}
+
+/// 命令注册表（`/help` 按此顺序列出）
+const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "me",
+        usage: "/me <action>",
+        description_id: "cmd-me",
+        permission: Some(Permission::Chat),
+        kind: CommandKind::Me,
+    },
+    CommandSpec {
+        name: "nick",
+        usage: "/nick <name>",
+        description_id: "cmd-nick",
+        permission: None,
+        kind: CommandKind::Nick,
+    },
+    CommandSpec {
+        name: "who",
+        usage: "/who",
+        description_id: "cmd-who",
+        permission: None,
+        kind: CommandKind::Who,
+    },
+    CommandSpec {
+        name: "topic",
+        usage: "/topic [text]",
+        description_id: "cmd-topic",
+        permission: None,
+        kind: CommandKind::Topic,
+    },
+    CommandSpec {
+        name: "msg",
+        usage: "/msg <user> <message>",
+        description_id: "cmd-msg",
+        permission: Some(Permission::Chat),
+        kind: CommandKind::Msg,
+    },
+    CommandSpec {
+        name: "kick",
+        usage: "/kick <user>",
+        description_id: "cmd-kick",
+        permission: Some(Permission::Kick),
+        kind: CommandKind::Kick,
+    },
+    CommandSpec {
+        name: "role",
+        usage: "/role <user> <guest|member|operator|owner>",
+        description_id: "cmd-role",
+        permission: Some(Permission::AssignRoles),
+        kind: CommandKind::Role,
+    },
+    CommandSpec {
+        name: "invite",
+        usage: "/invite [list | revoke <code>]",
+        description_id: "cmd-invite",
+        permission: Some(Permission::Invite),
+        kind: CommandKind::Invite,
+    },
+    CommandSpec {
+        name: "help",
+        usage: "/help",
+        description_id: "cmd-help",
+        permission: None,
+        kind: CommandKind::Help,
+    },
+];
+
+/// 命令执行上下文
+pub(crate) struct CommandContext<'a> {
+    pub(crate) state: &'a SharedState,
+    pub(crate) broadcaster: &'a Broadcaster,
+    pub(crate) user_id: u32,
+    /// 连接当前的用户名，`/nick` 成功后更新
+    pub(crate) username: &'a mut String,
+}
+
+/// 命令执行失败，作为 `ServerMessage::Error` 回复给执行者
+#[derive(Debug)]
+pub(crate) struct CommandError {
+    code: ErrorCode,
+    message: String,
+}
+
+impl CommandError {
+    fn new(code: ErrorCode, message: String) -> Self {
+        Self { code, message }
+    }
+
+    fn usage(spec: &CommandSpec) -> Self {
+        Self::new(ErrorCode::InvalidCommand, t!("command-usage", usage = spec.usage))
+    }
+
+    fn permission_denied() -> Self {
+        Self::new(ErrorCode::PermissionDenied, t!("permission-denied"))
+    }
+
+    /// 消息编码后超过帧大小
+    fn too_large() -> Self {
+        Self::new(ErrorCode::MessageTooLong, t!("message-too-large"))
+    }
+}
+
+impl From<CommandError> for ServerMessage {
+    fn from(e: CommandError) -> Self {
+        ServerMessage::error(e.code, e.message)
+    }
+}
+
+/// 命令执行结果：需要依次回复给执行者的消息（广播类命令没有回复）
+pub(crate) type CommandResult = Result<Vec<ServerMessage>, CommandError>;
+
+/// 解析 `/name args`，返回 (命令名, 参数)；不是命令时返回 None
+pub(crate) fn parse(content: &str) -> Option<(&str, &str)> {
+    let rest = content.strip_prefix('/')?;
+    if rest.starts_with('/') {
+        return None;
+    }
+    Some(match rest.split_once(char::is_whitespace) {
+        Some((name, args)) => (name, args.trim()),
+        None => (rest, ""),
+    })
+}
+
+/// 去掉 `//` 转义中的第一个斜杠
+pub(crate) fn unescape(content: String) -> String {
+    match content.strip_prefix("//") {
+        Some(rest) => format!("/{}", rest),
+        None => content,
+    }
+}
+
+/// 执行命令
+pub(crate) async fn execute(ctx: &mut CommandContext<'_>, name: &str, args: &str) -> CommandResult {
+    let Some(spec) = COMMANDS.iter().find(|c| c.name.eq_ignore_ascii_case(name)) else {
+        return Err(CommandError::new(
+            ErrorCode::UnknownCommand,
+            t!("unknown-command", name = name),
+        ));
+    };
+    if let Some(permission) = spec.permission {
+        check_permission(ctx, permission).await?;
+    }
+    match spec.kind {
+        CommandKind::Me => me(ctx, spec, args),
+        CommandKind::Nick => nick(ctx, spec, args).await,
+        CommandKind::Who => who(ctx).await,
+        CommandKind::Topic => topic(ctx, args).await,
+        CommandKind::Msg => msg(ctx, spec, args).await,
+        CommandKind::Kick => kick(ctx, spec, args).await,
+        CommandKind::Role => role(ctx, spec, args).await,
+        CommandKind::Invite => invite(ctx, spec, args).await,
+        CommandKind::Help => Ok(vec![help()]),
+    }
+}
+
+/// 检查执行者是否拥有权限
+async fn check_permission(ctx: &CommandContext<'_>, permission: Permission) -> Result<(), CommandError> {
+    if roles::allows(ctx.state.user_role(ctx.user_id).await, permission) {
+        Ok(())
+    } else {
+        Err(CommandError::permission_denied())
+    }
+}
+
+/// 查找可由执行者管理的在线用户
+async fn find_manageable(ctx: &CommandContext<'_>, username: &str) -> Result<UserRef, CommandError> {
+    let Some(target) = ctx.state.find_user(username).await else {
+        return Err(CommandError::new(
+            ErrorCode::UserNotFound,
+            t!("user-not-found", user = username),
+        ));
+    };
+    let actor = ctx.state.user_role(ctx.user_id).await;
+    if target.id == ctx.user_id || !roles::can_manage(actor, target.role) {
+        return Err(CommandError::permission_denied());
+    }
+    Ok(target)
+}
+
+/// `/me <action>`：广播动作消息
+fn me(ctx: &mut CommandContext<'_>, spec: &CommandSpec, args: &str) -> CommandResult {
+    if args.is_empty() {
+        return Err(CommandError::usage(spec));
+    }
+    ctx.broadcaster
+        .try_send(BroadcastMsg::Action {
+            message_id: ctx.state.next_message_id(),
+            username: ctx.username.clone(),
+            content: args.to_string(),
+            timestamp: unix_timestamp(),
+        })
+        .map_err(|_| CommandError::too_large())?;
+    Ok(Vec::new())
+}
+
+/// `/nick <name>`：改名并广播
+async fn nick(ctx: &mut CommandContext<'_>, spec: &CommandSpec, args: &str) -> CommandResult {
+    if args.is_empty() || args.contains(char::is_whitespace) {
+        return Err(CommandError::usage(spec));
+    }
+    let new_username = normalize_username(args);
+    if let Err(e) = validate_username(&new_username) {
+        return Err(CommandError::new(
+            e.code(),
+            t!("invalid-username", error = e.to_string()),
+        ));
+    }
+    if new_username == *ctx.username {
+        return Ok(Vec::new());
+    }
+
+    let Some(old_username) = ctx.state.rename_user(ctx.user_id, new_username.clone()).await else {
+        return Err(CommandError::new(ErrorCode::UsernameTaken, t!("username-taken")));
+    };
+    info!("User {} is now known as {}", old_username, new_username);
+    *ctx.username = new_username.clone();
+    ctx.broadcaster.send(BroadcastMsg::UserRenamed {
+        old_username,
+        new_username,
+    });
+    Ok(Vec::new())
+}
+
+/// `/who`：在线用户列表
+async fn who(ctx: &mut CommandContext<'_>) -> CommandResult {
+    let mut users = ctx.state.get_online_usernames().await;
+    users.sort();
+    Ok(ctx.state.batches(users, |users, more| ServerMessage::CommandReply {
+        reply: CommandReply::Who { users, more },
+    }))
+}
+
+/// `/topic [text]`：不带参数时查看话题，否则设置并广播
+async fn topic(ctx: &mut CommandContext<'_>, args: &str) -> CommandResult {
+    if args.is_empty() {
+        return Ok(vec![ServerMessage::CommandReply {
+            reply: CommandReply::Topic {
+                topic: ctx.state.topic().await,
+            },
+        }]);
+    }
+    check_permission(ctx, Permission::SetTopic).await?;
+    if args.len() > MAX_TOPIC_LEN {
+        return Err(CommandError::new(
+            ErrorCode::InvalidCommand,
+            t!("topic-too-long", max = MAX_TOPIC_LEN),
+        ));
+    }
+    ctx.state.set_topic(args.to_string()).await;
+    ctx.broadcaster.send(BroadcastMsg::TopicChanged {
+        topic: args.to_string(),
+        username: ctx.username.clone(),
+    });
+    Ok(Vec::new())
+}
+
+/// `/msg <user> <message>`：私聊，发送者收到一份回显
+///
+/// 收件人离线时私聊排队，在其下次加入时投递（见 [`crate::offline`]）。
+async fn msg(ctx: &mut CommandContext<'_>, spec: &CommandSpec, args: &str) -> CommandResult {
+    let Some((to, content)) = args.split_once(char::is_whitespace) else {
+        return Err(CommandError::usage(spec));
+    };
+    let to = to.strip_prefix('@').unwrap_or(to);
+    let content = content.trim();
+    if content.is_empty() {
+        return Err(CommandError::usage(spec));
+    }
+    let Some(recipient) = ctx.state.find_user(to).await else {
+        return queue_offline(ctx, to, content).await;
+    };
+
+    let to = recipient.username;
+    let message = ServerMessage::DirectMessage {
+        message_id: ctx.state.next_message_id(),
+        from: ctx.username.clone(),
+        to: to.clone(),
+        content: content.to_string(),
+        timestamp: unix_timestamp(),
+    };
+    if !ctx.state.fits_frame(&message) {
+        return Err(CommandError::too_large());
+    }
+    // 发给自己时只回显一次；对方队列已满时告知发送者，不回显未送达的消息
+    if recipient.id != ctx.user_id && recipient.direct_tx.try_send(Direct::Message(message.clone())).is_err() {
+        warn!("Direct message queue for {} is full, dropping message", to);
+        return Err(CommandError::new(
+            ErrorCode::RecipientBusy,
+            t!("recipient-busy", user = to.as_str()),
+        ));
+    }
+    Ok(vec![message])
+}
+
+/// 为离线用户排队私聊，回显私聊并告知发送者已排队
+async fn queue_offline(ctx: &mut CommandContext<'_>, to: &str, content: &str) -> CommandResult {
+    let not_found = || CommandError::new(ErrorCode::UserNotFound, t!("user-not-found", user = to));
+    let Some(to) = ctx.state.offline.username(to).await else {
+        return Err(not_found());
+    };
+    let message_id = ctx.state.next_message_id();
+    let timestamp = unix_timestamp();
+    let echo = ServerMessage::DirectMessage {
+        message_id,
+        from: ctx.username.clone(),
+        to: to.clone(),
+        content: content.to_string(),
+        timestamp,
+    };
+    let queued = ServerMessage::OfflineDirect {
+        message_id,
+        from: ctx.username.clone(),
+        to: to.clone(),
+        content: content.to_string(),
+        timestamp,
+    };
+    if !ctx.state.fits_frame(&echo) || !ctx.state.fits_frame(&queued) {
+        return Err(CommandError::too_large());
+    }
+    match ctx.state.offline.queue(&to, queued).await {
+        Ok(()) => {}
+        Err(QueueError::Unknown) => return Err(not_found()),
+        Err(QueueError::Full) => {
+            return Err(CommandError::new(
+                ErrorCode::RecipientBusy,
+                t!("offline-queue-full", user = to.as_str()),
+            ));
+        }
+    }
+    debug!("User {} queued a direct message for offline user {}", ctx.username, to);
+    Ok(vec![
+        echo,
+        ServerMessage::CommandReply {
+            reply: CommandReply::OfflineQueued { to },
+        },
+    ])
+}
+
+/// `/kick <user>`：断开角色低于自己的用户
+async fn kick(ctx: &mut CommandContext<'_>, spec: &CommandSpec, args: &str) -> CommandResult {
+    if args.is_empty() || args.contains(char::is_whitespace) {
+        return Err(CommandError::usage(spec));
+    }
+    let target = find_manageable(ctx, args.strip_prefix('@').unwrap_or(args)).await?;
+    info!("User {} kicked {}", ctx.username, target.username);
+    // 队列已满时连接多半已失去响应，心跳超时会将其断开
+    let _ = target.direct_tx.try_send(Direct::Kick {
+        by: ctx.username.clone(),
+    });
+    Ok(Vec::new())
+}
+
+/// `/role <user> <role>`：修改角色低于自己的用户的角色，不能授予高于自己的角色
+async fn role(ctx: &mut CommandContext<'_>, spec: &CommandSpec, args: &str) -> CommandResult {
+    let Some((username, role)) = args.split_once(char::is_whitespace) else {
+        return Err(CommandError::usage(spec));
+    };
+    let Ok(role) = role.trim().parse::<Role>() else {
+        return Err(CommandError::usage(spec));
+    };
+    let target = find_manageable(ctx, username.strip_prefix('@').unwrap_or(username)).await?;
+    if role > ctx.state.user_role(ctx.user_id).await {
+        return Err(CommandError::permission_denied());
+    }
+    if role == target.role {
+        return Ok(Vec::new());
+    }
+
+    ctx.state.set_role(target.id, role).await;
+    info!("User {} set role of {} to {}", ctx.username, target.username, role);
+    ctx.broadcaster.send(BroadcastMsg::RoleChanged {
+        username: target.username,
+        role,
+        by: ctx.username.clone(),
+    });
+    Ok(Vec::new())
+}
+
+/// `/invite [list | revoke <code>]`：签发、列出或撤销邀请码
+async fn invite(ctx: &mut CommandContext<'_>, spec: &CommandSpec, args: &str) -> CommandResult {
+    let reply = match args.split_whitespace().collect::<Vec<_>>()[..] {
+        [] => match ctx.state.access.create_invite().await {
+            Ok(code) => {
+                info!("User {} created an invite", ctx.username);
+                CommandReply::InviteCreated { code }
+            }
+            Err(InviteError::LimitReached) => {
+                return Err(CommandError::new(
+                    ErrorCode::InvalidCommand,
+                    t!("invite-limit", max = MAX_INVITES),
+                ));
+            }
+            Err(InviteError::Random(e)) => {
+                warn!("Failed to generate invite code: {}", e);
+                return Err(CommandError::new(ErrorCode::Io, t!("invite-failed")));
+            }
+        },
+        ["list"] => {
+            let message = ServerMessage::CommandReply {
+                reply: CommandReply::Invites {
+                    codes: ctx.state.access.invites().await,
+                },
+            };
+            if !ctx.state.fits_frame(&message) {
+                return Err(CommandError::too_large());
+            }
+            return Ok(vec![message]);
+        }
+        ["revoke", code] => {
+            if !ctx.state.access.revoke_invite(code).await {
+                return Err(CommandError::new(
+                    ErrorCode::InvalidCommand,
+                    t!("invite-not-found", code = code),
+                ));
+            }
+            info!("User {} revoked an invite", ctx.username);
+            CommandReply::InviteRevoked { code: code.to_string() }
+        }
+        _ => return Err(CommandError::usage(spec)),
+    };
+    Ok(vec![ServerMessage::CommandReply { reply }])
+}
+
+/// `/help`：列出所有命令
+fn help() -> ServerMessage {
+    let commands = COMMANDS
+        .iter()
+        .map(|c| CommandHelp {
+            usage: c.usage.to_string(),
+            description: t!(c.description_id),
+        })
+        .collect();
+    ServerMessage::CommandReply {
+        reply: CommandReply::Help { commands },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use protocol::CodecKind;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::config::ServerConfig;
+    use crate::history::History;
+
+    /// 以指定角色加入，返回用户 ID 和私聊队列
+    async fn add_user(state: &SharedState, username: &str, role: Role, capacity: usize) -> (u32, mpsc::Receiver<Direct>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        let id = state.add_user(username.to_string(), role, None, tx).await.unwrap();
+        (id, rx)
+    }
+
+    /// 以 `username` 的身份执行命令
+    async fn run(state: &SharedState, user_id: u32, username: &str, command: &str) -> CommandResult {
+        let broadcaster = Broadcaster::new(CodecKind::default(), Arc::new(History::new(None)));
+        let mut username = username.to_string();
+        let mut ctx = CommandContext {
+            state,
+            broadcaster: &broadcaster,
+            user_id,
+            username: &mut username,
+        };
+        let (name, args) = parse(command).unwrap();
+        execute(&mut ctx, name, args).await
+    }
+
+    #[tokio::test]
+    async fn test_msg_to_busy_recipient() {
+        let state = SharedState::new(&ServerConfig::default());
+        let (alice, _alice_rx) = add_user(&state, "alice", Role::Member, 8).await;
+        let (_, mut bob_rx) = add_user(&state, "bob", Role::Member, 1).await;
+
+        let replies = run(&state, alice, "alice", "/msg bob hi").await.unwrap();
+        assert!(matches!(&replies[..], [ServerMessage::DirectMessage { content, .. }] if content == "hi"));
+
+        // 队列已满：不回显，告知发送者
+        let err = run(&state, alice, "alice", "/msg bob again").await.unwrap_err();
+        assert_eq!(err.code, ErrorCode::RecipientBusy);
+
+        assert!(matches!(bob_rx.recv().await, Some(Direct::Message(ServerMessage::DirectMessage { content, .. })) if content == "hi"));
+        assert!(bob_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_kick_rank() {
+        let state = SharedState::new(&ServerConfig::default());
+        let (owner, _owner_rx) = add_user(&state, "owner", Role::Owner, 8).await;
+        let (op, mut op_rx) = add_user(&state, "op", Role::Operator, 8).await;
+        let (_, mut op2_rx) = add_user(&state, "op2", Role::Operator, 8).await;
+        let (member, mut member_rx) = add_user(&state, "member", Role::Member, 8).await;
+
+        // 成员没有踢人权限
+        let err = run(&state, member, "member", "/kick op").await.unwrap_err();
+        assert_eq!(err.code, ErrorCode::PermissionDenied);
+        assert!(op_rx.try_recv().is_err());
+
+        // 管理员只能踢出角色更低的用户，不能踢自己、同级或所有者
+        for target in ["op", "op2", "owner"] {
+            let err = run(&state, op, "op", &format!("/kick {}", target)).await.unwrap_err();
+            assert_eq!(err.code, ErrorCode::PermissionDenied, "{}", target);
+        }
+        assert!(op2_rx.try_recv().is_err());
+        run(&state, op, "op", "/kick @member").await.unwrap();
+        assert!(matches!(member_rx.try_recv(), Ok(Direct::Kick { by }) if by == "op"));
+
+        // 所有者可以踢出管理员
+        run(&state, owner, "owner", "/kick OP2").await.unwrap();
+        assert!(matches!(op2_rx.try_recv(), Ok(Direct::Kick { by }) if by == "owner"));
+
+        let err = run(&state, op, "op", "/kick nobody").await.unwrap_err();
+        assert_eq!(err.code, ErrorCode::UserNotFound);
+    }
+}
@@ -0,0 +1,85 @@
+//! 服务端配置
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use protocol::{CodecKind, Role};
+
+/// 默认文件存储目录
+const DEFAULT_FILES_DIR: &str = "chat-files";
+
+/// 默认文件存储总配额（字节）
+const DEFAULT_FILE_QUOTA: u64 = 512 * 1024 * 1024;
+
+/// 默认单用户文件配额（字节）
+const DEFAULT_USER_FILE_QUOTA: u64 = 50 * 1024 * 1024;
+
+/// 默认文件保存期限：7 天
+const DEFAULT_FILE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// 默认离线私聊保存期限：7 天
+const DEFAULT_OFFLINE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// 聊天室的加入方式
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum RoomMode {
+    /// 任何人都可以加入
+    #[default]
+    Public,
+    /// 需要管理员通过 `/invite` 签发的邀请码，邀请码在撤销前可以反复使用；
+    /// 持有成员及以上角色密钥的用户不需要
+    InviteOnly,
+    /// 需要密码，持有成员及以上角色密钥的用户不需要
+    Password(String),
+}
+
+/// 服务端配置
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    /// 消息序列化格式，客户端须使用相同格式
+    pub codec: CodecKind,
+    /// 上传文件的存储目录
+    pub files_dir: PathBuf,
+    /// 所有文件占用的总空间上限
+    ///
+    /// 文件过期删除后释放占用的配额，重启后按存储目录中保留的文件重新计算。
+    pub file_quota: u64,
+    /// 单个用户（按用户名，不区分大小写）保存的文件占用的空间上限
+    pub user_file_quota: u64,
+    /// 上传的文件保存多久后过期删除
+    pub file_ttl: Duration,
+    /// 发给离线用户的私聊排队多久后丢弃
+    pub offline_ttl: Duration,
+    /// 角色密钥到角色的映射：Join 时以访问密钥发送角色密钥的用户获得对应角色
+    pub role_keys: HashMap<String, Role>,
+    /// 未分配角色的用户加入时的角色
+    pub default_role: Role,
+    /// 聊天室的加入方式
+    pub mode: RoomMode,
+    /// 消息历史数据库（SQLite）路径，None 时不保存历史，也不支持搜索
+    pub history_path: Option<PathBuf>,
+    /// 每日消息，用户加入后发送
+    pub motd: Option<String>,
+    /// 发送给客户端的文本所用语言，None 时按环境变量和系统设置决定
+    pub lang: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            codec: CodecKind::default(),
+            files_dir: PathBuf::from(DEFAULT_FILES_DIR),
+            file_quota: DEFAULT_FILE_QUOTA,
+            user_file_quota: DEFAULT_USER_FILE_QUOTA,
+            file_ttl: DEFAULT_FILE_TTL,
+            offline_ttl: DEFAULT_OFFLINE_TTL,
+            role_keys: HashMap::new(),
+            default_role: Role::default(),
+            mode: RoomMode::default(),
+            history_path: None,
+            motd: None,
+            lang: None,
+        }
+    }
+}
@@ -0,0 +1,589 @@
+//! 上传文件存储
+//!
+//! 文件以 `<file_id>.blob` 保存在配置的目录中，上传过程中写入 `<file_id>.part`，
+//! 文件信息写入同名的 `<file_id>.meta`。服务器启动时从 `.meta` 重建索引和配额占用，
+//! 只清理未完成的上传和缺少对应文件的残留。
+//!
+//! 文件保存 [`ServerConfig::file_ttl`] 后过期，过期的文件在下次上传或下载时删除并释放配额。
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use i18n::t;
+use protocol::{
+    chunk_checksum, username_key, ErrorCode, ServerMessage, MAX_CONCURRENT_DOWNLOADS, MAX_CONCURRENT_UPLOADS,
+};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+use crate::config::ServerConfig;
+
+/// 已保存的文件
+#[derive(Clone, Debug)]
+pub struct StoredFile {
+    pub name: String,
+    pub size: u64,
+    pub mime: String,
+    pub uploader: String,
+    /// 上传完成的时间
+    pub uploaded_at: SystemTime,
+}
+
+impl StoredFile {
+    /// 序列化为 `.meta` 文件内容：上传时间、MIME 类型、上传者各占一行，文件名在最后
+    fn to_meta(&self) -> String {
+        let uploaded_at = self.uploaded_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        format!("{}\n{}\n{}\n{}", uploaded_at, self.mime, self.uploader, self.name)
+    }
+
+    /// 从 `.meta` 文件内容和文件大小解析，格式不正确时返回 None
+    fn from_meta(meta: &str, size: u64) -> Option<Self> {
+        let mut lines = meta.splitn(4, '\n');
+        let uploaded_at = lines.next()?.parse().ok()?;
+        let mime = lines.next()?.to_string();
+        let uploader = lines.next()?.to_string();
+        let name = lines.next()?.to_string();
+        Some(Self {
+            name,
+            size,
+            mime,
+            uploader,
+            uploaded_at: UNIX_EPOCH + Duration::from_secs(uploaded_at),
+        })
+    }
+}
+
+/// 配额占用统计
+#[derive(Default)]
+struct Usage {
+    /// 所有文件（含上传中）占用的空间
+    total: u64,
+    /// 用户名（[`username_key`]）-> 占用空间
+    per_user: HashMap<String, u64>,
+}
+
+impl Usage {
+    fn add(&mut self, uploader: &str, size: u64) {
+        self.total += size;
+        *self.per_user.entry(username_key(uploader)).or_default() += size;
+    }
+
+    fn release(&mut self, uploader: &str, size: u64) {
+        self.total = self.total.saturating_sub(size);
+        let key = username_key(uploader);
+        if let Some(used) = self.per_user.get_mut(&key) {
+            *used = used.saturating_sub(size);
+            if *used == 0 {
+                self.per_user.remove(&key);
+            }
+        }
+    }
+}
+
+/// 文件存储
+pub struct FileStore {
+    dir: PathBuf,
+    quota: u64,
+    user_quota: u64,
+    ttl: Duration,
+    usage: Mutex<Usage>,
+    files: RwLock<HashMap<u64, StoredFile>>,
+    next_file_id: AtomicU64,
+}
+
+impl FileStore {
+    pub fn new(config: &ServerConfig) -> Self {
+        Self {
+            dir: config.files_dir.clone(),
+            quota: config.file_quota,
+            user_quota: config.user_file_quota,
+            ttl: config.file_ttl,
+            usage: Mutex::new(Usage::default()),
+            files: RwLock::new(HashMap::new()),
+            next_file_id: AtomicU64::new(1),
+        }
+    }
+
+    /// 创建存储目录，加载上次运行保存的文件，并清理未完成的上传和过期的文件
+    pub async fn init(&self) -> std::io::Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let mut stale = Vec::new();
+        let mut max_id = 0;
+        let mut files = self.files.write().await;
+        let mut usage = self.usage.lock().await;
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let file_id = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse::<u64>().ok());
+            let file_id = match (path.extension().and_then(|e| e.to_str()), file_id) {
+                (Some("part"), _) => {
+                    stale.push(path);
+                    continue;
+                }
+                (Some("blob"), Some(id)) => id,
+                (Some("blob") | Some("meta"), None) => {
+                    stale.push(path);
+                    continue;
+                }
+                _ => continue,
+            };
+            match load_file(&path, &self.meta_path(file_id)).await {
+                Some(file) if !self.is_expired(&file) => {
+                    usage.add(&file.uploader, file.size);
+                    files.insert(file_id, file);
+                    max_id = max_id.max(file_id);
+                }
+                _ => stale.push(path),
+            }
+        }
+        drop(usage);
+
+        // 没有对应 .blob 的 .meta 同样是残留
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("meta") {
+                continue;
+            }
+            let file_id = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse::<u64>().ok());
+            if !file_id.is_some_and(|id| files.contains_key(&id)) {
+                stale.push(path);
+            }
+        }
+        for path in stale {
+            debug!("Removing stale file {}", path.display());
+            tokio::fs::remove_file(&path).await?;
+        }
+
+        self.next_file_id.store(max_id + 1, Ordering::SeqCst);
+        info!("File store ready at {} ({} files)", self.dir.display(), files.len());
+        Ok(())
+    }
+
+    /// 为一次上传预留配额并分配文件 ID，超出配额时返回 None
+    ///
+    /// 预留前先删除过期的文件，释放它们占用的配额。
+    pub async fn reserve(&self, uploader: &str, size: u64) -> Option<u64> {
+        self.expire().await;
+        let mut usage = self.usage.lock().await;
+        let user_used = usage.per_user.get(&username_key(uploader)).copied().unwrap_or(0);
+        if usage.total + size > self.quota || user_used + size > self.user_quota {
+            return None;
+        }
+        usage.add(uploader, size);
+        Some(self.next_file_id.fetch_add(1, Ordering::SeqCst))
+    }
+
+    /// 释放预留的配额并删除未完成的上传
+    pub async fn abort(&self, file_id: u64, uploader: &str, size: u64) {
+        self.usage.lock().await.release(uploader, size);
+        let _ = tokio::fs::remove_file(self.part_path(file_id)).await;
+    }
+
+    /// 完成上传：写入文件信息，将临时文件转为正式文件并加入索引
+    pub async fn commit(&self, file_id: u64, file: StoredFile) -> std::io::Result<()> {
+        tokio::fs::write(self.meta_path(file_id), file.to_meta()).await?;
+        if let Err(e) = tokio::fs::rename(self.part_path(file_id), self.blob_path(file_id)).await {
+            let _ = tokio::fs::remove_file(self.meta_path(file_id)).await;
+            return Err(e);
+        }
+        self.files.write().await.insert(file_id, file);
+        Ok(())
+    }
+
+    /// 查询已保存且未过期的文件
+    pub async fn get(&self, file_id: u64) -> Option<StoredFile> {
+        self.files.read().await.get(&file_id).filter(|f| !self.is_expired(f)).cloned()
+    }
+
+    /// 删除所有过期的文件并释放它们占用的配额
+    pub async fn expire(&self) {
+        let expired: Vec<(u64, StoredFile)> = {
+            let mut files = self.files.write().await;
+            let ids: Vec<u64> = files.iter().filter(|(_, f)| self.is_expired(f)).map(|(&id, _)| id).collect();
+            ids.into_iter().filter_map(|id| files.remove(&id).map(|f| (id, f))).collect()
+        };
+        for (file_id, file) in expired {
+            self.release(file_id, &file).await;
+        }
+    }
+
+    /// 删除已保存的文件并释放其占用的配额
+    async fn release(&self, file_id: u64, file: &StoredFile) {
+        debug!("Removing expired file {} ({})", file_id, file.name);
+        self.usage.lock().await.release(&file.uploader, file.size);
+        for path in [self.blob_path(file_id), self.meta_path(file_id)] {
+            if let Err(e) = tokio::fs::remove_file(&path).await {
+                warn!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// 文件是否已超过保存期限
+    fn is_expired(&self, file: &StoredFile) -> bool {
+        file.uploaded_at.elapsed().is_ok_and(|age| age >= self.ttl)
+    }
+
+    /// 上传中的临时文件路径
+    pub fn part_path(&self, file_id: u64) -> PathBuf {
+        self.dir.join(format!("{}.part", file_id))
+    }
+
+    /// 已保存文件的路径
+    pub fn blob_path(&self, file_id: u64) -> PathBuf {
+        self.dir.join(format!("{}.blob", file_id))
+    }
+
+    /// 已保存文件的信息文件路径
+    fn meta_path(&self, file_id: u64) -> PathBuf {
+        self.dir.join(format!("{}.meta", file_id))
+    }
+}
+
+/// 读取已保存文件的信息，信息文件缺失或损坏时返回 None
+async fn load_file(blob: &Path, meta: &Path) -> Option<StoredFile> {
+    let size = tokio::fs::metadata(blob).await.ok()?.len();
+    let meta = tokio::fs::read_to_string(meta).await.ok()?;
+    StoredFile::from_meta(&meta, size)
+}
+
+/// 进行中的上传
+struct PendingUpload {
+    file_id: u64,
+    name: String,
+    size: u64,
+    mime: String,
+    received: u64,
+    next_index: u32,
+    file: File,
+}
+
+/// 进行中的下载
+struct PendingDownload {
+    transfer_id: u32,
+    next_index: u32,
+    remaining: u64,
+    file: File,
+}
+
+/// 单个连接的文件传输状态
+pub struct TransferSession {
+    store: Arc<FileStore>,
+    username: String,
+    uploads: HashMap<u32, PendingUpload>,
+    downloads: VecDeque<PendingDownload>,
+    /// 下载分块大小（取决于连接的序列化格式）
+    chunk_size: usize,
+}
+
+impl TransferSession {
+    pub fn new(store: Arc<FileStore>, username: String, chunk_size: usize) -> Self {
+        Self {
+            store,
+            username,
+            uploads: HashMap::new(),
+            downloads: VecDeque::new(),
+            chunk_size,
+        }
+    }
+
+    /// 处理上传请求，返回 FileAccepted 或 FileCancelled
+    pub async fn offer(&mut self, transfer_id: u32, name: String, size: u64, mime: String) -> ServerMessage {
+        if self.uploads.contains_key(&transfer_id) {
+            return cancelled(transfer_id, "transfer-id-in-use");
+        }
+        if self.uploads.len() >= MAX_CONCURRENT_UPLOADS {
+            return too_many(transfer_id);
+        }
+
+        let file_id = match self.store.reserve(&self.username, size).await {
+            Some(id) => id,
+            None => return cancelled(transfer_id, "transfer-quota-exceeded"),
+        };
+
+        let file = match File::create(self.store.part_path(file_id)).await {
+            Ok(f) => f,
+            Err(e) => {
+                warn!("Failed to create upload file: {}", e);
+                self.store.abort(file_id, &self.username, size).await;
+                return cancelled(transfer_id, "transfer-save-failed");
+            }
+        };
+
+        debug!("User {} uploading {} ({} bytes) as file {}", self.username, name, size, file_id);
+        self.uploads.insert(
+            transfer_id,
+            PendingUpload {
+                file_id,
+                name,
+                size,
+                mime,
+                received: 0,
+                next_index: 0,
+                file,
+            },
+        );
+        ServerMessage::FileAccepted { transfer_id }
+    }
+
+    /// 写入上传分块，失败时取消上传并返回 FileCancelled
+    pub async fn chunk(&mut self, transfer_id: u32, index: u32, data: Vec<u8>, checksum: u32) -> Option<ServerMessage> {
+        let upload = match self.uploads.get_mut(&transfer_id) {
+            Some(u) => u,
+            None => return Some(cancelled(transfer_id, "transfer-unknown")),
+        };
+
+        let reason = if index != upload.next_index {
+            Some("transfer-chunk-out-of-order")
+        } else if chunk_checksum(&data) != checksum {
+            Some("transfer-checksum-mismatch")
+        } else if upload.received + data.len() as u64 > upload.size {
+            Some("transfer-size-mismatch")
+        } else if let Err(e) = upload.file.write_all(&data).await {
+            warn!("Failed to write upload chunk: {}", e);
+            Some("transfer-save-failed")
+        } else {
+            upload.received += data.len() as u64;
+            upload.next_index += 1;
+            None
+        };
+
+        match reason {
+            Some(reason) => {
+                self.cancel(transfer_id).await;
+                Some(cancelled(transfer_id, reason))
+            }
+            None => None,
+        }
+    }
+
+    /// 完成上传，成功返回文件 ID 和文件信息，失败返回 FileCancelled
+    pub async fn complete(&mut self, transfer_id: u32) -> std::result::Result<(u64, StoredFile), ServerMessage> {
+        let mut upload = match self.uploads.remove(&transfer_id) {
+            Some(u) => u,
+            None => return Err(cancelled(transfer_id, "transfer-unknown")),
+        };
+
+        if upload.received != upload.size {
+            self.store.abort(upload.file_id, &self.username, upload.size).await;
+            return Err(cancelled(transfer_id, "transfer-size-mismatch"));
+        }
+
+        let stored = StoredFile {
+            name: upload.name,
+            size: upload.size,
+            mime: upload.mime,
+            uploader: self.username.clone(),
+            uploaded_at: SystemTime::now(),
+        };
+        let result = match upload.file.flush().await {
+            Ok(()) => self.store.commit(upload.file_id, stored.clone()).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!("Failed to store upload: {}", e);
+            self.store.abort(upload.file_id, &self.username, upload.size).await;
+            return Err(cancelled(transfer_id, "transfer-save-failed"));
+        }
+
+        info!("User {} uploaded {} as file {}", self.username, stored.name, upload.file_id);
+        Ok((upload.file_id, stored))
+    }
+
+    /// 开始下载，失败或同时下载的文件过多时返回 FileCancelled
+    pub async fn download(&mut self, transfer_id: u32, file_id: u64) -> Option<ServerMessage> {
+        if self.downloads.iter().any(|d| d.transfer_id == transfer_id) {
+            return Some(cancelled(transfer_id, "transfer-id-in-use"));
+        }
+        // 每个下载占用一个打开的文件，不限制数量会耗尽文件描述符
+        if self.downloads.len() >= MAX_CONCURRENT_DOWNLOADS {
+            return Some(too_many(transfer_id));
+        }
+        let stored = match self.store.get(file_id).await {
+            Some(f) => f,
+            None => return Some(cancelled(transfer_id, "transfer-file-not-found")),
+        };
+        let file = match File::open(self.store.blob_path(file_id)).await {
+            Ok(f) => f,
+            Err(e) => {
+                warn!("Failed to open file {}: {}", file_id, e);
+                return Some(cancelled(transfer_id, "transfer-read-failed"));
+            }
+        };
+        self.downloads.push_back(PendingDownload {
+            transfer_id,
+            next_index: 0,
+            remaining: stored.size,
+            file,
+        });
+        None
+    }
+
+    /// 是否有待发送的下载分块
+    pub fn has_pending_downloads(&self) -> bool {
+        !self.downloads.is_empty()
+    }
+
+    /// 读取下一个下载分块；下载结束时返回 FileComplete
+    ///
+    /// 多个下载轮流发送，每次调用只发送一个分块。
+    pub async fn next_download_message(&mut self) -> Option<ServerMessage> {
+        let mut download = self.downloads.pop_front()?;
+        let transfer_id = download.transfer_id;
+
+        if download.remaining == 0 {
+            return Some(ServerMessage::FileComplete { transfer_id });
+        }
+
+        let len = download.remaining.min(self.chunk_size as u64);
+        let mut data = Vec::with_capacity(len as usize);
+        match (&mut download.file).take(len).read_to_end(&mut data).await {
+            Ok(n) if n as u64 == len => {}
+            Ok(_) => return Some(cancelled(transfer_id, "transfer-read-failed")),
+            Err(e) => {
+                warn!("Failed to read file chunk: {}", e);
+                return Some(cancelled(transfer_id, "transfer-read-failed"));
+            }
+        }
+
+        let index = download.next_index;
+        download.next_index += 1;
+        download.remaining -= len;
+        self.downloads.push_back(download);
+
+        Some(ServerMessage::FileChunk {
+            transfer_id,
+            index,
+            checksum: chunk_checksum(&data),
+            data,
+        })
+    }
+
+    /// 取消上传或下载
+    pub async fn cancel(&mut self, transfer_id: u32) {
+        if let Some(upload) = self.uploads.remove(&transfer_id) {
+            self.store.abort(upload.file_id, &self.username, upload.size).await;
+        }
+        self.downloads.retain(|d| d.transfer_id != transfer_id);
+    }
+
+    /// 连接断开时清理所有未完成的传输
+    pub async fn abort_all(&mut self) {
+        for (_, upload) in self.uploads.drain() {
+            self.store.abort(upload.file_id, &self.username, upload.size).await;
+        }
+        self.downloads.clear();
+    }
+}
+
+/// 构造传输取消消息
+fn cancelled(transfer_id: u32, reason_id: &str) -> ServerMessage {
+    ServerMessage::FileCancelled {
+        transfer_id,
+        code: None,
+        reason: t!(reason_id),
+    }
+}
+
+/// 同时进行的传输过多时的取消消息
+fn too_many(transfer_id: u32) -> ServerMessage {
+    ServerMessage::FileCancelled {
+        transfer_id,
+        code: Some(ErrorCode::TooManyTransfers),
+        reason: t!("transfer-too-many"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 使用独立临时目录的存储
+    async fn store(name: &str, config: ServerConfig) -> FileStore {
+        let dir = std::env::temp_dir().join(format!("chat-files-test-{}-{}", name, std::process::id()));
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        let store = FileStore::new(&ServerConfig { files_dir: dir, ..config });
+        store.init().await.unwrap();
+        store
+    }
+
+    /// 预留并保存一个文件
+    async fn upload(store: &FileStore, uploader: &str, data: &[u8]) -> u64 {
+        let file_id = store.reserve(uploader, data.len() as u64).await.unwrap();
+        tokio::fs::write(store.part_path(file_id), data).await.unwrap();
+        let file = StoredFile {
+            name: "notes\nfinal.txt".to_string(),
+            size: data.len() as u64,
+            mime: "text/plain".to_string(),
+            uploader: uploader.to_string(),
+            uploaded_at: SystemTime::now(),
+        };
+        store.commit(file_id, file).await.unwrap();
+        file_id
+    }
+
+    #[tokio::test]
+    async fn test_files_survive_restart() {
+        let config = ServerConfig {
+            user_file_quota: 10,
+            ..ServerConfig::default()
+        };
+        let first = store("restart", config.clone()).await;
+        let file_id = upload(&first, "Alice", b"hello").await;
+        let pending = first.reserve("Bob", 3).await.unwrap();
+        tokio::fs::write(first.part_path(pending), b"abc").await.unwrap();
+
+        let second = FileStore::new(&ServerConfig {
+            files_dir: first.dir.clone(),
+            ..config
+        });
+        second.init().await.unwrap();
+        let file = second.get(file_id).await.unwrap();
+        assert_eq!(file.name, "notes\nfinal.txt");
+        assert_eq!(file.size, 5);
+        assert_eq!(file.mime, "text/plain");
+        assert_eq!(file.uploader, "Alice");
+        assert!(second.blob_path(file_id).exists());
+        assert!(!second.part_path(pending).exists());
+
+        // 重启后仍计入配额，新文件 ID 不与已保存的文件重复
+        assert_eq!(second.reserve("alice", 6).await, None);
+        assert!(second.reserve("alice", 5).await.unwrap() > file_id);
+    }
+
+    #[tokio::test]
+    async fn test_expired_files_release_quota() {
+        let config = ServerConfig {
+            file_quota: 5,
+            file_ttl: Duration::ZERO,
+            ..ServerConfig::default()
+        };
+        let store = store("expire", config).await;
+        let file_id = upload(&store, "Alice", b"hello").await;
+        assert!(store.get(file_id).await.is_none());
+
+        assert!(store.reserve("Bob", 5).await.is_some());
+        assert!(!store.blob_path(file_id).exists());
+        assert!(!store.meta_path(file_id).exists());
+    }
+
+    #[tokio::test]
+    async fn test_user_quota_ignores_case() {
+        let config = ServerConfig {
+            user_file_quota: 10,
+            ..ServerConfig::default()
+        };
+        let store = store("user-quota", config).await;
+        let file_id = store.reserve("Alice", 6).await.unwrap();
+        assert_eq!(store.reserve("ALICE", 6).await, None);
+        assert!(store.reserve("Bob", 6).await.is_some());
+
+        store.abort(file_id, "alice", 6).await;
+        assert!(store.reserve("ALICE", 6).await.is_some());
+    }
+}
@@ -0,0 +1,298 @@
+//! 消息历史与全文搜索
+//!
+//! 聊天消息和 `/me` 动作消息保存在 SQLite 数据库中，并用 FTS5 的 trigram 分词建立全文索引，
+//! 中文等不用空格分词的文字也能按子串搜索。私聊不保存。
+//! 未配置数据库路径时不保存历史，搜索返回 `SearchUnavailable`。
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use protocol::{username_key, SearchHit, MAX_SEARCH_RESULTS};
+use rusqlite::types::Value;
+use rusqlite::{params, Connection};
+use tracing::{info, warn};
+
+/// trigram 分词只能匹配至少 3 个字符的关键词，更短的关键词改用 LIKE 逐条匹配
+const MIN_INDEXED_TERM_CHARS: usize = 3;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS messages (
+    id INTEGER PRIMARY KEY,
+    username TEXT NOT NULL,
+    user_key TEXT NOT NULL,
+    content TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    action INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS messages_user_key ON messages (user_key);
+CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5 (
+    content,
+    content = 'messages',
+    content_rowid = 'id',
+    tokenize = 'trigram'
+);
+";
+
+/// 要保存的消息
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub message_id: u64,
+    pub username: String,
+    pub content: String,
+    pub timestamp: u64,
+    pub action: bool,
+}
+
+/// 搜索条件
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    pub query: String,
+    pub from_user: Option<String>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub before: Option<u64>,
+    pub limit: u32,
+}
+
+/// 一页搜索结果
+#[derive(Debug, Clone)]
+pub struct SearchPage {
+    /// 从新到旧排列
+    pub hits: Vec<SearchHit>,
+    /// 是否还有更早的结果
+    pub more: bool,
+}
+
+/// 消息历史
+pub struct History {
+    path: Option<PathBuf>,
+    db: OnceLock<Arc<Mutex<Connection>>>,
+}
+
+impl History {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            db: OnceLock::new(),
+        }
+    }
+
+    /// 打开数据库并建表，返回已保存的最大消息 ID（未启用或没有消息时为 0）
+    pub async fn init(&self) -> anyhow::Result<u64> {
+        let Some(path) = self.path.clone() else {
+            return Ok(0);
+        };
+        let conn = tokio::task::spawn_blocking(move || Connection::open(path)).await??;
+        conn.execute_batch(SCHEMA)?;
+        let last_id: i64 = conn.query_row("SELECT COALESCE(MAX(id), 0) FROM messages", [], |row| row.get(0))?;
+        info!("Message history ready ({} messages)", last_id);
+        let _ = self.db.set(Arc::new(Mutex::new(conn)));
+        Ok(last_id as u64)
+    }
+
+    /// 在后台保存消息，未启用时忽略
+    pub fn record(&self, entry: HistoryEntry) {
+        let Some(db) = self.db.get().cloned() else {
+            return;
+        };
+        tokio::task::spawn_blocking(move || {
+            let message_id = entry.message_id;
+            if let Err(e) = insert(&db.lock().unwrap(), entry) {
+                warn!("Failed to save message #{} to history: {}", message_id, e);
+            }
+        });
+    }
+
+    /// 搜索历史消息，未启用时返回 None
+    pub async fn search(&self, query: SearchQuery) -> Option<rusqlite::Result<SearchPage>> {
+        let db = self.db.get().cloned()?;
+        let result = tokio::task::spawn_blocking(move || search(&db.lock().unwrap(), &query))
+            .await
+            .expect("history search task panicked");
+        Some(result)
+    }
+}
+
+fn insert(conn: &Connection, entry: HistoryEntry) -> rusqlite::Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO messages (id, username, user_key, content, timestamp, action) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            entry.message_id as i64,
+            entry.username,
+            username_key(&entry.username),
+            entry.content,
+            entry.timestamp as i64,
+            entry.action,
+        ],
+    )?;
+    tx.execute(
+        "INSERT INTO messages_fts (rowid, content) VALUES (?1, ?2)",
+        params![entry.message_id as i64, entry.content],
+    )?;
+    tx.commit()
+}
+
+fn search(conn: &Connection, query: &SearchQuery) -> rusqlite::Result<SearchPage> {
+    let mut conditions = Vec::new();
+    let mut args = Vec::new();
+
+    // 每个关键词都按字面匹配，用户输入不会被当作 FTS5 查询语法
+    let (indexed, short): (Vec<&str>, Vec<&str>) = query
+        .query
+        .split_whitespace()
+        .partition(|term| term.chars().count() >= MIN_INDEXED_TERM_CHARS);
+    if !indexed.is_empty() {
+        let expr = indexed
+            .iter()
+            .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" AND ");
+        conditions.push("id IN (SELECT rowid FROM messages_fts WHERE messages_fts MATCH ?)");
+        args.push(Value::Text(expr));
+    }
+    for term in short {
+        conditions.push("content LIKE ? ESCAPE '\\'");
+        let escaped = term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        args.push(Value::Text(format!("%{}%", escaped)));
+    }
+    if let Some(user) = &query.from_user {
+        conditions.push("user_key = ?");
+        args.push(Value::Text(username_key(user)));
+    }
+    if let Some(since) = query.since {
+        conditions.push("timestamp >= ?");
+        args.push(Value::Integer(since as i64));
+    }
+    if let Some(until) = query.until {
+        conditions.push("timestamp <= ?");
+        args.push(Value::Integer(until as i64));
+    }
+    if let Some(before) = query.before {
+        conditions.push("id < ?");
+        args.push(Value::Integer(before as i64));
+    }
+
+    let limit = query.limit.clamp(1, MAX_SEARCH_RESULTS) as usize;
+    // 多取一条判断是否还有下一页
+    args.push(Value::Integer(limit as i64 + 1));
+    let where_clause = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    };
+    let sql = format!(
+        "SELECT id, username, content, timestamp, action FROM messages {} ORDER BY id DESC LIMIT ?",
+        where_clause
+    );
+
+    let mut stmt = conn.prepare(&sql)?;
+    let mut hits = stmt
+        .query_map(rusqlite::params_from_iter(args), |row| {
+            Ok(SearchHit {
+                message_id: row.get::<_, i64>(0)? as u64,
+                username: row.get(1)?,
+                content: row.get(2)?,
+                timestamp: row.get::<_, i64>(3)? as u64,
+                action: row.get(4)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let more = hits.len() > limit;
+    hits.truncate(limit);
+    Ok(SearchPage { hits, more })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 内存数据库，依次保存 `messages` 中的 (用户名, 内容)，消息 ID 从 1 开始
+    fn db(messages: &[(&str, &str)]) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+        for (i, (username, content)) in messages.iter().enumerate() {
+            let entry = HistoryEntry {
+                message_id: i as u64 + 1,
+                username: username.to_string(),
+                content: content.to_string(),
+                timestamp: 1000 + i as u64,
+                action: false,
+            };
+            insert(&conn, entry).unwrap();
+        }
+        conn
+    }
+
+    fn query(text: &str) -> SearchQuery {
+        SearchQuery {
+            query: text.to_string(),
+            from_user: None,
+            since: None,
+            until: None,
+            before: None,
+            limit: MAX_SEARCH_RESULTS,
+        }
+    }
+
+    fn ids(page: &SearchPage) -> Vec<u64> {
+        page.hits.iter().map(|hit| hit.message_id).collect()
+    }
+
+    #[test]
+    fn test_search_escapes_quotes() {
+        let conn = db(&[
+            ("alice", r#"she said "hello" twice"#),
+            ("bob", "hello world"),
+            ("carol", "OR NOT AND *"),
+        ]);
+        assert_eq!(ids(&search(&conn, &query(r#""hello""#)).unwrap()), vec![1]);
+        assert_eq!(ids(&search(&conn, &query(r#"said "hello"#)).unwrap()), vec![1]);
+        assert_eq!(ids(&search(&conn, &query("hello")).unwrap()), vec![2, 1]);
+        // FTS5 运算符按字面匹配
+        assert_eq!(ids(&search(&conn, &query("NOT AND")).unwrap()), vec![3]);
+        assert!(search(&conn, &query(r#"wor"ld"#)).unwrap().hits.is_empty());
+    }
+
+    #[test]
+    fn test_search_short_terms() {
+        let conn = db(&[
+            ("alice", "你好，世界"),
+            ("bob", "go 100% faster"),
+            ("carol", "go_home"),
+            ("dave", "good morning"),
+        ]);
+        // 少于 3 个字符的关键词用 LIKE 匹配
+        assert_eq!(ids(&search(&conn, &query("你好")).unwrap()), vec![1]);
+        assert_eq!(ids(&search(&conn, &query("go")).unwrap()), vec![4, 3, 2]);
+        // LIKE 通配符按字面匹配
+        assert_eq!(ids(&search(&conn, &query("%")).unwrap()), vec![2]);
+        assert_eq!(ids(&search(&conn, &query("o_")).unwrap()), vec![3]);
+        // 长短关键词同时满足
+        assert_eq!(ids(&search(&conn, &query("go morning")).unwrap()), vec![4]);
+    }
+
+    #[test]
+    fn test_search_pagination() {
+        let messages: Vec<(&str, &str)> = (0..5).map(|i| (if i % 2 == 0 { "Alice" } else { "bob" }, "status update")).collect();
+        let conn = db(&messages);
+
+        let first = search(&conn, &SearchQuery { limit: 2, ..query("update") }).unwrap();
+        assert_eq!(ids(&first), vec![5, 4]);
+        assert!(first.more);
+        let second = search(&conn, &SearchQuery { limit: 2, before: Some(4), ..query("update") }).unwrap();
+        assert_eq!(ids(&second), vec![3, 2]);
+        assert!(second.more);
+        let last = search(&conn, &SearchQuery { limit: 2, before: Some(2), ..query("update") }).unwrap();
+        assert_eq!(ids(&last), vec![1]);
+        assert!(!last.more);
+
+        let from_alice = SearchQuery {
+            from_user: Some("ALICE".to_string()),
+            since: Some(1001),
+            ..query("update")
+        };
+        assert_eq!(ids(&search(&conn, &from_alice).unwrap()), vec![5, 3]);
+    }
+}
@@ -0,0 +1,15 @@
+//! 聊天室服务端库
+//!
+//! `chat-server` 可执行文件的实现，也供其他 crate 的测试在进程内启动服务器。
+
+mod access;
+mod commands;
+mod config;
+mod files;
+mod history;
+mod offline;
+mod roles;
+mod server;
+
+pub use config::{RoomMode, ServerConfig};
+pub use server::ChatServer;
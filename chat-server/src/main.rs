@@ -2,15 +2,26 @@
 //!
 //! 基于 Tokio 的异步 TCP 服务器
 
-mod server;
-
 use anyhow::Result;
-use server::ChatServer;
+use chat_server::{ChatServer, RoomMode, ServerConfig};
+use i18n::{Catalog, Localizer};
 use tracing::info;
 use tracing_subscriber::EnvFilter;
 
 const DEFAULT_ADDR: &str = "127.0.0.1:8080";
 
+/// 服务端消息目录
+const CATALOGS: &[Catalog] = &[
+    Catalog {
+        locale: "en-US",
+        source: include_str!("../locales/en-US.ftl"),
+    },
+    Catalog {
+        locale: "zh-CN",
+        source: include_str!("../locales/zh-CN.ftl"),
+    },
+];
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // 初始化日志
@@ -18,18 +29,114 @@ async fn main() -> Result<()> {
         .with_env_filter(
             EnvFilter::from_default_env()
                 .add_directive("chat_server=debug".parse()?)
-                .add_directive("protocol=debug".parse()?),
+                .add_directive("protocol=debug".parse()?)
+                .add_directive("i18n=warn".parse()?),
         )
         .init();
 
-    let addr = std::env::args()
-        .nth(1)
-        .unwrap_or_else(|| DEFAULT_ADDR.to_string());
+    // 用法: chat-server [addr] [--files-dir DIR] [--file-ttl-hours HOURS] [--offline-ttl-hours HOURS] [--codec bincode|postcard|msgpack|json] [--lang LOCALE] [--motd-file FILE]
+    //                   [--role-key ROLE=KEY]... [--default-role ROLE] [--invite-only | --password PASSWORD]
+    //                   [--history FILE]
+    let mut addr = DEFAULT_ADDR.to_string();
+    let mut config = ServerConfig::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--files-dir" => {
+                config.files_dir = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--files-dir requires a value"))?
+                    .into();
+            }
+            "--file-ttl-hours" => {
+                let hours: u64 = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--file-ttl-hours requires a value"))?
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("invalid --file-ttl-hours: {}", e))?;
+                config.file_ttl = std::time::Duration::from_secs(hours.saturating_mul(60 * 60));
+            }
+            "--offline-ttl-hours" => {
+                let hours: u64 = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--offline-ttl-hours requires a value"))?
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("invalid --offline-ttl-hours: {}", e))?;
+                config.offline_ttl = std::time::Duration::from_secs(hours.saturating_mul(60 * 60));
+            }
+            "--history" => {
+                config.history_path = Some(
+                    args.next()
+                        .ok_or_else(|| anyhow::anyhow!("--history requires a value"))?
+                        .into(),
+                );
+            }
+            "--codec" => {
+                config.codec = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--codec requires a value"))?
+                    .parse()
+                    .map_err(|e: String| anyhow::anyhow!(e))?;
+            }
+            "--lang" => {
+                config.lang = Some(
+                    args.next()
+                        .ok_or_else(|| anyhow::anyhow!("--lang requires a value"))?,
+                );
+            }
+            "--role-key" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--role-key requires a value"))?;
+                let (role, key) = value
+                    .split_once('=')
+                    .filter(|(_, key)| !key.is_empty())
+                    .ok_or_else(|| anyhow::anyhow!("--role-key expects ROLE=KEY"))?;
+                let role = role.parse().map_err(|e: String| anyhow::anyhow!(e))?;
+                config.role_keys.insert(key.to_string(), role);
+            }
+            "--default-role" => {
+                config.default_role = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--default-role requires a value"))?
+                    .parse()
+                    .map_err(|e: String| anyhow::anyhow!(e))?;
+            }
+            "--motd-file" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--motd-file requires a value"))?;
+                let motd = std::fs::read_to_string(&path)
+                    .map_err(|e| anyhow::anyhow!("cannot read MOTD file {}: {}", path, e))?;
+                config.motd = Some(motd.trim_end().to_string()).filter(|m| !m.is_empty());
+            }
+            "--invite-only" => set_mode(&mut config, RoomMode::InviteOnly)?,
+            "--password" => {
+                let password = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--password requires a value"))?;
+                set_mode(&mut config, RoomMode::Password(password))?;
+            }
+            _ => addr = arg,
+        }
+    }
+
+    let locale = i18n::detect_locale(config.lang.as_deref());
+    i18n::init(Localizer::new(&locale, CATALOGS));
 
-    info!("Chat Server starting on {}", addr);
+    info!("Chat Server starting on {} (codec: {}, locale: {})", addr, config.codec, locale);
 
-    let server = ChatServer::new();
+    let server = ChatServer::with_config(config);
     server.run(&addr).await?;
 
     Ok(())
 }
+
+/// 设置聊天室模式，不允许同时指定多种模式
+fn set_mode(config: &mut ServerConfig, mode: RoomMode) -> Result<()> {
+    if config.mode != RoomMode::Public {
+        anyhow::bail!("--invite-only and --password cannot be combined");
+    }
+    config.mode = mode;
+    Ok(())
+}
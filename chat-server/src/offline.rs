@@ -0,0 +1,196 @@
+//! 离线私聊
+//!
+//! 服务器没有账号，用户名不能证明身份，因此离线私聊按公钥认人：用户离开时记下其用户名和
+//! 加密私聊用的公钥，之后发给该用户名的 `/msg` 私聊在服务器上排队，只投递给下次以同一公钥
+//! 加入的用户。离开时没有公钥的用户不能接收离线私聊；以不同公钥使用过该名字的用户离开后，
+//! 之前排队的私聊被丢弃。
+//!
+//! 排队的私聊只保存在内存中，超过 [`ServerConfig::offline_ttl`] 或服务器重启后丢弃。
+//!
+//! [`ServerConfig::offline_ttl`]: crate::config::ServerConfig::offline_ttl
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use protocol::{username_key, ServerMessage};
+use tokio::sync::Mutex;
+
+/// 每个用户最多排队的离线私聊数量
+pub(crate) const MAX_OFFLINE_MESSAGES: usize = 20;
+
+/// 所有用户排队的离线私聊总数上限
+const MAX_QUEUED_TOTAL: usize = 1000;
+
+/// 最多记住的离线用户数量，超过时忘记最早离开的用户
+const MAX_KNOWN_USERS: usize = 1024;
+
+/// 排队失败的原因
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum QueueError {
+    /// 收件人不是以公钥离开的用户
+    Unknown,
+    /// 收件人或服务器的队列已满
+    Full,
+}
+
+/// 排队的私聊
+struct Queued {
+    message: ServerMessage,
+    queued_at: Instant,
+}
+
+/// 离开过的用户
+struct KnownUser {
+    /// 最后使用的用户名
+    username: String,
+    public_key: Vec<u8>,
+    left_at: Instant,
+    queue: VecDeque<Queued>,
+}
+
+/// 离线私聊队列，按用户名比较键（见 `username_key`）索引
+pub(crate) struct OfflineQueue {
+    ttl: Duration,
+    users: Mutex<HashMap<String, KnownUser>>,
+}
+
+impl OfflineQueue {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            users: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 用户离开时记下用户名和公钥，公钥与之前记录的不同时丢弃排队的私聊
+    pub(crate) async fn remember(&self, username: &str, public_key: Option<&[u8]>) {
+        let key = username_key(username);
+        let mut users = self.users.lock().await;
+        let Some(public_key) = public_key else {
+            users.remove(&key);
+            return;
+        };
+        if let Some(user) = users.get_mut(&key).filter(|u| u.public_key == public_key) {
+            user.username = username.to_string();
+            user.left_at = Instant::now();
+            return;
+        }
+        if users.len() >= MAX_KNOWN_USERS && !users.contains_key(&key) {
+            if let Some(oldest) = users.iter().min_by_key(|(_, u)| u.left_at).map(|(k, _)| k.clone()) {
+                users.remove(&oldest);
+            }
+        }
+        users.insert(
+            key,
+            KnownUser {
+                username: username.to_string(),
+                public_key: public_key.to_vec(),
+                left_at: Instant::now(),
+                queue: VecDeque::new(),
+            },
+        );
+    }
+
+    /// 可以接收离线私聊的用户最后使用的用户名
+    pub(crate) async fn username(&self, username: &str) -> Option<String> {
+        let users = self.users.lock().await;
+        users.get(&username_key(username)).map(|u| u.username.clone())
+    }
+
+    /// 为离线用户排队一条 `OfflineDirect` 消息
+    pub(crate) async fn queue(&self, to: &str, message: ServerMessage) -> Result<(), QueueError> {
+        let mut users = self.users.lock().await;
+        for user in users.values_mut() {
+            user.queue.retain(|q| q.queued_at.elapsed() < self.ttl);
+        }
+        let total: usize = users.values().map(|u| u.queue.len()).sum();
+        let user = users.get_mut(&username_key(to)).ok_or(QueueError::Unknown)?;
+        if user.queue.len() >= MAX_OFFLINE_MESSAGES || total >= MAX_QUEUED_TOTAL {
+            return Err(QueueError::Full);
+        }
+        user.queue.push_back(Queued {
+            message,
+            queued_at: Instant::now(),
+        });
+        Ok(())
+    }
+
+    /// 用户加入时取出排队的私聊（按排队顺序），公钥与离开时不同则不投递
+    pub(crate) async fn take(&self, username: &str, public_key: Option<&[u8]>) -> Vec<ServerMessage> {
+        let mut users = self.users.lock().await;
+        let Some(user) = users.get_mut(&username_key(username)) else {
+            return Vec::new();
+        };
+        if public_key != Some(user.public_key.as_slice()) {
+            return Vec::new();
+        }
+        user.queue
+            .drain(..)
+            .filter(|q| q.queued_at.elapsed() < self.ttl)
+            .map(|q| q.message)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(content: &str) -> ServerMessage {
+        ServerMessage::OfflineDirect {
+            message_id: 1,
+            from: "alice".to_string(),
+            to: "Bob".to_string(),
+            content: content.to_string(),
+            timestamp: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delivered_to_same_key() {
+        let queue = OfflineQueue::new(Duration::from_secs(60));
+        assert_eq!(queue.queue("bob", message("hi")).await, Err(QueueError::Unknown));
+
+        queue.remember("Bob", Some(&[1; 32])).await;
+        assert_eq!(queue.username("BOB").await.as_deref(), Some("Bob"));
+        queue.queue("bob", message("one")).await.unwrap();
+        queue.queue("BOB", message("two")).await.unwrap();
+
+        // 其他公钥或没有公钥的用户拿不到
+        assert!(queue.take("bob", Some(&[2; 32])).await.is_empty());
+        assert!(queue.take("bob", None).await.is_empty());
+        let delivered = queue.take("Bob", Some(&[1; 32])).await;
+        assert_eq!(delivered, vec![message("one"), message("two")]);
+        assert!(queue.take("Bob", Some(&[1; 32])).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_new_identity_drops_queue() {
+        let queue = OfflineQueue::new(Duration::from_secs(60));
+        queue.remember("bob", Some(&[1; 32])).await;
+        queue.queue("bob", message("for the first bob")).await.unwrap();
+
+        queue.remember("bob", Some(&[2; 32])).await;
+        assert!(queue.take("bob", Some(&[1; 32])).await.is_empty());
+        assert!(queue.take("bob", Some(&[2; 32])).await.is_empty());
+
+        // 没有公钥离开的用户不能接收离线私聊
+        queue.remember("bob", None).await;
+        assert_eq!(queue.queue("bob", message("hi")).await, Err(QueueError::Unknown));
+    }
+
+    #[tokio::test]
+    async fn test_limits_and_expiry() {
+        let queue = OfflineQueue::new(Duration::from_secs(60));
+        queue.remember("bob", Some(&[1; 32])).await;
+        for _ in 0..MAX_OFFLINE_MESSAGES {
+            queue.queue("bob", message("hi")).await.unwrap();
+        }
+        assert_eq!(queue.queue("bob", message("hi")).await, Err(QueueError::Full));
+
+        let expired = OfflineQueue::new(Duration::ZERO);
+        expired.remember("bob", Some(&[1; 32])).await;
+        expired.queue("bob", message("hi")).await.unwrap();
+        assert!(expired.take("bob", Some(&[1; 32])).await.is_empty());
+    }
+}
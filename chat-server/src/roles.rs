@@ -0,0 +1,133 @@
+//! 角色权限
+//!
+//! 服务器没有账号系统，用户名谁先连接就归谁，不能证明身份，因此角色不按用户名分配。
+//! 非默认角色只能凭配置的角色密钥获得：Join 时把角色密钥作为访问密钥发送的用户获得对应角色，
+//! 其他用户获得默认角色。`/role` 修改的角色只在本次连接内有效，改名不会改变已有的角色。
+
+use std::collections::HashMap;
+
+use protocol::Role;
+
+use crate::access::constant_time_eq;
+
+/// 需要权限检查的操作
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Permission {
+    /// 发送聊天消息（包括 /me 和 /msg）
+    Chat,
+    /// 上传文件
+    Upload,
+    /// 修改话题
+    SetTopic,
+    /// 踢出用户
+    Kick,
+    /// 分配角色
+    AssignRoles,
+    /// 签发和撤销邀请码
+    Invite,
+}
+
+impl Permission {
+    /// 权限矩阵：拥有该权限所需的最低角色
+    fn min_role(self) -> Role {
+        match self {
+            Permission::Chat | Permission::Upload => Role::Member,
+            Permission::SetTopic | Permission::Kick | Permission::AssignRoles | Permission::Invite => {
+                Role::Operator
+            }
+        }
+    }
+}
+
+/// 角色密钥
+pub(crate) struct RoleKeys {
+    keys: Vec<(String, Role)>,
+}
+
+impl RoleKeys {
+    /// `keys` 为密钥到角色的映射，空密钥被忽略
+    pub(crate) fn new(keys: &HashMap<String, Role>) -> Self {
+        Self {
+            keys: keys
+                .iter()
+                .filter(|(key, _)| !key.is_empty())
+                .map(|(key, &role)| (key.clone(), role))
+                .collect(),
+        }
+    }
+
+    /// 与访问密钥匹配的角色，没有匹配时返回 None
+    ///
+    /// 与每个密钥逐一比较，不会因为提前返回泄露匹配的是第几个密钥。
+    pub(crate) fn role_for(&self, access_key: Option<&str>) -> Option<Role> {
+        let access_key = access_key?;
+        self.keys.iter().fold(None, |found, (key, role)| {
+            if constant_time_eq(key.as_bytes(), access_key.as_bytes()) {
+                Some(*role)
+            } else {
+                found
+            }
+        })
+    }
+}
+
+/// 角色是否拥有权限
+pub(crate) fn allows(role: Role, permission: Permission) -> bool {
+    role >= permission.min_role()
+}
+
+/// `actor` 能否管理（踢出、修改角色）当前角色为 `target` 的用户
+///
+/// 只能管理角色低于自己的用户；所有者可以管理包括其他所有者在内的所有人。
+pub(crate) fn can_manage(actor: Role, target: Role) -> bool {
+    actor == Role::Owner || target < actor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permission_matrix() {
+        let cases = [
+            (Permission::Chat, Role::Member),
+            (Permission::Upload, Role::Member),
+            (Permission::SetTopic, Role::Operator),
+            (Permission::Kick, Role::Operator),
+            (Permission::AssignRoles, Role::Operator),
+            (Permission::Invite, Role::Operator),
+        ];
+        for (permission, min_role) in cases {
+            for &role in Role::ALL {
+                assert_eq!(allows(role, permission), role >= min_role, "{:?} {:?}", role, permission);
+            }
+        }
+    }
+
+    #[test]
+    fn test_can_manage() {
+        assert!(can_manage(Role::Operator, Role::Member));
+        assert!(can_manage(Role::Operator, Role::Guest));
+        assert!(!can_manage(Role::Operator, Role::Operator));
+        assert!(!can_manage(Role::Operator, Role::Owner));
+        assert!(can_manage(Role::Member, Role::Guest));
+        assert!(!can_manage(Role::Guest, Role::Guest));
+        assert!(can_manage(Role::Owner, Role::Owner));
+    }
+
+    #[test]
+    fn test_role_keys() {
+        let keys = RoleKeys::new(&HashMap::from([
+            ("op-secret".to_string(), Role::Operator),
+            ("guest-key".to_string(), Role::Guest),
+            (String::new(), Role::Owner),
+        ]));
+        assert_eq!(keys.role_for(Some("op-secret")), Some(Role::Operator));
+        assert_eq!(keys.role_for(Some("guest-key")), Some(Role::Guest));
+        // 角色只能凭密钥获得：空密钥、前缀或缺少密钥都不匹配
+        assert_eq!(keys.role_for(Some("")), None);
+        assert_eq!(keys.role_for(Some("op-secre")), None);
+        assert_eq!(keys.role_for(Some("OP-SECRET")), None);
+        assert_eq!(keys.role_for(None), None);
+    }
+}
@@ -1,59 +1,273 @@
 //! 聊天服务器核心实现
 
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
 
 use protocol::{
-    ClientMessage, Connection, ProtocolError, ServerMessage, TcpListener, TcpTransport,
-    TransportListener, HEARTBEAT_TIMEOUT, JOIN_TIMEOUT, MAX_CONNECTIONS,
+    extract_mentions, mention_prefixes, normalize_username, username_key, validate_username, ClientMessage, Codec, CodecKind, Compression, Connection,
+    EncodedFrame, ErrorCode, OnlineUser, ProtocolError, ReadReceipt, Role, SearchHit, ServerMessage, TcpListener, TcpTransport,
+    TransportListener, HEARTBEAT_TIMEOUT, JOIN_TIMEOUT, MAX_CONNECTIONS, MAX_FRAME_SIZE, PUBLIC_KEY_LEN,
 };
-use tokio::sync::{broadcast, watch, RwLock};
+use tokio::sync::{broadcast, mpsc, watch, RwLock};
 use tokio::time::timeout;
+use i18n::t;
 use tracing::{debug, error, info, warn};
 
+use crate::access::Access;
+use crate::commands::{self, CommandContext};
+use crate::config::ServerConfig;
+use crate::files::{FileStore, TransferSession};
+use crate::history::{History, HistoryEntry, SearchQuery};
+use crate::offline::OfflineQueue;
+use crate::roles::{self, Permission, RoleKeys};
+
 /// 广播消息类型
 #[derive(Clone, Debug)]
-pub enum BroadcastMsg {
+pub(crate) enum BroadcastMsg {
     /// 聊天消息
     Chat {
+        message_id: u64,
         username: String,
         content: String,
         timestamp: u64,
+        reply_to: Option<u64>,
+        mentions: Vec<String>,
     },
     /// 用户加入
-    UserJoined { username: String },
+    UserJoined {
+        username: String,
+        role: Role,
+        public_key: Option<Vec<u8>>,
+    },
     /// 用户离开
     UserLeft { username: String },
+    /// `/me` 动作消息
+    Action {
+        message_id: u64,
+        username: String,
+        content: String,
+        timestamp: u64,
+    },
+    /// 用户改名
+    UserRenamed {
+        old_username: String,
+        new_username: String,
+    },
+    /// 角色变更
+    RoleChanged {
+        username: String,
+        role: Role,
+        by: String,
+    },
+    /// 话题变更
+    TopicChanged { topic: String, username: String },
+    /// 用户的已读位置前进
+    ReadReceipt { username: String, up_to: u64 },
+    /// 文件已共享
+    FileShared {
+        file_id: u64,
+        username: String,
+        name: String,
+        size: u64,
+        mime: String,
+        timestamp: u64,
+    },
     /// 服务器关闭
     Shutdown { message: String },
 }
 
+impl BroadcastMsg {
+    /// 需要保存到消息历史的内容
+    fn history_entry(&self) -> Option<HistoryEntry> {
+        match self {
+            BroadcastMsg::Chat { message_id, username, content, timestamp, .. } => Some(HistoryEntry {
+                message_id: *message_id,
+                username: username.clone(),
+                content: content.clone(),
+                timestamp: *timestamp,
+                action: false,
+            }),
+            BroadcastMsg::Action { message_id, username, content, timestamp } => Some(HistoryEntry {
+                message_id: *message_id,
+                username: username.clone(),
+                content: content.clone(),
+                timestamp: *timestamp,
+                action: true,
+            }),
+            _ => None,
+        }
+    }
+
+    /// 转换为发送给客户端的消息
+    fn into_server_message(self) -> ServerMessage {
+        match self {
+            BroadcastMsg::Chat { message_id, username, content, timestamp, reply_to, mentions } => {
+                ServerMessage::ChatBroadcast { message_id, username, content, timestamp, reply_to, mentions }
+            }
+            BroadcastMsg::UserJoined { username, role, public_key } => {
+                ServerMessage::UserJoined { username, role, public_key }
+            }
+            BroadcastMsg::RoleChanged { username, role, by } => ServerMessage::RoleChanged { username, role, by },
+            BroadcastMsg::UserLeft { username } => ServerMessage::UserLeft { username },
+            BroadcastMsg::Action { message_id, username, content, timestamp } => {
+                ServerMessage::ActionBroadcast { message_id, username, content, timestamp }
+            }
+            BroadcastMsg::UserRenamed { old_username, new_username } => {
+                ServerMessage::UserRenamed { old_username, new_username }
+            }
+            BroadcastMsg::TopicChanged { topic, username } => ServerMessage::TopicChanged { topic, username },
+            BroadcastMsg::FileShared { file_id, username, name, size, mime, timestamp } => {
+                ServerMessage::FileShared { file_id, username, name, size, mime, timestamp }
+            }
+            BroadcastMsg::ReadReceipt { username, up_to } => ServerMessage::ReadReceipts {
+                receipts: vec![ReadReceipt { username, up_to }],
+            },
+            BroadcastMsg::Shutdown { message } => ServerMessage::Shutdown { message },
+        }
+    }
+}
+
+/// 已编码的广播帧，所有接收者共享同一份数据
+#[derive(Clone, Debug)]
+struct BroadcastFrame {
+    frame: EncodedFrame,
+    /// 发送后是否关闭连接
+    is_shutdown: bool,
+}
+
+/// 广播发送端
+///
+/// 每条广播只序列化（和压缩）一次，各连接直接发送编码好的帧。
+/// 聊天消息同时写入消息历史。
+#[derive(Clone)]
+pub(crate) struct Broadcaster {
+    tx: broadcast::Sender<BroadcastFrame>,
+    codec: CodecKind,
+    history: Arc<History>,
+}
+
+impl Broadcaster {
+    pub(crate) fn new(codec: CodecKind, history: Arc<History>) -> Self {
+        let (tx, _) = broadcast::channel(256);
+        Self { tx, codec, history }
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<BroadcastFrame> {
+        self.tx.subscribe()
+    }
+
+    /// 编码并广播消息（没有接收者时忽略）
+    pub(crate) fn send(&self, msg: BroadcastMsg) {
+        if let Err(e) = self.try_send(msg) {
+            error!("Failed to encode broadcast: {}", e);
+        }
+    }
+
+    /// 编码并广播消息，编码失败时返回错误，消息不会广播也不会写入历史
+    ///
+    /// 用户发送的内容在编码后可能超过帧大小（如 JSON 中需要转义的字符），调用者应把错误告诉发送者。
+    pub(crate) fn try_send(&self, msg: BroadcastMsg) -> protocol::Result<()> {
+        let is_shutdown = matches!(msg, BroadcastMsg::Shutdown { .. });
+        let entry = msg.history_entry();
+        let frame = EncodedFrame::encode(&self.codec, &msg.into_server_message(), Some(Compression::Lz4))?;
+        if let Some(entry) = entry {
+            self.history.record(entry);
+        }
+        let _ = self.tx.send(BroadcastFrame { frame, is_shutdown });
+        Ok(())
+    }
+}
+
+/// 私聊等只发给单个用户的消息队列容量
+const DIRECT_CHANNEL_CAPACITY: usize = 64;
+
+/// 最多记录的已读位置数量，超过时丢弃最旧的位置
+const MAX_READ_CURSORS: usize = 1024;
+
+/// 加入时发送的已读回执每帧包含的条数（最长的用户名也不会超过帧大小）
+const READ_RECEIPTS_PER_FRAME: usize = 32;
+
+/// 发给单个连接的消息
+#[derive(Debug)]
+pub(crate) enum Direct {
+    /// 转发给客户端的消息
+    Message(ServerMessage),
+    /// 被踢出，`by` 为执行操作的用户
+    Kick { by: String },
+}
+
 /// 用户信息
 #[derive(Debug)]
 struct User {
     username: String,
+    role: Role,
+    /// 加密私聊用的公钥，服务端只负责分发
+    public_key: Option<Vec<u8>>,
+    /// 发给该用户连接的单播消息
+    direct_tx: mpsc::Sender<Direct>,
+}
+
+/// 查找在线用户的结果
+pub(crate) struct UserRef {
+    pub(crate) id: u32,
+    pub(crate) username: String,
+    pub(crate) role: Role,
+    pub(crate) direct_tx: mpsc::Sender<Direct>,
 }
 
 /// 共享状态
-struct SharedState {
+pub(crate) struct SharedState {
     /// 在线用户列表: user_id -> User
     users: RwLock<HashMap<u32, User>>,
-    /// 用户名到 user_id 的映射（用于检查重名）
+    /// 用户名比较键（见 `username_key`）到 user_id 的映射，用于检查重名和易混淆的名字
     usernames: RwLock<HashMap<String, u32>>,
     /// 当前连接数
     connection_count: AtomicU32,
     /// 下一个用户 ID
     next_user_id: AtomicU32,
+    /// 下一个消息 ID
+    next_message_id: AtomicU64,
+    /// 角色密钥
+    role_keys: RoleKeys,
+    /// 没有角色密钥的用户的角色
+    default_role: Role,
+    /// 准入检查与邀请码
+    pub(crate) access: Access,
+    /// 聊天室话题
+    topic: RwLock<Option<String>>,
+    /// 按用户名比较键记录的已读位置，用户离开后保留
+    read_cursors: RwLock<HashMap<String, ReadReceipt>>,
+    /// 每日消息
+    motd: Option<String>,
+    /// 发给离线用户的私聊
+    pub(crate) offline: OfflineQueue,
+    /// 上传文件存储
+    files: Arc<FileStore>,
+    /// 消息历史
+    history: Arc<History>,
+    /// 消息序列化格式
+    codec: CodecKind,
 }
 
 impl SharedState {
-    fn new() -> Self {
+    pub(crate) fn new(config: &ServerConfig) -> Self {
         Self {
             users: RwLock::new(HashMap::new()),
             usernames: RwLock::new(HashMap::new()),
             connection_count: AtomicU32::new(0),
             next_user_id: AtomicU32::new(1),
+            next_message_id: AtomicU64::new(1),
+            role_keys: RoleKeys::new(&config.role_keys),
+            default_role: config.default_role,
+            access: Access::new(config.mode.clone()),
+            topic: RwLock::new(None),
+            read_cursors: RwLock::new(HashMap::new()),
+            motd: config.motd.clone(),
+            offline: OfflineQueue::new(config.offline_ttl),
+            files: Arc::new(FileStore::new(config)),
+            history: Arc::new(History::new(config.history_path.clone())),
+            codec: config.codec,
         }
     }
 
@@ -74,34 +288,194 @@ impl SharedState {
         }
     }
 
+    /// 消息编码后是否不超过帧大小
+    ///
+    /// 用于转发用户内容的单播消息，以免发送时才失败而断开连接。
+    pub(crate) fn fits_frame(&self, msg: &ServerMessage) -> bool {
+        self.codec.encode(msg).is_ok_and(|payload| payload.len() <= MAX_FRAME_SIZE)
+    }
+
+    /// 消息 ID 是否已经分配过
+    fn is_issued(&self, message_id: u64) -> bool {
+        message_id != 0 && message_id < self.next_message_id.load(Ordering::SeqCst)
+    }
+
+    /// 把列表分成若干批，每批由 `make` 构造成编码后不超过帧大小的消息
+    ///
+    /// `make` 的第二个参数表示后面是否还有下一批；检查大小时传入 false（编码不会比 true 短）。
+    /// 至少返回一条消息（列表为空时是空的一批），单独一项也放不下时丢弃该项。
+    pub(crate) fn batches<T: Clone>(&self, items: Vec<T>, make: impl Fn(Vec<T>, bool) -> ServerMessage) -> Vec<ServerMessage> {
+        let mut batches = Vec::new();
+        let mut current = Vec::new();
+        for item in items {
+            current.push(item);
+            if self.fits_frame(&make(current.clone(), false)) {
+                continue;
+            }
+            let item = current.pop().expect("item was just pushed");
+            if !current.is_empty() {
+                batches.push(std::mem::take(&mut current));
+            }
+            current.push(item);
+            if !self.fits_frame(&make(current.clone(), false)) {
+                warn!("Dropping a list item that does not fit in a frame");
+                current.clear();
+            }
+        }
+        if !current.is_empty() || batches.is_empty() {
+            batches.push(current);
+        }
+        let last = batches.len() - 1;
+        batches
+            .into_iter()
+            .enumerate()
+            .map(|(i, batch)| make(batch, i < last))
+            .collect()
+    }
+
+    /// 分配新的消息 ID
+    pub(crate) fn next_message_id(&self) -> u64 {
+        self.next_message_id.fetch_add(1, Ordering::SeqCst)
+    }
+
     /// 减少连接数
     fn remove_connection(&self) {
         self.connection_count.fetch_sub(1, Ordering::SeqCst);
     }
 
     /// 添加用户，成功返回分配的用户 ID，失败返回 None
-    async fn add_user(&self, username: String) -> Option<u32> {
+    ///
+    /// 与在线用户的名字仅大小写不同或易混淆时也视为重名。
+    pub(crate) async fn add_user(
+        &self,
+        username: String,
+        role: Role,
+        public_key: Option<Vec<u8>>,
+        direct_tx: mpsc::Sender<Direct>,
+    ) -> Option<u32> {
+        let key = username_key(&username);
         let mut usernames = self.usernames.write().await;
-        if usernames.contains_key(&username) {
+        if usernames.contains_key(&key) {
             return None;
         }
         // 只有在确认用户名可用后才分配 ID
         let id = self.next_user_id.fetch_add(1, Ordering::SeqCst);
-        usernames.insert(username.clone(), id);
+        usernames.insert(key, id);
         drop(usernames);
 
         let mut users = self.users.write().await;
-        users.insert(id, User { username });
+        users.insert(
+            id,
+            User {
+                username,
+                role,
+                public_key,
+                direct_tx,
+            },
+        );
         Some(id)
     }
 
-    /// 移除用户
+    /// 在线用户的当前角色
+    pub(crate) async fn user_role(&self, id: u32) -> Role {
+        let users = self.users.read().await;
+        users.get(&id).map(|u| u.role).unwrap_or(self.default_role)
+    }
+
+    /// 修改在线用户的角色，只在本次连接内有效
+    ///
+    /// 用户名不能证明身份，按名字保存角色会让下一个使用该名字的人继承它。
+    pub(crate) async fn set_role(&self, id: u32, role: Role) {
+        if let Some(user) = self.users.write().await.get_mut(&id) {
+            user.role = role;
+        }
+    }
+
+    /// 修改用户名，成功返回旧用户名；新名字与其他在线用户冲突时返回 None
+    pub(crate) async fn rename_user(&self, id: u32, new_username: String) -> Option<String> {
+        let new_key = username_key(&new_username);
+        let mut usernames = self.usernames.write().await;
+        if usernames.get(&new_key).is_some_and(|&owner| owner != id) {
+            return None;
+        }
+        let mut users = self.users.write().await;
+        let user = users.get_mut(&id)?;
+        let old_username = std::mem::replace(&mut user.username, new_username.clone());
+        usernames.remove(&username_key(&old_username));
+        usernames.insert(new_key.clone(), id);
+        drop(users);
+        drop(usernames);
+
+        // 已读位置跟随用户改名
+        let mut cursors = self.read_cursors.write().await;
+        if let Some(mut cursor) = cursors.remove(&username_key(&old_username)) {
+            cursor.username = new_username;
+            cursors.insert(new_key, cursor);
+        }
+        Some(old_username)
+    }
+
+    /// 记录用户的已读位置，位置前进或被清除时返回 true
+    ///
+    /// 尚未分配的消息 ID 视为无效，以免一次标记把以后的消息都算作已读；`up_to` 为 0 时清除已读位置。
+    pub(crate) async fn mark_read(&self, username: &str, up_to: u64) -> bool {
+        if up_to != 0 && !self.is_issued(up_to) {
+            return false;
+        }
+        let mut cursors = self.read_cursors.write().await;
+        let key = username_key(username);
+        if up_to == 0 {
+            return cursors.remove(&key).is_some();
+        }
+        if cursors.get(&key).is_some_and(|cursor| cursor.up_to >= up_to) {
+            return false;
+        }
+        if cursors.len() >= MAX_READ_CURSORS && !cursors.contains_key(&key) {
+            if let Some(oldest) = cursors.iter().min_by_key(|(_, c)| c.up_to).map(|(k, _)| k.clone()) {
+                cursors.remove(&oldest);
+            }
+        }
+        cursors.insert(key, ReadReceipt { username: username.to_string(), up_to });
+        true
+    }
+
+    /// 所有用户的已读位置
+    async fn read_receipts(&self) -> Vec<ReadReceipt> {
+        self.read_cursors.read().await.values().cloned().collect()
+    }
+
+    /// 按用户名（比较键相同即可）查找在线用户
+    pub(crate) async fn find_user(&self, username: &str) -> Option<UserRef> {
+        let usernames = self.usernames.read().await;
+        let &id = usernames.get(&username_key(username))?;
+        let users = self.users.read().await;
+        users.get(&id).map(|u| UserRef {
+            id,
+            username: u.username.clone(),
+            role: u.role,
+            direct_tx: u.direct_tx.clone(),
+        })
+    }
+
+    /// 当前话题
+    pub(crate) async fn topic(&self) -> Option<String> {
+        self.topic.read().await.clone()
+    }
+
+    /// 设置话题
+    pub(crate) async fn set_topic(&self, topic: String) {
+        *self.topic.write().await = Some(topic);
+    }
+
+    /// 移除用户，并记下其用户名和公钥以便接收离线私聊
     async fn remove_user(&self, id: u32) -> Option<String> {
         let mut users = self.users.write().await;
         if let Some(user) = users.remove(&id) {
             drop(users);
             let mut usernames = self.usernames.write().await;
-            usernames.remove(&user.username);
+            usernames.remove(&username_key(&user.username));
+            drop(usernames);
+            self.offline.remember(&user.username, user.public_key.as_deref()).await;
             Some(user.username)
         } else {
             None
@@ -113,17 +487,56 @@ impl SharedState {
         self.users.read().await.len()
     }
 
+    /// 解析消息中的 @提及，只保留当前在线的用户（返回其实际用户名）
+    pub(crate) async fn resolve_mentions(&self, content: &str) -> Vec<String> {
+        // 规范化开销较大，在取锁之前算好每个提及的候选比较键（从长到短），不阻塞加入和改名
+        let candidates: Vec<Vec<String>> = extract_mentions(content)
+            .into_iter()
+            .map(|token| mention_prefixes(token).map(username_key).collect())
+            .collect();
+
+        let usernames = self.usernames.read().await;
+        let users = self.users.read().await;
+        let mut mentions: Vec<String> = Vec::new();
+        for keys in &candidates {
+            // 取能匹配在线用户的最长前缀
+            let user = keys
+                .iter()
+                .find_map(|key| usernames.get(key))
+                .and_then(|id| users.get(id));
+            if let Some(user) = user {
+                if !mentions.contains(&user.username) {
+                    mentions.push(user.username.clone());
+                }
+            }
+        }
+        mentions
+    }
+
     /// 获取所有在线用户名列表
-    async fn get_online_usernames(&self) -> Vec<String> {
+    pub(crate) async fn get_online_usernames(&self) -> Vec<String> {
         let users = self.users.read().await;
         users.values().map(|u| u.username.clone()).collect()
     }
+
+    /// 获取所有在线用户及其角色
+    async fn get_online_users(&self) -> Vec<OnlineUser> {
+        let users = self.users.read().await;
+        users
+            .values()
+            .map(|u| OnlineUser {
+                username: u.username.clone(),
+                role: u.role,
+                public_key: u.public_key.clone(),
+            })
+            .collect()
+    }
 }
 
 /// 聊天服务器
 pub struct ChatServer {
     state: Arc<SharedState>,
-    broadcast_tx: broadcast::Sender<BroadcastMsg>,
+    broadcaster: Broadcaster,
     /// 关闭信号发送端
     shutdown_tx: watch::Sender<bool>,
     /// 关闭信号接收端（用于克隆给客户端处理器）
@@ -132,11 +545,16 @@ pub struct ChatServer {
 
 impl ChatServer {
     pub fn new() -> Self {
-        let (broadcast_tx, _) = broadcast::channel(256);
+        Self::with_config(ServerConfig::default())
+    }
+
+    /// 使用指定配置创建服务器
+    pub fn with_config(config: ServerConfig) -> Self {
         let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let state = Arc::new(SharedState::new(&config));
         Self {
-            state: Arc::new(SharedState::new()),
-            broadcast_tx,
+            broadcaster: Broadcaster::new(config.codec, Arc::clone(&state.history)),
+            state,
             shutdown_tx,
             shutdown_rx,
         }
@@ -145,6 +563,15 @@ impl ChatServer {
     /// 运行服务器（支持 graceful shutdown）
     pub async fn run(&self, addr: &str) -> anyhow::Result<()> {
         let listener = TcpListener::bind(addr).await?;
+        self.serve(listener).await
+    }
+
+    /// 在已绑定的监听器上运行服务器（测试中可绑定端口 0 后取得实际地址）
+    pub async fn serve(&self, listener: TcpListener) -> anyhow::Result<()> {
+        self.state.files.init().await?;
+        // 消息 ID 接着历史中的最大 ID 分配，重启后搜索结果仍能对应到唯一的消息
+        let last_message_id = self.state.history.init().await?;
+        self.state.next_message_id.store(last_message_id + 1, Ordering::SeqCst);
         info!("Server listening on {}", listener.local_addr()?);
 
         loop {
@@ -157,23 +584,24 @@ impl ChatServer {
                             if !self.state.try_add_connection() {
                                 warn!("Connection limit reached, rejecting new connection");
                                 // 发送错误消息后关闭
-                                let mut conn = Connection::new(transport);
+                                let mut conn = Connection::with_codec(transport, self.state.codec);
                                 let _ = conn
-                                    .send(&ServerMessage::Error {
-                                        message: "服务器繁忙，请稍后重试".to_string(),
-                                    })
+                                    .send(&ServerMessage::error(
+                                        ErrorCode::ServerBusy,
+                                        t!("server-busy"),
+                                    ))
                                     .await;
                                 continue;
                             }
 
                             let state = Arc::clone(&self.state);
-                            let broadcast_tx = self.broadcast_tx.clone();
-                            let broadcast_rx = self.broadcast_tx.subscribe();
+                            let broadcaster = self.broadcaster.clone();
+                            let broadcast_rx = self.broadcaster.subscribe();
                             let shutdown_rx = self.shutdown_rx.clone();
 
                             tokio::spawn(async move {
                                 if let Err(e) =
-                                    handle_client(transport, state.clone(), broadcast_tx, broadcast_rx, shutdown_rx)
+                                    handle_client(transport, state.clone(), broadcaster, broadcast_rx, shutdown_rx)
                                         .await
                                 {
                                     debug!("Client handler error: {}", e);
@@ -202,8 +630,8 @@ impl ChatServer {
     /// 执行 graceful shutdown
     async fn shutdown(&self) {
         // 广播关闭消息给所有客户端
-        let _ = self.broadcast_tx.send(BroadcastMsg::Shutdown {
-            message: "服务器正在关闭".to_string(),
+        self.broadcaster.send(BroadcastMsg::Shutdown {
+            message: t!("server-shutting-down"),
         });
 
         // 发送关闭信号
@@ -238,60 +666,68 @@ impl Default for ChatServer {
 async fn handle_client(
     transport: TcpTransport,
     state: Arc<SharedState>,
-    broadcast_tx: broadcast::Sender<BroadcastMsg>,
-    mut broadcast_rx: broadcast::Receiver<BroadcastMsg>,
+    broadcaster: Broadcaster,
+    mut broadcast_rx: broadcast::Receiver<BroadcastFrame>,
     mut shutdown_rx: watch::Receiver<bool>,
 ) -> anyhow::Result<()> {
-    let mut conn = Connection::new(transport);
+    let mut conn = Connection::with_codec(transport, state.codec);
+    let (direct_tx, mut direct_rx) = mpsc::channel(DIRECT_CHANNEL_CAPACITY);
 
     // 等待 Join 消息（带超时）
     let join_result = timeout(JOIN_TIMEOUT, conn.recv::<ClientMessage>()).await;
 
-    let (user_id, username) = match join_result {
-        Ok(Ok(ClientMessage::Join { username })) => {
+    let (user_id, mut username, compression, offline_messages) = match join_result {
+        Ok(Ok(ClientMessage::Join { username, compression, access_key, public_key })) => {
+            let username = normalize_username(&username);
+
             // 验证用户名
-            if let Err(e) = (ClientMessage::Join {
-                username: username.clone(),
-            })
-            .validate()
-            {
-                conn.send(&ServerMessage::Error {
-                    message: format!("无效的用户名: {}", e),
-                })
+            if let Err(e) = validate_username(&username) {
+                conn.send(&ServerMessage::error(e.code(), t!("invalid-username", error = e.to_string())))
                 .await?;
                 return Ok(());
             }
 
+            // 检查密码或邀请码
+            // 访问密钥是角色密钥时获得对应角色
+            let key_role = state.role_keys.role_for(access_key.as_deref());
+            if let Err((code, message)) = state.access.check(access_key.as_deref(), key_role).await {
+                info!("User {} was refused: {:?}", username, code);
+                conn.send(&ServerMessage::error(code, message)).await?;
+                return Ok(());
+            }
+
+            if let Some(key) = public_key.as_ref().filter(|k| k.len() != PUBLIC_KEY_LEN) {
+                let e = ProtocolError::InvalidPublicKey { len: key.len() };
+                conn.send(&ServerMessage::error(e.code(), t!("invalid-message", error = e.to_string()))).await?;
+                return Ok(());
+            }
+
             // 尝试添加用户（ID 在内部分配）
-            let user_id = match state.add_user(username.clone()).await {
-                Some(id) => id,
+            let role = key_role.unwrap_or(state.default_role);
+            let user_id = match state.add_user(username.clone(), role, public_key.clone(), direct_tx).await {
+                Some(user) => user,
                 None => {
-                    conn.send(&ServerMessage::Error {
-                        message: "用户名已存在".to_string(),
-                    })
+                    conn.send(&ServerMessage::error(ErrorCode::UsernameTaken, t!("username-taken")))
                     .await?;
                     return Ok(());
                 }
             };
 
-            // 获取当前在线用户列表（包括刚加入的自己）
-            let online_users = state.get_online_usernames().await;
+            // 取出离线期间收到的私聊，等欢迎消息和已读回执发完后投递
+            let offline_messages = state.offline.take(&username, public_key.as_deref()).await;
 
-            // 发送欢迎消息（包含在线用户列表）
-            conn.send(&ServerMessage::Welcome { user_id, online_users }).await?;
-
-            // 广播用户加入
-            let _ = broadcast_tx.send(BroadcastMsg::UserJoined {
+            // 广播用户加入（自己的广播接收端已经订阅，也会收到，但排在欢迎消息之后）
+            broadcaster.send(BroadcastMsg::UserJoined {
                 username: username.clone(),
+                role,
+                public_key,
             });
 
-            info!("User {} (id={}) joined", username, user_id);
-            (user_id, username)
+            info!("User {} (id={}, role={}) joined", username, user_id, role);
+            (user_id, username, Compression::negotiate(&compression), offline_messages)
         }
         Ok(Ok(_)) => {
-            conn.send(&ServerMessage::Error {
-                message: "请先发送 Join 消息".to_string(),
-            })
+            conn.send(&ServerMessage::error(ErrorCode::NotJoined, t!("join-required")))
             .await?;
             return Ok(());
         }
@@ -301,131 +737,500 @@ async fn handle_client(
         }
         Err(_) => {
             debug!("Join timeout");
-            conn.send(&ServerMessage::Error {
-                message: "加入超时".to_string(),
-            })
+            conn.send(&ServerMessage::error(ErrorCode::JoinTimeout, t!("join-timeout")))
             .await?;
             return Ok(());
         }
     };
 
-    // 分离读写
-    let (mut reader, mut writer) = conn.split();
+    // 文件传输状态
+    let mut transfers = TransferSession::new(
+        Arc::clone(&state.files),
+        username.clone(),
+        state.codec.max_chunk_size(),
+    );
 
-    // 主消息循环
-    loop {
-        tokio::select! {
-            // 接收客户端消息（带心跳超时）
-            result = timeout(HEARTBEAT_TIMEOUT, reader.recv::<ClientMessage>()) => {
-                match result {
-                    Ok(Ok(msg)) => {
-                        match msg {
-                            ClientMessage::Chat { content } => {
-                                // 验证消息
-                                if let Err(e) = (ClientMessage::Chat { content: content.clone() }).validate() {
-                                    writer.send(&ServerMessage::Error {
-                                        message: format!("消息无效: {}", e),
-                                    }).await?;
-                                    continue;
-                                }
+    // 用户已经加入：之后的任何错误（如发送失败）都先清理用户再返回，
+    // 否则用户名、文件配额和未完成的上传会一直占用到服务器重启
+    let result: anyhow::Result<()> = async {
+        // 发送欢迎消息和话题，之后的消息（从在线用户列表开始）按协商结果压缩
+        let topic = state.topic().await;
+        conn.send(&ServerMessage::Welcome { user_id, compression, topic }).await?;
+        conn.set_compression(compression);
+
+        let online_users = state.get_online_users().await;
+        for msg in state.batches(online_users, |users, more| ServerMessage::OnlineUsers { users, more }) {
+            conn.send(&msg).await?;
+        }
+
+        if let Some(message) = &state.motd {
+            conn.send(&ServerMessage::Motd { message: message.clone() }).await?;
+        }
+
+        let receipts = state.read_receipts().await;
+        for chunk in receipts.chunks(READ_RECEIPTS_PER_FRAME) {
+            conn.send(&ServerMessage::ReadReceipts { receipts: chunk.to_vec() }).await?;
+        }
 
-                                let timestamp = std::time::SystemTime::now()
-                                    .duration_since(std::time::UNIX_EPOCH)
-                                    .unwrap()
-                                    .as_secs();
+        for message in &offline_messages {
+            conn.send(message).await?;
+        }
 
-                                debug!("User {} sent: {}", username, content);
+        // 分离读写
+        let (mut reader, mut writer) = conn.split();
 
-                                // 广播消息
-                                let _ = broadcast_tx.send(BroadcastMsg::Chat {
-                                    username: username.clone(),
-                                    content,
-                                    timestamp,
-                                });
+        // 主消息循环
+        loop {
+            tokio::select! {
+                // 接收客户端消息（带心跳超时）
+                result = timeout(HEARTBEAT_TIMEOUT, reader.recv::<ClientMessage>()) => {
+                    match result {
+                        Ok(Ok(msg)) => {
+                            // 验证消息
+                            if let Err(e) = msg.validate() {
+                                let reply = match &msg {
+                                    ClientMessage::FileOffer { transfer_id, .. }
+                                    | ClientMessage::FileChunk { transfer_id, .. } => {
+                                        transfers.cancel(*transfer_id).await;
+                                        ServerMessage::FileCancelled {
+                                            transfer_id: *transfer_id,
+                                            code: Some(e.code()),
+                                            reason: t!("invalid-file", error = e.to_string()),
+                                        }
+                                    }
+                                    _ => ServerMessage::error(e.code(), t!("invalid-message", error = e.to_string())),
+                                };
+                                writer.send(&reply).await?;
+                                continue;
                             }
-                            ClientMessage::Ping => {
-                                writer.send(&ServerMessage::Pong).await?;
+
+                            match msg {
+                                ClientMessage::Chat { content, reply_to } => {
+                                    // 以 / 开头的消息作为命令执行，不广播
+                                    if let Some((name, args)) = commands::parse(&content) {
+                                        let mut ctx = CommandContext {
+                                            state: &state,
+                                            broadcaster: &broadcaster,
+                                            user_id,
+                                            username: &mut username,
+                                        };
+                                        let replies = match commands::execute(&mut ctx, name, args).await {
+                                            Ok(replies) => replies,
+                                            Err(e) => vec![e.into()],
+                                        };
+                                        for reply in replies {
+                                            writer.send(&reply).await?;
+                                        }
+                                        continue;
+                                    }
+                                    if !roles::allows(state.user_role(user_id).await, Permission::Chat) {
+                                        writer.send(&ServerMessage::error(ErrorCode::PermissionDenied, t!("permission-denied"))).await?;
+                                        continue;
+                                    }
+                                    if reply_to.is_some_and(|id| !state.is_issued(id)) {
+                                        writer.send(&ServerMessage::error(ErrorCode::ReplyNotFound, t!("reply-not-found"))).await?;
+                                        continue;
+                                    }
+                                    let content = commands::unescape(content);
+
+                                    let timestamp = unix_timestamp();
+                                    let message_id = state.next_message_id();
+                                    let mentions = state.resolve_mentions(&content).await;
+
+                                    debug!("User {} sent #{}: {}", username, message_id, content);
+
+                                    // 广播消息
+                                    let sent = broadcaster.try_send(BroadcastMsg::Chat {
+                                        message_id,
+                                        username: username.clone(),
+                                        content,
+                                        timestamp,
+                                        reply_to,
+                                        mentions,
+                                    });
+                                    if let Err(e) = sent {
+                                        debug!("Dropping message #{} from {}: {}", message_id, username, e);
+                                        writer.send(&ServerMessage::error(ErrorCode::MessageTooLong, t!("message-too-large"))).await?;
+                                    }
+                                }
+                                ClientMessage::EncryptedDirect { to, nonce, ciphertext } => {
+                                    if !roles::allows(state.user_role(user_id).await, Permission::Chat) {
+                                        writer.send(&ServerMessage::error(ErrorCode::PermissionDenied, t!("permission-denied"))).await?;
+                                        continue;
+                                    }
+                                    let Some(recipient) = state.find_user(&to).await else {
+                                        writer.send(&ServerMessage::error(ErrorCode::UserNotFound, t!("user-not-found", user = to.as_str()))).await?;
+                                        continue;
+                                    };
+
+                                    // 服务端无法解密，只转发密文
+                                    let message = ServerMessage::EncryptedDirect {
+                                        message_id: state.next_message_id(),
+                                        from: username.clone(),
+                                        to: recipient.username,
+                                        nonce,
+                                        ciphertext,
+                                        timestamp: unix_timestamp(),
+                                    };
+                                    if !state.fits_frame(&message) {
+                                        writer.send(&ServerMessage::error(ErrorCode::MessageTooLong, t!("message-too-large"))).await?;
+                                        continue;
+                                    }
+                                    // 对方队列已满时告知发送者，不回显未送达的消息
+                                    if recipient.id != user_id && recipient.direct_tx.try_send(Direct::Message(message.clone())).is_err() {
+                                        warn!("Direct message queue for {} is full, dropping message", to);
+                                        writer.send(&ServerMessage::error(ErrorCode::RecipientBusy, t!("recipient-busy", user = to.as_str()))).await?;
+                                        continue;
+                                    }
+                                    writer.send(&message).await?;
+                                }
+                                ClientMessage::Search { query, from_user, since, until, before, limit } => {
+                                    let query = SearchQuery { query, from_user, since, until, before, limit };
+                                    let reply = match state.history.search(query).await {
+                                        Some(Ok(page)) => search_results(&state.codec, before, page.hits, page.more),
+                                        Some(Err(e)) => {
+                                            warn!("History search failed: {}", e);
+                                            ServerMessage::error(ErrorCode::Io, t!("search-failed"))
+                                        }
+                                        None => ServerMessage::error(ErrorCode::SearchUnavailable, t!("search-unavailable")),
+                                    };
+                                    writer.send(&reply).await?;
+                                }
+                                ClientMessage::MarkRead { up_to } => {
+                                    if state.mark_read(&username, up_to).await {
+                                        broadcaster.send(BroadcastMsg::ReadReceipt { username: username.clone(), up_to });
+                                    }
+                                }
+                                ClientMessage::FileOffer { transfer_id, name, size, mime } => {
+                                    let reply = if roles::allows(state.user_role(user_id).await, Permission::Upload) {
+                                        transfers.offer(transfer_id, name, size, mime).await
+                                    } else {
+                                        ServerMessage::FileCancelled {
+                                            transfer_id,
+                                            code: Some(ErrorCode::PermissionDenied),
+                                            reason: t!("permission-denied"),
+                                        }
+                                    };
+                                    writer.send(&reply).await?;
+                                }
+                                ClientMessage::FileChunk { transfer_id, index, data, checksum } => {
+                                    if let Some(reply) = transfers.chunk(transfer_id, index, data, checksum).await {
+                                        writer.send(&reply).await?;
+                                    }
+                                }
+                                ClientMessage::FileComplete { transfer_id } => {
+                                    match transfers.complete(transfer_id).await {
+                                        Ok((file_id, file)) => {
+                                            writer.send(&ServerMessage::FileComplete { transfer_id }).await?;
+                                            broadcaster.send(BroadcastMsg::FileShared {
+                                                file_id,
+                                                username: file.uploader,
+                                                name: file.name,
+                                                size: file.size,
+                                                mime: file.mime,
+                                                timestamp: unix_timestamp(),
+                                            });
+                                        }
+                                        Err(reply) => {
+                                            writer.send(&reply).await?;
+                                        }
+                                    }
+                                }
+                                ClientMessage::FileDownload { transfer_id, file_id } => {
+                                    if let Some(reply) = transfers.download(transfer_id, file_id).await {
+                                        writer.send(&reply).await?;
+                                    }
+                                }
+                                ClientMessage::FileCancel { transfer_id } => {
+                                    transfers.cancel(transfer_id).await;
+                                }
+                                ClientMessage::Ping => {
+                                    writer.send(&ServerMessage::Pong).await?;
+                                }
+                                ClientMessage::Leave => {
+                                    info!("User {} left", username);
+                                    break;
+                                }
+                                ClientMessage::Join { .. } => {
+                                    // 已经加入，忽略重复的 Join
+                                    writer.send(&ServerMessage::error(
+                                        ErrorCode::AlreadyJoined,
+                                        t!("already-joined"),
+                                    )).await?;
+                                }
                             }
-                            ClientMessage::Leave => {
-                                info!("User {} left", username);
+                        }
+                        Ok(Err(ProtocolError::ConnectionClosed)) => {
+                            info!("User {} disconnected", username);
+                            break;
+                        }
+                        Ok(Err(e)) => {
+                            warn!("Error receiving from {}: {}", username, e);
+                            break;
+                        }
+                        Err(_) => {
+                            // 心跳超时
+                            warn!("Heartbeat timeout for user {}", username);
+                            break;
+                        }
+                    }
+                }
+
+                // 接收广播消息
+                result = broadcast_rx.recv() => {
+                    match result {
+                        Ok(broadcast) => {
+                            if let Err(e) = writer.write_encoded(&broadcast.frame).await {
+                                debug!("Failed to send to {}: {}", username, e);
                                 break;
                             }
-                            ClientMessage::Join { .. } => {
-                                // 已经加入，忽略重复的 Join
-                                writer.send(&ServerMessage::Error {
-                                    message: "已经加入聊天室".to_string(),
-                                }).await?;
+
+                            if broadcast.is_shutdown {
+                                info!("Shutdown signal received, closing connection for {}", username);
+                                break;
                             }
                         }
-                    }
-                    Ok(Err(ProtocolError::ConnectionClosed)) => {
-                        info!("User {} disconnected", username);
-                        break;
-                    }
-                    Ok(Err(e)) => {
-                        warn!("Error receiving from {}: {}", username, e);
-                        break;
-                    }
-                    Err(_) => {
-                        // 心跳超时
-                        warn!("Heartbeat timeout for user {}", username);
-                        break;
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            warn!("User {} lagged {} messages", username, n);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            break;
+                        }
                     }
                 }
-            }
 
-            // 接收广播消息
-            result = broadcast_rx.recv() => {
-                match result {
-                    Ok(msg) => {
-                        let (server_msg, should_exit) = match msg {
-                            BroadcastMsg::Chat { username, content, timestamp } => {
-                                (ServerMessage::ChatBroadcast { username, content, timestamp }, false)
-                            }
-                            BroadcastMsg::UserJoined { username } => {
-                                (ServerMessage::UserJoined { username }, false)
-                            }
-                            BroadcastMsg::UserLeft { username } => {
-                                (ServerMessage::UserLeft { username }, false)
-                            }
-                            BroadcastMsg::Shutdown { message } => {
-                                (ServerMessage::Shutdown { message }, true)
+                // 发送私聊等单播消息
+                Some(direct) = direct_rx.recv() => {
+                    match direct {
+                        Direct::Message(msg) => {
+                            if let Err(e) = writer.send(&msg).await {
+                                debug!("Failed to send to {}: {}", username, e);
+                                break;
                             }
-                        };
-
-                        if let Err(e) = writer.send(&server_msg).await {
-                            debug!("Failed to send to {}: {}", username, e);
+                        }
+                        Direct::Kick { by } => {
+                            info!("User {} was kicked by {}", username, by);
+                            let _ = writer.send(&ServerMessage::error(ErrorCode::Kicked, t!("kicked", by = by))).await;
                             break;
                         }
+                    }
+                }
 
-                        if should_exit {
-                            info!("Shutdown signal received, closing connection for {}", username);
+                // 发送下载分块（每轮只发送一块，不阻塞其他消息的收发）
+                _ = std::future::ready(()), if transfers.has_pending_downloads() => {
+                    if let Some(msg) = transfers.next_download_message().await {
+                        if let Err(e) = writer.send(&msg).await {
+                            debug!("Failed to send to {}: {}", username, e);
                             break;
                         }
                     }
-                    Err(broadcast::error::RecvError::Lagged(n)) => {
-                        warn!("User {} lagged {} messages", username, n);
-                    }
-                    Err(broadcast::error::RecvError::Closed) => {
+                }
+
+                // 监听 shutdown 信号
+                _ = shutdown_rx.changed() => {
+                    if *shutdown_rx.borrow() {
+                        info!("Shutdown signal received for {}", username);
                         break;
                     }
                 }
             }
+        }
+
+        Ok(())
+    }
+    .await;
+
+    // 清理未完成的文件传输
+    transfers.abort_all().await;
+
+    // 清理用户
+    if let Some(username) = state.remove_user(user_id).await {
+        broadcaster.send(BroadcastMsg::UserLeft { username });
+    }
+
+    result
+}
+
+/// 构造搜索结果，放不进一帧的结果留到下一页
+fn search_results(codec: &CodecKind, before: Option<u64>, mut hits: Vec<SearchHit>, mut more: bool) -> ServerMessage {
+    loop {
+        let next_before = if more { hits.last().map(|h| h.message_id) } else { None };
+        let msg = ServerMessage::SearchResults { before, hits: hits.clone(), next_before };
+        if codec.encode(&msg).is_ok_and(|payload| payload.len() <= MAX_FRAME_SIZE) {
+            return msg;
+        }
+        let dropped = hits.pop();
+        more = true;
+        if hits.is_empty() {
+            // 单条消息就超过帧大小（JSON 转义后可能出现），跳过它
+            return ServerMessage::SearchResults { before, hits, next_before: dropped.map(|h| h.message_id) };
+        }
+    }
+}
+
+/// 当前 Unix 时间戳（秒）
+pub(crate) fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::{CommandReply, Transport, TransportConfig, MAX_USERNAME_LEN};
+    use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+
+    type TestConnection = Connection<OwnedReadHalf, OwnedWriteHalf, CodecKind>;
+
+    /// 在进程内启动服务器，返回其地址
+    async fn start_server(config: ServerConfig) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let config = ServerConfig {
+            files_dir: std::env::temp_dir().join(format!("chat-server-test-{}", addr.replace([':', '.'], "-"))),
+            ..config
+        };
+        tokio::spawn(async move { ChatServer::with_config(config).serve(listener).await });
+        addr
+    }
 
-            // 监听 shutdown 信号
-            _ = shutdown_rx.changed() => {
-                if *shutdown_rx.borrow() {
-                    info!("Shutdown signal received for {}", username);
+    /// 以原始连接加入，返回连接和在线用户列表
+    async fn join(addr: &str, codec: CodecKind, username: &str, public_key: Option<Vec<u8>>) -> (TestConnection, Vec<OnlineUser>) {
+        let transport = TcpTransport::connect(addr, &TransportConfig::default()).await.unwrap();
+        let mut conn = Connection::with_codec(transport, codec);
+        conn.send(&ClientMessage::Join {
+            username: username.to_string(),
+            compression: Vec::new(),
+            access_key: None,
+            public_key,
+        })
+        .await
+        .unwrap();
+        let msg: ServerMessage = conn.recv().await.unwrap();
+        assert!(matches!(msg, ServerMessage::Welcome { .. }), "{} got {:?}", username, msg);
+        let mut online_users = Vec::new();
+        loop {
+            let ServerMessage::OnlineUsers { users, more } = conn.recv().await.unwrap() else {
+                panic!("expected OnlineUsers");
+            };
+            online_users.extend(users);
+            if !more {
+                return (conn, online_users);
+            }
+        }
+    }
+
+    /// 用户名每个字形最多包含的字符数
+    const MAX_GRAPHEME_CHARS: usize = 8;
+
+    /// 编码后最长的用户名：每个字形都由汉字加上最多的组合符号组成
+    fn long_username(index: usize) -> String {
+        (0..MAX_USERNAME_LEN)
+            .map(|i| {
+                let base = char::from_u32(0x4E00 + (index * MAX_USERNAME_LEN + i) as u32).unwrap();
+                std::iter::once(base).chain(std::iter::repeat_n('\u{3099}', MAX_GRAPHEME_CHARS - 1)).collect::<String>()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_join_with_full_room() {
+        for &codec in CodecKind::ALL {
+            let addr = start_server(ServerConfig { codec, ..ServerConfig::default() }).await;
+            let mut connections = Vec::new();
+            for i in 0..MAX_CONNECTIONS - 1 {
+                let name = long_username(i);
+                validate_username(&name).unwrap();
+                connections.push(join(&addr, codec, &name, Some(vec![i as u8; PUBLIC_KEY_LEN])).await.0);
+            }
+            let (_, online_users) = join(&addr, codec, "last", Some(vec![0; PUBLIC_KEY_LEN])).await;
+            assert_eq!(online_users.len(), MAX_CONNECTIONS, "codec {}", codec);
+            assert!(online_users.iter().all(|u| u.public_key.is_some()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_who_is_split_across_frames() {
+        let codec = CodecKind::Json;
+        let addr = start_server(ServerConfig { codec, ..ServerConfig::default() }).await;
+        let mut connections = Vec::new();
+        for i in 0..MAX_CONNECTIONS - 1 {
+            connections.push(join(&addr, codec, &long_username(i), None).await.0);
+        }
+        let (mut conn, _) = join(&addr, codec, "last", None).await;
+
+        conn.send(&ClientMessage::Chat { content: "/who".to_string(), reply_to: None }).await.unwrap();
+        let mut users = Vec::new();
+        let mut replies = 0;
+        loop {
+            if let ServerMessage::CommandReply { reply: CommandReply::Who { users: batch, more } } = conn.recv().await.unwrap() {
+                users.extend(batch);
+                replies += 1;
+                if !more {
                     break;
                 }
             }
         }
+        assert!(replies > 1);
+        assert_eq!(users.len(), MAX_CONNECTIONS);
     }
 
-    // 清理用户
-    if let Some(username) = state.remove_user(user_id).await {
-        let _ = broadcast_tx.send(BroadcastMsg::UserLeft { username });
+    /// 读取消息直到满足 `done`，返回该消息
+    async fn recv_until(conn: &mut TestConnection, done: impl Fn(&ServerMessage) -> bool) -> ServerMessage {
+        loop {
+            let msg: ServerMessage = conn.recv().await.unwrap();
+            if done(&msg) {
+                return msg;
+            }
+        }
     }
 
-    Ok(())
+    /// 等待 `username` 离开
+    async fn wait_left(conn: &mut TestConnection, username: &str) {
+        recv_until(conn, |msg| matches!(msg, ServerMessage::UserLeft { username: u } if u == username)).await;
+    }
+
+    #[tokio::test]
+    async fn test_offline_direct_message() {
+        let codec = CodecKind::default();
+        let addr = start_server(ServerConfig::default()).await;
+        let (mut alice, _) = join(&addr, codec, "alice", None).await;
+        let bob_key = vec![7u8; PUBLIC_KEY_LEN];
+
+        let (bob, _) = join(&addr, codec, "Bob", Some(bob_key.clone())).await;
+        drop(bob);
+        wait_left(&mut alice, "Bob").await;
+
+        alice.send(&ClientMessage::Chat { content: "/msg bob hello".to_string(), reply_to: None }).await.unwrap();
+        let echo = recv_until(&mut alice, |msg| matches!(msg, ServerMessage::DirectMessage { .. })).await;
+        assert!(matches!(echo, ServerMessage::DirectMessage { ref to, .. } if to == "Bob"));
+        let reply: ServerMessage = alice.recv().await.unwrap();
+        assert_eq!(reply, ServerMessage::CommandReply { reply: CommandReply::OfflineQueued { to: "Bob".to_string() } });
+
+        // 以同一公钥加入时，已读回执之后收到排队的私聊
+        let (mut bob, _) = join(&addr, codec, "bob", Some(bob_key.clone())).await;
+        let msg: ServerMessage = bob.recv().await.unwrap();
+        assert!(
+            matches!(msg, ServerMessage::OfflineDirect { ref from, ref content, .. } if from == "alice" && content == "hello"),
+            "{:?}",
+            msg
+        );
+        drop(bob);
+        wait_left(&mut alice, "bob").await;
+
+        // 使用其他公钥的人拿不到
+        alice.send(&ClientMessage::Chat { content: "/msg bob again".to_string(), reply_to: None }).await.unwrap();
+        recv_until(&mut alice, |msg| matches!(msg, ServerMessage::CommandReply { .. })).await;
+        let (mut impostor, _) = join(&addr, codec, "bob", Some(vec![8u8; PUBLIC_KEY_LEN])).await;
+        impostor.send(&ClientMessage::Ping).await.unwrap();
+        let msg = recv_until(&mut impostor, |msg| matches!(msg, ServerMessage::Pong | ServerMessage::OfflineDirect { .. })).await;
+        assert_eq!(msg, ServerMessage::Pong);
+
+        // 没有以公钥离开过的用户仍然是不在线
+        alice.send(&ClientMessage::Chat { content: "/msg carol hi".to_string(), reply_to: None }).await.unwrap();
+        let msg = recv_until(&mut alice, |msg| matches!(msg, ServerMessage::Error { .. })).await;
+        assert!(matches!(msg, ServerMessage::Error { code: ErrorCode::UserNotFound, .. }));
+    }
 }
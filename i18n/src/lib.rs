@@ -0,0 +1,197 @@
+//! 本地化支持
+//!
+//! 基于 Fluent 消息目录。各程序在启动时用自己的目录调用 `init`，
+//! 之后通过 `t!` 宏取得当前语言的文本:
+//!
+//! ```
+//! i18n::init(i18n::Localizer::new(
+//!     "en-US",
+//!     &[i18n::Catalog { locale: "en-US", source: "hello = Hello, { $name }!" }],
+//! ));
+//! assert_eq!(i18n::t!("hello", name = "alice"), "Hello, alice!");
+//! ```
+//!
+//! 语言按以下顺序决定（见 `detect_locale`）：显式配置、`CHAT_LANG` 环境变量、系统设置，
+//! 都没有时使用 `FALLBACK_LOCALE`。
+
+use std::sync::OnceLock;
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::FluentResource;
+use tracing::warn;
+use unic_langid::LanguageIdentifier;
+
+pub use fluent_bundle::FluentArgs;
+
+/// 找不到匹配语言或条目时使用的语言
+pub const FALLBACK_LOCALE: &str = "en-US";
+
+/// 覆盖系统语言的环境变量
+pub const LANG_ENV: &str = "CHAT_LANG";
+
+/// 一种语言的 Fluent 消息目录
+#[derive(Debug, Clone, Copy)]
+pub struct Catalog {
+    /// 语言标签，如 "zh-CN"
+    pub locale: &'static str,
+    /// FTL 源文本
+    pub source: &'static str,
+}
+
+/// 本地化器：选定语言的消息目录，加上回退语言的目录
+pub struct Localizer {
+    bundle: FluentBundle<FluentResource>,
+    fallback: Option<FluentBundle<FluentResource>>,
+}
+
+impl Localizer {
+    /// 从提供的目录中选择最匹配 `requested` 的语言
+    ///
+    /// 先精确匹配语言标签，再只匹配语种（如 "zh-TW" 匹配 "zh-CN"），
+    /// 都失败时使用 `FALLBACK_LOCALE`。
+    pub fn new(requested: &str, catalogs: &[Catalog]) -> Self {
+        let requested: LanguageIdentifier =
+            requested.parse().unwrap_or_else(|_| fallback_langid());
+        let find = |matches: &dyn Fn(&LanguageIdentifier) -> bool| {
+            catalogs
+                .iter()
+                .find(|c| c.locale.parse().map(|l| matches(&l)).unwrap_or(false))
+        };
+
+        let selected = find(&|l| *l == requested)
+            .or_else(|| find(&|l| l.language == requested.language))
+            .or_else(|| find(&|l| *l == fallback_langid()));
+        let fallback = catalogs
+            .iter()
+            .find(|c| c.locale == FALLBACK_LOCALE)
+            .filter(|c| selected.is_none_or(|s| s.locale != c.locale));
+
+        Self {
+            bundle: selected.map_or_else(|| empty_bundle(fallback_langid()), build_bundle),
+            fallback: fallback.map(build_bundle),
+        }
+    }
+
+    /// 当前语言
+    pub fn locale(&self) -> &LanguageIdentifier {
+        &self.bundle.locales[0]
+    }
+
+    /// 格式化消息，找不到条目时返回消息 ID 本身
+    pub fn format(&self, id: &str, args: Option<&FluentArgs>) -> String {
+        std::iter::once(&self.bundle)
+            .chain(&self.fallback)
+            .find_map(|bundle| format_in(bundle, id, args))
+            .unwrap_or_else(|| id.to_string())
+    }
+}
+
+fn fallback_langid() -> LanguageIdentifier {
+    FALLBACK_LOCALE.parse().expect("valid fallback locale")
+}
+
+fn empty_bundle(locale: LanguageIdentifier) -> FluentBundle<FluentResource> {
+    let mut bundle = FluentBundle::new_concurrent(vec![locale]);
+    // 界面不处理双向文本，不插入 Unicode 隔离符
+    bundle.set_use_isolating(false);
+    bundle
+}
+
+fn build_bundle(catalog: &Catalog) -> FluentBundle<FluentResource> {
+    let locale = catalog.locale.parse().unwrap_or_else(|_| fallback_langid());
+    let mut bundle = empty_bundle(locale);
+    let resource = FluentResource::try_new(catalog.source.to_string()).unwrap_or_else(|(res, errors)| {
+        warn!("Invalid FTL in catalog {}: {:?}", catalog.locale, errors);
+        res
+    });
+    if let Err(errors) = bundle.add_resource(resource) {
+        warn!("Duplicate FTL entries in catalog {}: {:?}", catalog.locale, errors);
+    }
+    bundle
+}
+
+fn format_in(bundle: &FluentBundle<FluentResource>, id: &str, args: Option<&FluentArgs>) -> Option<String> {
+    let pattern = bundle.get_message(id)?.value()?;
+    let mut errors = Vec::new();
+    Some(bundle.format_pattern(pattern, args, &mut errors).into_owned())
+}
+
+/// 决定要使用的语言
+///
+/// 依次检查显式配置、`CHAT_LANG` 环境变量和系统语言。
+pub fn detect_locale(configured: Option<&str>) -> String {
+    configured
+        .map(str::to_string)
+        .or_else(|| std::env::var(LANG_ENV).ok().filter(|s| !s.is_empty()))
+        .or_else(sys_locale::get_locale)
+        .unwrap_or_else(|| FALLBACK_LOCALE.to_string())
+}
+
+static LOCALIZER: OnceLock<Localizer> = OnceLock::new();
+
+/// 设置全局本地化器（只有第一次调用生效）
+pub fn init(localizer: Localizer) {
+    let _ = LOCALIZER.set(localizer);
+}
+
+/// 使用全局本地化器格式化消息，未初始化时返回消息 ID
+pub fn tr(id: &str, args: Option<&FluentArgs>) -> String {
+    match LOCALIZER.get() {
+        Some(localizer) => localizer.format(id, args),
+        None => id.to_string(),
+    }
+}
+
+/// 取得本地化文本: `t!("id")` 或 `t!("id", name = value, ...)`
+#[macro_export]
+macro_rules! t {
+    ($id:expr) => {
+        $crate::tr($id, None)
+    };
+    ($id:expr, $($key:ident = $value:expr),+ $(,)?) => {{
+        let mut args = $crate::FluentArgs::new();
+        $(args.set(stringify!($key), $value);)+
+        $crate::tr($id, Some(&args))
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CATALOGS: &[Catalog] = &[
+        Catalog {
+            locale: "en-US",
+            source: "greeting = Hello, { $name }!\nonly-english = English only",
+        },
+        Catalog {
+            locale: "zh-CN",
+            source: "greeting = 你好，{ $name }！",
+        },
+    ];
+
+    fn args(name: &str) -> FluentArgs<'_> {
+        let mut args = FluentArgs::new();
+        args.set("name", name);
+        args
+    }
+
+    #[test]
+    fn test_exact_and_language_match() {
+        let zh = Localizer::new("zh-CN", CATALOGS);
+        assert_eq!(zh.format("greeting", Some(&args("alice"))), "你好，alice！");
+        let zh_tw = Localizer::new("zh-TW", CATALOGS);
+        assert_eq!(zh_tw.locale().to_string(), "zh-CN");
+    }
+
+    #[test]
+    fn test_fallback() {
+        let fr = Localizer::new("fr-FR", CATALOGS);
+        assert_eq!(fr.format("greeting", Some(&args("bob"))), "Hello, bob!");
+
+        // 缺失的条目回退到英文，再缺失则返回 ID
+        let zh = Localizer::new("zh-CN", CATALOGS);
+        assert_eq!(zh.format("only-english", None), "English only");
+        assert_eq!(zh.format("missing", None), "missing");
+    }
+}
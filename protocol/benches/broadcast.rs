@@ -0,0 +1,125 @@
+//! 广播路径对比：1000 个连接下逐连接编码与一次编码共享帧
+//!
+//! 运行: `cargo bench -p protocol --bench broadcast`
+//!
+//! 除耗时外，还通过计数分配器打印每次广播的分配次数/字节数，
+//! 以及 1000 个空闲 `FrameReader` 占用的缓冲区内存。
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use protocol::{
+    Bincode, Compression, EncodedFrame, FrameReader, FrameWriter, ServerMessage, MAX_FRAME_SIZE,
+};
+use tokio::io::{empty, sink, Empty, Sink};
+use tokio::runtime::Runtime;
+
+/// 连接数
+const CLIENTS: usize = 1000;
+
+/// 统计分配次数和字节数的全局分配器
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// 统计闭包执行期间的分配次数和字节数
+fn measure<T>(f: impl FnOnce() -> T) -> (T, usize, usize) {
+    let count = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    let result = f();
+    (
+        result,
+        ALLOCATIONS.load(Ordering::Relaxed) - count,
+        ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes,
+    )
+}
+
+/// 代表性的聊天广播消息
+fn sample_broadcast() -> ServerMessage {
+    ServerMessage::ChatBroadcast {
+        message_id: 123_456,
+        username: "alice".to_string(),
+        content: "Deploy finished, @bob please verify the staging environment 🚀".to_string(),
+        timestamp: 1_700_000_000,
+        reply_to: Some(123_400),
+        mentions: vec!["bob".to_string()],
+    }
+}
+
+/// 半数连接协商了压缩，模拟混合客户端
+fn writers() -> Vec<FrameWriter<Sink>> {
+    (0..CLIENTS)
+        .map(|i| {
+            let mut writer = FrameWriter::new(sink());
+            if i % 2 == 0 {
+                writer.set_compression(Some(Compression::Lz4));
+            }
+            writer
+        })
+        .collect()
+}
+
+/// 旧做法：每个连接各自序列化
+async fn broadcast_per_recipient(writers: &mut [FrameWriter<Sink>], msg: &ServerMessage) {
+    for writer in writers {
+        writer.write_frame(msg).await.unwrap();
+    }
+}
+
+/// 新做法：编码一次，所有连接共享同一帧
+async fn broadcast_encoded_once(writers: &mut [FrameWriter<Sink>], msg: &ServerMessage) {
+    let frame = EncodedFrame::encode(&Bincode, msg, Some(Compression::Lz4)).unwrap();
+    for writer in writers {
+        writer.write_encoded(&frame).await.unwrap();
+    }
+}
+
+fn bench_broadcast(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let msg = sample_broadcast();
+    let mut writers = writers();
+
+    let (_, count, bytes) = measure(|| rt.block_on(broadcast_per_recipient(&mut writers, &msg)));
+    println!("per-recipient: {count} allocations, {bytes} bytes per broadcast to {CLIENTS} clients");
+    let (_, count, bytes) = measure(|| rt.block_on(broadcast_encoded_once(&mut writers, &msg)));
+    println!("encoded-once:  {count} allocations, {bytes} bytes per broadcast to {CLIENTS} clients");
+
+    let (readers, count, bytes) = measure(|| {
+        (0..CLIENTS)
+            .map(|_| FrameReader::new(empty()))
+            .collect::<Vec<FrameReader<Empty>>>()
+    });
+    println!(
+        "{CLIENTS} idle readers: {count} allocations, {bytes} bytes (previously >= {} bytes of buffers)",
+        CLIENTS * MAX_FRAME_SIZE
+    );
+    drop(readers);
+
+    c.bench_function("broadcast/per_recipient/1000", |b| {
+        b.iter(|| rt.block_on(broadcast_per_recipient(&mut writers, black_box(&msg))))
+    });
+    c.bench_function("broadcast/encoded_once/1000", |b| {
+        b.iter(|| rt.block_on(broadcast_encoded_once(&mut writers, black_box(&msg))))
+    });
+}
+
+criterion_group!(benches, bench_broadcast);
+criterion_main!(benches);
@@ -0,0 +1,39 @@
+//! 序列化格式对比：编码后大小与编解码耗时
+//!
+//! 运行: `cargo bench -p protocol --bench codecs`
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use protocol::{Codec, CodecKind, ServerMessage};
+
+/// 代表性的聊天广播消息
+fn sample_broadcast() -> ServerMessage {
+    ServerMessage::ChatBroadcast {
+        message_id: 123_456,
+        username: "alice".to_string(),
+        content: "Deploy finished, @bob please verify the staging environment 🚀".to_string(),
+        timestamp: 1_700_000_000,
+        reply_to: Some(123_400),
+        mentions: vec!["bob".to_string()],
+    }
+}
+
+fn bench_codecs(c: &mut Criterion) {
+    let msg = sample_broadcast();
+
+    for codec in CodecKind::ALL {
+        let bytes = codec.encode(&msg).unwrap();
+        println!("{:<8} encoded size: {} bytes", codec.name(), bytes.len());
+
+        c.bench_function(&format!("encode/{}", codec.name()), |b| {
+            b.iter(|| codec.encode(black_box(&msg)).unwrap())
+        });
+        c.bench_function(&format!("decode/{}", codec.name()), |b| {
+            b.iter(|| codec.decode::<ServerMessage>(black_box(&bytes)).unwrap())
+        });
+    }
+}
+
+criterion_group!(benches, bench_codecs);
+criterion_main!(benches);
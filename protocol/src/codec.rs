@@ -3,52 +3,77 @@
 //! 帧格式:
 //! ```text
 //! ┌────────────┬────────────────┬────────────────────────────────┐
-//! │ Version(1B)│  Length (4B)   │         Payload (bincode)      │
+//! │ Version(1B)│  Length (4B)   │         Payload (Codec)        │
 //! │    u8      │    u32 BE      │         Message enum           │
 //! └────────────┴────────────────┴────────────────────────────────┘
 //! ```
+//!
+//! Version 字节的最高位为压缩标志，置位时负载为 LZ4 压缩数据（见 `compression`），
+//! 低 7 位为协议版本号。`MAX_FRAME_SIZE` 同时限制压缩后和解压后的大小。
+//!
+//! 负载的序列化格式由 `Codec` 决定，默认为 bincode。
+//!
+//! 需要发送给多个连接的消息（如广播）可以用 `EncodedFrame` 预先编码一次，
+//! 各连接通过 `FrameWriter::write_encoded` 共享同一份数据发送。
 
+use bytes::{BufMut, Bytes, BytesMut};
 use serde::{de::DeserializeOwned, Serialize};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
+use crate::compression::Compression;
 use crate::error::{ProtocolError, Result};
-use crate::{MAX_FRAME_SIZE, PROTOCOL_VERSION};
+use crate::format::{Bincode, Codec};
+use crate::{COMPRESSION_THRESHOLD, MAX_FRAME_SIZE, PROTOCOL_VERSION};
 
 /// 帧头大小: 1 字节版本 + 4 字节长度
 const HEADER_SIZE: usize = 5;
 
+/// Version 字节中的压缩标志位
+const FLAG_COMPRESSED: u8 = 0x80;
+
 /// 帧读取器
-pub struct FrameReader<R> {
+///
+/// 读取进度保存在读取器内部，`read_frame` 可以安全地在 `tokio::select!` 中被取消，
+/// 下次调用会从中断处继续读取。
+///
+/// 缓冲区按实际帧大小增长并在后续帧中复用，空闲连接不占用 `MAX_FRAME_SIZE` 的内存。
+pub struct FrameReader<R, C = Bincode> {
     reader: R,
     buffer: Vec<u8>,
+    /// buffer 中已读取的字节数（包括帧头）
+    filled: usize,
+    /// 解压缓冲区（复用）
+    scratch: Vec<u8>,
+    codec: C,
 }
 
 impl<R: AsyncRead + Unpin> FrameReader<R> {
-    /// 创建新的帧读取器
+    /// 创建新的帧读取器（bincode 格式）
     pub fn new(reader: R) -> Self {
+        Self::with_codec(reader, Bincode)
+    }
+}
+
+impl<R: AsyncRead + Unpin, C: Codec> FrameReader<R, C> {
+    /// 创建使用指定序列化格式的帧读取器
+    pub fn with_codec(reader: R, codec: C) -> Self {
         Self {
             reader,
-            buffer: Vec::with_capacity(MAX_FRAME_SIZE),
+            buffer: Vec::new(),
+            filled: 0,
+            scratch: Vec::new(),
+            codec,
         }
     }
 
     /// 读取并解码一帧消息
     pub async fn read_frame<M: DeserializeOwned>(&mut self) -> Result<M> {
         // 读取帧头
-        let mut header = [0u8; HEADER_SIZE];
-        self.reader
-            .read_exact(&mut header)
-            .await
-            .map_err(|e| {
-                if e.kind() == std::io::ErrorKind::UnexpectedEof {
-                    ProtocolError::ConnectionClosed
-                } else {
-                    ProtocolError::Io(e)
-                }
-            })?;
+        self.fill_to(HEADER_SIZE).await?;
 
-        // 解析版本号
-        let version = header[0];
+        // 解析压缩标志和版本号
+        let compressed = self.buffer[0] & FLAG_COMPRESSED != 0;
+        let version = self.buffer[0] & !FLAG_COMPRESSED;
         if version != PROTOCOL_VERSION {
             return Err(ProtocolError::VersionMismatch {
                 expected: PROTOCOL_VERSION,
@@ -57,7 +82,12 @@ impl<R: AsyncRead + Unpin> FrameReader<R> {
         }
 
         // 解析长度（大端序）
-        let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        let length = u32::from_be_bytes([
+            self.buffer[1],
+            self.buffer[2],
+            self.buffer[3],
+            self.buffer[4],
+        ]) as usize;
 
         // 检查帧大小
         if length > MAX_FRAME_SIZE {
@@ -67,26 +97,36 @@ impl<R: AsyncRead + Unpin> FrameReader<R> {
             });
         }
 
-        // 读取消息体（仅在需要时扩容）
-        if self.buffer.len() < length {
-            self.buffer.resize(length, 0);
-        }
-        self.reader
-            .read_exact(&mut self.buffer[..length])
-            .await
-            .map_err(|e| {
-                if e.kind() == std::io::ErrorKind::UnexpectedEof {
-                    ProtocolError::ConnectionClosed
-                } else {
-                    ProtocolError::Io(e)
-                }
-            })?;
+        // 读取消息体
+        self.fill_to(HEADER_SIZE + length).await?;
+        self.filled = 0;
 
-        // 反序列化
-        let msg = bincode::deserialize(&self.buffer[..length])?;
+        // 解压并反序列化
+        let payload = &self.buffer[HEADER_SIZE..HEADER_SIZE + length];
+        let msg = if compressed {
+            Compression::Lz4.decompress_into(payload, &mut self.scratch)?;
+            self.codec.decode(&self.scratch)?
+        } else {
+            self.codec.decode(payload)?
+        };
         Ok(msg)
     }
 
+    /// 读取数据直到 buffer 中至少有 `len` 字节（仅在需要时扩容）
+    async fn fill_to(&mut self, len: usize) -> Result<()> {
+        if self.buffer.len() < len {
+            self.buffer.resize(len, 0);
+        }
+        while self.filled < len {
+            let n = self.reader.read(&mut self.buffer[self.filled..len]).await?;
+            if n == 0 {
+                return Err(ProtocolError::ConnectionClosed);
+            }
+            self.filled += n;
+        }
+        Ok(())
+    }
+
     /// 接收消息（read_frame 的别名）
     pub async fn recv<M: DeserializeOwned>(&mut self) -> Result<M> {
         self.read_frame().await
@@ -94,33 +134,55 @@ impl<R: AsyncRead + Unpin> FrameReader<R> {
 }
 
 /// 帧写入器
-pub struct FrameWriter<W> {
+pub struct FrameWriter<W, C = Bincode> {
     writer: W,
+    /// 协商后的压缩算法，None 表示不压缩
+    compression: Option<Compression>,
+    codec: C,
 }
 
 impl<W: AsyncWrite + Unpin> FrameWriter<W> {
-    /// 创建新的帧写入器
+    /// 创建新的帧写入器（bincode 格式，默认不压缩）
     pub fn new(writer: W) -> Self {
-        Self { writer }
+        Self::with_codec(writer, Bincode)
+    }
+}
+
+impl<W: AsyncWrite + Unpin, C: Codec> FrameWriter<W, C> {
+    /// 创建使用指定序列化格式的帧写入器（默认不压缩）
+    pub fn with_codec(writer: W, codec: C) -> Self {
+        Self {
+            writer,
+            compression: None,
+            codec,
+        }
+    }
+
+    /// 使用的序列化格式
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    /// 设置压缩算法，超过 `COMPRESSION_THRESHOLD` 的帧会被压缩
+    pub fn set_compression(&mut self, compression: Option<Compression>) {
+        self.compression = compression;
     }
 
     /// 编码并写入一帧消息
     pub async fn write_frame<M: Serialize>(&mut self, msg: &M) -> Result<()> {
         // 序列化消息
-        let payload = bincode::serialize(msg)?;
+        let payload = encode_payload(&self.codec, msg)?;
 
-        // 检查大小
-        if payload.len() > MAX_FRAME_SIZE {
-            return Err(ProtocolError::FrameTooLarge {
-                size: payload.len(),
-                max: MAX_FRAME_SIZE,
-            });
-        }
+        // 超过阈值且压缩有效时才使用压缩负载
+        let (version, payload) = match self.compression.and_then(|c| compress_payload(c, &payload)) {
+            Some(compressed) => (PROTOCOL_VERSION | FLAG_COMPRESSED, compressed),
+            None => (PROTOCOL_VERSION, payload),
+        };
 
         // 构造帧头
         let length = payload.len() as u32;
         let mut header = [0u8; HEADER_SIZE];
-        header[0] = PROTOCOL_VERSION;
+        header[0] = version;
         header[1..5].copy_from_slice(&length.to_be_bytes());
 
         // 写入帧头和消息体
@@ -131,16 +193,96 @@ impl<W: AsyncWrite + Unpin> FrameWriter<W> {
         Ok(())
     }
 
+    /// 写入预编码的帧，按本连接的压缩设置选择压缩或原始版本
+    ///
+    /// 帧必须使用与本写入器相同的序列化格式编码。
+    pub async fn write_encoded(&mut self, frame: &EncodedFrame) -> Result<()> {
+        self.writer.write_all(frame.bytes_for(self.compression)).await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
     /// 发送消息（write_frame 的别名）
     pub async fn send<M: Serialize>(&mut self, msg: &M) -> Result<()> {
         self.write_frame(msg).await
     }
 }
 
+/// 预编码的完整帧（帧头 + 负载）
+///
+/// 克隆只增加引用计数，适合一次编码、多个连接发送的广播场景。
+#[derive(Clone, Debug)]
+pub struct EncodedFrame {
+    /// 未压缩的帧
+    plain: Bytes,
+    /// 压缩后的帧（仅在压缩有效时生成）
+    compressed: Option<(Compression, Bytes)>,
+}
+
+impl EncodedFrame {
+    /// 编码消息
+    ///
+    /// `compression` 不为 None 时，若负载超过阈值且压缩有效，会同时生成压缩版本，
+    /// 供协商了该算法的连接使用。
+    pub fn encode<M: Serialize, C: Codec>(
+        codec: &C,
+        msg: &M,
+        compression: Option<Compression>,
+    ) -> Result<Self> {
+        let payload = encode_payload(codec, msg)?;
+        let compressed = compression.and_then(|c| {
+            compress_payload(c, &payload)
+                .map(|p| (c, build_frame(PROTOCOL_VERSION | FLAG_COMPRESSED, &p)))
+        });
+        Ok(Self {
+            plain: build_frame(PROTOCOL_VERSION, &payload),
+            compressed,
+        })
+    }
+
+    /// 按连接的压缩设置选择要发送的数据
+    pub fn bytes_for(&self, compression: Option<Compression>) -> &Bytes {
+        match (&self.compressed, compression) {
+            (Some((c, bytes)), Some(negotiated)) if *c == negotiated => bytes,
+            _ => &self.plain,
+        }
+    }
+}
+
+/// 序列化消息并检查帧大小
+fn encode_payload<M: Serialize, C: Codec>(codec: &C, msg: &M) -> Result<Vec<u8>> {
+    let payload = codec.encode(msg)?;
+    if payload.len() > MAX_FRAME_SIZE {
+        return Err(ProtocolError::FrameTooLarge {
+            size: payload.len(),
+            max: MAX_FRAME_SIZE,
+        });
+    }
+    Ok(payload)
+}
+
+/// 压缩负载，仅在超过阈值且压缩后更小时返回
+fn compress_payload(compression: Compression, payload: &[u8]) -> Option<Vec<u8>> {
+    if payload.len() <= COMPRESSION_THRESHOLD {
+        return None;
+    }
+    let compressed = compression.compress(payload);
+    (compressed.len() < payload.len()).then_some(compressed)
+}
+
+/// 构造完整帧
+fn build_frame(version: u8, payload: &[u8]) -> Bytes {
+    let mut frame = BytesMut::with_capacity(HEADER_SIZE + payload.len());
+    frame.put_u8(version);
+    frame.put_u32(payload.len() as u32);
+    frame.extend_from_slice(payload);
+    frame.freeze()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{ClientMessage, ServerMessage};
+    use crate::{ClientMessage, Json, ServerMessage};
     use std::io::Cursor;
 
     #[tokio::test]
@@ -153,6 +295,9 @@ mod tests {
             let mut writer = FrameWriter::new(&mut buffer);
             let msg = ClientMessage::Join {
                 username: "test_user".to_string(),
+                compression: vec![],
+                access_key: None,
+                public_key: None,
             };
             writer.write_frame(&msg).await.unwrap();
         }
@@ -164,12 +309,126 @@ mod tests {
             assert_eq!(
                 msg,
                 ClientMessage::Join {
-                    username: "test_user".to_string()
+                    username: "test_user".to_string(),
+                    compression: vec![],
+                    access_key: None,
+                    public_key: None,
                 }
             );
         }
     }
 
+    #[tokio::test]
+    async fn test_read_frame_resumes_after_cancel() {
+        let mut buffer = Vec::new();
+        FrameWriter::new(&mut buffer)
+            .write_frame(&ClientMessage::Ping)
+            .await
+            .unwrap();
+
+        // 帧分两次到达，第一次读取在中途被取消
+        let (mut tx, server) = tokio::io::duplex(64);
+        let split_at = 3;
+        let mut reader = FrameReader::new(server);
+        tx.write_all(&buffer[..split_at]).await.unwrap();
+        let cancelled = tokio::time::timeout(
+            std::time::Duration::from_millis(10),
+            reader.read_frame::<ClientMessage>(),
+        )
+        .await;
+        assert!(cancelled.is_err());
+
+        tx.write_all(&buffer[split_at..]).await.unwrap();
+        let msg: ClientMessage = reader.read_frame().await.unwrap();
+        assert_eq!(msg, ClientMessage::Ping);
+    }
+
+    #[tokio::test]
+    async fn test_compressed_frame_roundtrip() {
+        let msg = ClientMessage::Chat {
+            content: "hello ".repeat(500),
+            reply_to: None,
+        };
+
+        let mut plain = Vec::new();
+        FrameWriter::new(&mut plain).write_frame(&msg).await.unwrap();
+
+        let mut compressed = Vec::new();
+        {
+            let mut writer = FrameWriter::new(&mut compressed);
+            writer.set_compression(Some(Compression::Lz4));
+            writer.write_frame(&msg).await.unwrap();
+        }
+        assert!(compressed[0] & FLAG_COMPRESSED != 0);
+        assert!(compressed.len() < plain.len());
+
+        let mut reader = FrameReader::new(Cursor::new(&compressed));
+        let decoded: ClientMessage = reader.read_frame().await.unwrap();
+        assert_eq!(decoded, msg);
+    }
+
+    #[tokio::test]
+    async fn test_json_frame_roundtrip() {
+        let mut buffer = Vec::new();
+        FrameWriter::with_codec(&mut buffer, Json)
+            .write_frame(&ClientMessage::Ping)
+            .await
+            .unwrap();
+        assert_eq!(&buffer[HEADER_SIZE..], br#""Ping""#);
+
+        let mut reader = FrameReader::with_codec(Cursor::new(&buffer), Json);
+        let msg: ClientMessage = reader.read_frame().await.unwrap();
+        assert_eq!(msg, ClientMessage::Ping);
+    }
+
+    #[tokio::test]
+    async fn test_encoded_frame_matches_write_frame() {
+        let msg = ClientMessage::Chat {
+            content: "hello ".repeat(500),
+            reply_to: None,
+        };
+        let frame = EncodedFrame::encode(&Bincode, &msg, Some(Compression::Lz4)).unwrap();
+
+        for compression in [None, Some(Compression::Lz4)] {
+            let mut expected = Vec::new();
+            let mut writer = FrameWriter::new(&mut expected);
+            writer.set_compression(compression);
+            writer.write_frame(&msg).await.unwrap();
+
+            let mut actual = Vec::new();
+            let mut writer = FrameWriter::new(&mut actual);
+            writer.set_compression(compression);
+            writer.write_encoded(&frame).await.unwrap();
+
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_small_frame_not_compressed() {
+        let mut buffer = Vec::new();
+        let mut writer = FrameWriter::new(&mut buffer);
+        writer.set_compression(Some(Compression::Lz4));
+        writer.write_frame(&ClientMessage::Ping).await.unwrap();
+        assert_eq!(buffer[0], PROTOCOL_VERSION);
+    }
+
+    #[tokio::test]
+    async fn test_decompression_bomb_rejected() {
+        // 声明的解压大小超过 MAX_FRAME_SIZE
+        let mut payload = ((MAX_FRAME_SIZE * 100) as u32).to_le_bytes().to_vec();
+        payload.extend_from_slice(&lz4_flex::block::compress(&vec![0u8; MAX_FRAME_SIZE * 100]));
+        assert!(payload.len() <= MAX_FRAME_SIZE);
+
+        let mut frame = vec![PROTOCOL_VERSION | FLAG_COMPRESSED];
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&payload);
+
+        let mut reader = FrameReader::new(Cursor::new(&frame));
+        let result: Result<ClientMessage> = reader.read_frame().await;
+        assert!(matches!(result, Err(ProtocolError::FrameTooLarge { .. })));
+    }
+
     #[tokio::test]
     async fn test_server_message_frame() {
         let mut buffer = Vec::new();
@@ -177,9 +436,12 @@ mod tests {
         {
             let mut writer = FrameWriter::new(&mut buffer);
             let msg = ServerMessage::ChatBroadcast {
+                message_id: 1,
                 username: "alice".to_string(),
                 content: "Hello, world!".to_string(),
                 timestamp: 1234567890,
+                reply_to: None,
+                mentions: vec![],
             };
             writer.write_frame(&msg).await.unwrap();
         }
@@ -189,10 +451,15 @@ mod tests {
             let msg: ServerMessage = reader.read_frame().await.unwrap();
             match msg {
                 ServerMessage::ChatBroadcast {
+                    message_id,
                     username,
                     content,
                     timestamp,
+                    reply_to,
+                    ..
                 } => {
+                    assert_eq!(message_id, 1);
+                    assert_eq!(reply_to, None);
                     assert_eq!(username, "alice");
                     assert_eq!(content, "Hello, world!");
                     assert_eq!(timestamp, 1234567890);
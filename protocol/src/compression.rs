@@ -0,0 +1,105 @@
+//! 帧负载压缩
+//!
+//! 压缩负载格式: 4 字节小端序原始长度 + LZ4 block 数据。
+//! 解压前先检查原始长度，防止解压炸弹。
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ProtocolError, Result};
+use crate::MAX_FRAME_SIZE;
+
+/// 原始长度前缀大小
+const SIZE_PREFIX_LEN: usize = 4;
+
+/// 压缩算法
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// LZ4 block 格式
+    Lz4,
+}
+
+impl Compression {
+    /// 本端支持的压缩算法（按优先级排序）
+    pub const SUPPORTED: &'static [Compression] = &[Compression::Lz4];
+
+    /// 从对端提供的算法列表中选出双方都支持的算法
+    pub fn negotiate(offered: &[Compression]) -> Option<Compression> {
+        Self::SUPPORTED
+            .iter()
+            .copied()
+            .find(|c| offered.contains(c))
+    }
+
+    /// 压缩负载
+    pub(crate) fn compress(self, payload: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::Lz4 => {
+                let mut out = Vec::with_capacity(SIZE_PREFIX_LEN + payload.len());
+                out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+                out.extend_from_slice(&lz4_flex::block::compress(payload));
+                out
+            }
+        }
+    }
+
+    /// 解压负载到 `out`（复用其容量），解压后大小不得超过 MAX_FRAME_SIZE
+    pub(crate) fn decompress_into(self, payload: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        match self {
+            Compression::Lz4 => {
+                if payload.len() < SIZE_PREFIX_LEN {
+                    return Err(ProtocolError::Decompression("missing size prefix".to_string()));
+                }
+                let (prefix, data) = payload.split_at(SIZE_PREFIX_LEN);
+                let size = u32::from_le_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as usize;
+                if size > MAX_FRAME_SIZE {
+                    return Err(ProtocolError::FrameTooLarge {
+                        size,
+                        max: MAX_FRAME_SIZE,
+                    });
+                }
+                out.clear();
+                out.resize(size, 0);
+                let n = lz4_flex::block::decompress_into(data, out)
+                    .map_err(|e| ProtocolError::Decompression(e.to_string()))?;
+                if n != size {
+                    return Err(ProtocolError::Decompression(format!(
+                        "expected {} bytes, got {}",
+                        size, n
+                    )));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lz4_roundtrip() {
+        let payload = b"hello hello hello hello hello hello".repeat(20);
+        let compressed = Compression::Lz4.compress(&payload);
+        assert!(compressed.len() < payload.len());
+        let mut out = Vec::new();
+        Compression::Lz4.decompress_into(&compressed, &mut out).unwrap();
+        assert_eq!(out, payload);
+    }
+
+    #[test]
+    fn test_lz4_rejects_oversized_prefix() {
+        let mut payload = ((MAX_FRAME_SIZE + 1) as u32).to_le_bytes().to_vec();
+        payload.extend_from_slice(&[0u8; 16]);
+        assert!(matches!(
+            Compression::Lz4.decompress_into(&payload, &mut Vec::new()),
+            Err(ProtocolError::FrameTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Compression::negotiate(&[Compression::Lz4]), Some(Compression::Lz4));
+        assert_eq!(Compression::negotiate(&[]), None);
+    }
+}
@@ -6,7 +6,9 @@ use serde::{de::DeserializeOwned, Serialize};
 use tokio::io::{AsyncRead, AsyncWrite};
 
 use crate::codec::{FrameReader, FrameWriter};
+use crate::compression::Compression;
 use crate::error::Result;
+use crate::format::{Bincode, Codec};
 use crate::transport::Transport;
 
 /// 连接封装
@@ -16,33 +18,48 @@ use crate::transport::Transport;
 /// # Type Parameters
 /// * `R` - 读取端类型
 /// * `W` - 写入端类型
-pub struct Connection<R, W> {
-    reader: FrameReader<R>,
-    writer: FrameWriter<W>,
+/// * `C` - 序列化格式，默认为 bincode
+pub struct Connection<R, W, C = Bincode> {
+    reader: FrameReader<R, C>,
+    writer: FrameWriter<W, C>,
 }
 
 impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> Connection<R, W> {
-    /// 从传输层创建连接
+    /// 从传输层创建连接（bincode 格式）
     pub fn new<T: Transport<Reader = R, Writer = W>>(transport: T) -> Self {
-        let (reader, writer) = transport.split();
-        Self {
-            reader: FrameReader::new(reader),
-            writer: FrameWriter::new(writer),
-        }
+        Self::with_codec(transport, Bincode)
     }
 
-    /// 从读写端直接创建连接
+    /// 从读写端直接创建连接（bincode 格式）
     pub fn from_parts(reader: R, writer: W) -> Self {
+        Self::from_parts_with_codec(reader, writer, Bincode)
+    }
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin, C: Codec> Connection<R, W, C> {
+    /// 从传输层创建使用指定序列化格式的连接
+    pub fn with_codec<T: Transport<Reader = R, Writer = W>>(transport: T, codec: C) -> Self {
+        let (reader, writer) = transport.split();
+        Self::from_parts_with_codec(reader, writer, codec)
+    }
+
+    /// 从读写端直接创建使用指定序列化格式的连接
+    pub fn from_parts_with_codec(reader: R, writer: W, codec: C) -> Self {
         Self {
-            reader: FrameReader::new(reader),
-            writer: FrameWriter::new(writer),
+            reader: FrameReader::with_codec(reader, codec.clone()),
+            writer: FrameWriter::with_codec(writer, codec),
         }
     }
 
+    /// 设置发送方向的压缩算法（接收方向总是自动解压）
+    pub fn set_compression(&mut self, compression: Option<Compression>) {
+        self.writer.set_compression(compression);
+    }
+
     /// 分离为读取端和写入端
     ///
     /// 用于需要并发读写的场景
-    pub fn split(self) -> (FrameReader<R>, FrameWriter<W>) {
+    pub fn split(self) -> (FrameReader<R, C>, FrameWriter<W, C>) {
         (self.reader, self.writer)
     }
 
@@ -60,7 +77,7 @@ impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> Connection<R, W> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{ClientMessage, ServerMessage, TcpListener, TcpTransport, TransportConfig, TransportListener};
+    use crate::{ClientMessage, OnlineUser, Role, ServerMessage, TcpListener, TcpTransport, TransportConfig, TransportListener};
 
     #[tokio::test]
     async fn test_connection_send_recv() {
@@ -79,6 +96,9 @@ mod tests {
             // 发送消息
             conn.send(&ClientMessage::Join {
                 username: "test".to_string(),
+                compression: vec![],
+                access_key: None,
+                public_key: None,
             })
             .await
             .unwrap();
@@ -86,6 +106,8 @@ mod tests {
             // 接收响应
             let msg: ServerMessage = conn.recv().await.unwrap();
             assert!(matches!(msg, ServerMessage::Welcome { .. }));
+            let msg: ServerMessage = conn.recv().await.unwrap();
+            assert!(matches!(msg, ServerMessage::OnlineUsers { more: false, .. }));
         });
 
         // 服务端接受连接
@@ -97,9 +119,15 @@ mod tests {
         assert!(matches!(msg, ClientMessage::Join { .. }));
 
         // 发送响应
-        conn.send(&ServerMessage::Welcome { user_id: 1, online_users: vec!["test_user".to_string()] })
+        conn.send(&ServerMessage::Welcome { user_id: 1, compression: None, topic: None })
             .await
             .unwrap();
+        conn.send(&ServerMessage::OnlineUsers {
+            users: vec![OnlineUser { username: "test_user".to_string(), role: Role::Member, public_key: None }],
+            more: false,
+        })
+        .await
+        .unwrap();
 
         client_handle.await.unwrap();
     }
@@ -5,7 +5,7 @@ use std::time::Duration;
 /// 协议版本号
 pub const PROTOCOL_VERSION: u8 = 1;
 
-/// 用户名最大长度
+/// 用户名最大长度（按字素簇计算）
 pub const MAX_USERNAME_LEN: usize = 20;
 
 /// 单条消息最大长度
@@ -14,6 +14,48 @@ pub const MAX_MESSAGE_LEN: usize = 4096;
 /// 消息帧最大大小
 pub const MAX_FRAME_SIZE: usize = 8192;
 
+/// 负载压缩阈值：超过此大小的帧才会尝试压缩
+pub const COMPRESSION_THRESHOLD: usize = 512;
+
+/// 文件分块大小（需保证分块消息不超过 MAX_FRAME_SIZE）
+pub const FILE_CHUNK_SIZE: usize = 4096;
+
+/// 单个文件最大大小
+pub const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// 每个连接同时进行的上传数上限，超出的上传请求被服务器拒绝
+pub const MAX_CONCURRENT_UPLOADS: usize = 4;
+
+/// 每个连接同时进行的下载数上限，超出的下载请求被服务器拒绝
+pub const MAX_CONCURRENT_DOWNLOADS: usize = 4;
+
+/// X25519 公钥长度
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+/// 加密私聊的 nonce 长度（ChaCha20-Poly1305）
+pub const NONCE_LEN: usize = 12;
+
+/// AEAD 认证标签长度
+pub const AEAD_TAG_LEN: usize = 16;
+
+/// 加密私聊密文最大长度（明文最长为 MAX_MESSAGE_LEN）
+pub const MAX_CIPHERTEXT_LEN: usize = MAX_MESSAGE_LEN + AEAD_TAG_LEN;
+
+/// 话题最大长度（字节）
+pub const MAX_TOPIC_LEN: usize = 200;
+
+/// 搜索关键词最大长度（字节）
+pub const MAX_SEARCH_QUERY_LEN: usize = 200;
+
+/// 每页搜索结果数上限
+pub const MAX_SEARCH_RESULTS: u32 = 50;
+
+/// 文件名最大长度
+pub const MAX_FILE_NAME_LEN: usize = 255;
+
+/// MIME 类型最大长度（RFC 6838 中类型和子类型各不超过 127 个字符）
+pub const MAX_MIME_LEN: usize = 255;
+
 /// 服务端最大连接数
 pub const MAX_CONNECTIONS: usize = 100;
 
@@ -1,5 +1,6 @@
 //! 错误类型定义
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 /// 协议错误类型
@@ -13,6 +14,10 @@ pub enum ProtocolError {
     #[error("Serialization error: {0}")]
     Serialization(#[from] bincode::Error),
 
+    /// 其他序列化格式的编解码错误
+    #[error("{codec} codec error: {message}")]
+    Codec { codec: &'static str, message: String },
+
     /// 协议版本不匹配
     #[error("Protocol version mismatch: expected {expected}, got {actual}")]
     VersionMismatch { expected: u8, actual: u8 },
@@ -21,6 +26,10 @@ pub enum ProtocolError {
     #[error("Frame too large: {size} bytes (max: {max})")]
     FrameTooLarge { size: usize, max: usize },
 
+    /// 压缩帧解压失败
+    #[error("Decompression error: {0}")]
+    Decompression(String),
+
     /// 连接超时
     #[error("Connection timeout")]
     ConnectionTimeout,
@@ -41,6 +50,10 @@ pub enum ProtocolError {
     #[error("Username contains invalid characters")]
     UsernameInvalidChars,
 
+    /// 用户名混用了容易造成混淆的多种文字
+    #[error("Username mixes scripts")]
+    UsernameMixedScript,
+
     /// 消息为空
     #[error("Message is empty")]
     MessageEmpty,
@@ -48,7 +61,225 @@ pub enum ProtocolError {
     /// 消息过长
     #[error("Message too long: {len} bytes (max: {max})")]
     MessageTooLong { len: usize, max: usize },
+
+    /// 文件名无效
+    #[error("Invalid file name")]
+    FileNameInvalid,
+
+    /// MIME 类型无效
+    #[error("Invalid MIME type")]
+    MimeTypeInvalid,
+
+    /// 文件过大
+    #[error("File too large: {size} bytes (max: {max})")]
+    FileTooLarge { size: u64, max: u64 },
+
+    /// 文件分块过大
+    #[error("File chunk too large: {len} bytes (max: {max})")]
+    ChunkTooLarge { len: usize, max: usize },
+
+    /// 文件分块校验失败
+    #[error("File chunk {index} checksum mismatch")]
+    ChecksumMismatch { index: u32 },
+
+    /// 公钥长度错误
+    #[error("Invalid public key length: {len} bytes")]
+    InvalidPublicKey { len: usize },
+
+    /// 加密消息的 nonce 或密文长度错误
+    #[error("Invalid encrypted message")]
+    InvalidEncryptedMessage,
+}
+
+impl ProtocolError {
+    /// 对应的错误码
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ProtocolError::Io(_) => ErrorCode::Io,
+            ProtocolError::Serialization(_) | ProtocolError::Codec { .. } => ErrorCode::Serialization,
+            ProtocolError::VersionMismatch { .. } => ErrorCode::VersionMismatch,
+            ProtocolError::FrameTooLarge { .. } => ErrorCode::FrameTooLarge,
+            ProtocolError::Decompression(_) => ErrorCode::Decompression,
+            ProtocolError::ConnectionTimeout => ErrorCode::ConnectionTimeout,
+            ProtocolError::ConnectionClosed => ErrorCode::ConnectionClosed,
+            ProtocolError::UsernameEmpty => ErrorCode::UsernameEmpty,
+            ProtocolError::UsernameTooLong { .. } => ErrorCode::UsernameTooLong,
+            ProtocolError::UsernameInvalidChars => ErrorCode::UsernameInvalidChars,
+            ProtocolError::UsernameMixedScript => ErrorCode::UsernameMixedScript,
+            ProtocolError::MessageEmpty => ErrorCode::MessageEmpty,
+            ProtocolError::MessageTooLong { .. } => ErrorCode::MessageTooLong,
+            ProtocolError::FileNameInvalid => ErrorCode::FileNameInvalid,
+            ProtocolError::MimeTypeInvalid => ErrorCode::MimeTypeInvalid,
+            ProtocolError::FileTooLarge { .. } => ErrorCode::FileTooLarge,
+            ProtocolError::ChunkTooLarge { .. } => ErrorCode::ChunkTooLarge,
+            ProtocolError::ChecksumMismatch { .. } => ErrorCode::ChecksumMismatch,
+            ProtocolError::InvalidPublicKey { .. } => ErrorCode::InvalidPublicKey,
+            ProtocolError::InvalidEncryptedMessage => ErrorCode::InvalidEncryptedMessage,
+        }
+    }
+}
+
+/// 机器可读的错误码，随 `ServerMessage::Error` 发送
+///
+/// 前半部分与 `ProtocolError` 的变体一一对应，后半部分是服务器端的拒绝原因。
+/// 客户端应根据错误码做出反应，附带的文本仅供展示。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ErrorCode {
+    /// IO 错误
+    Io,
+    /// 序列化错误
+    Serialization,
+    /// 协议版本不匹配
+    VersionMismatch,
+    /// 帧大小超限
+    FrameTooLarge,
+    /// 压缩帧解压失败
+    Decompression,
+    /// 连接超时
+    ConnectionTimeout,
+    /// 连接已关闭
+    ConnectionClosed,
+    /// 用户名为空
+    UsernameEmpty,
+    /// 用户名过长
+    UsernameTooLong,
+    /// 用户名包含无效字符
+    UsernameInvalidChars,
+    /// 用户名混用了多种文字
+    UsernameMixedScript,
+    /// 消息为空
+    MessageEmpty,
+    /// 消息过长
+    MessageTooLong,
+    /// 文件名无效
+    FileNameInvalid,
+    /// 文件过大
+    FileTooLarge,
+    /// 文件分块过大
+    ChunkTooLarge,
+    /// 文件分块校验失败
+    ChecksumMismatch,
+    /// 公钥长度错误
+    InvalidPublicKey,
+    /// 加密消息格式错误
+    InvalidEncryptedMessage,
+    /// 用户名已被占用（或与在线用户的名字易混淆）
+    UsernameTaken,
+    /// 服务器连接数已满
+    ServerBusy,
+    /// 未在限定时间内发送 Join
+    JoinTimeout,
+    /// 加入前发送了其他消息
+    NotJoined,
+    /// 重复发送 Join
+    AlreadyJoined,
+    /// 发送过于频繁
+    RateLimited,
+    /// 已被封禁
+    Banned,
+    /// 未知的斜杠命令
+    UnknownCommand,
+    /// 命令参数错误
+    InvalidCommand,
+    /// 指定的用户不在线
+    UserNotFound,
+    /// 当前角色没有执行该操作的权限
+    PermissionDenied,
+    /// 被管理员踢出
+    Kicked,
+    /// 聊天室需要密码，或密码错误
+    PasswordRequired,
+    /// 聊天室仅限邀请，邀请码缺失或无效
+    InviteRequired,
+    /// 服务器未保存消息历史，无法搜索
+    SearchUnavailable,
+    /// 同时进行的上传或下载过多
+    TooManyTransfers,
+    /// 回复的消息不存在
+    ReplyNotFound,
+    /// MIME 类型无效
+    MimeTypeInvalid,
+    /// 接收者的私聊队列已满，消息未送达
+    RecipientBusy,
+}
+
+impl ErrorCode {
+    /// 错误码的标识符（如 `username-taken`）
+    ///
+    /// 错误码的展示文本只在客户端的本地化文件中维护，键为 `error-<标识符>`。
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCode::Io => "io",
+            ErrorCode::Serialization => "serialization",
+            ErrorCode::VersionMismatch => "version-mismatch",
+            ErrorCode::FrameTooLarge => "frame-too-large",
+            ErrorCode::Decompression => "decompression",
+            ErrorCode::ConnectionTimeout => "connection-timeout",
+            ErrorCode::ConnectionClosed => "connection-closed",
+            ErrorCode::UsernameEmpty => "username-empty",
+            ErrorCode::UsernameTooLong => "username-too-long",
+            ErrorCode::UsernameInvalidChars => "username-invalid-chars",
+            ErrorCode::UsernameMixedScript => "username-mixed-script",
+            ErrorCode::MessageEmpty => "message-empty",
+            ErrorCode::MessageTooLong => "message-too-long",
+            ErrorCode::FileNameInvalid => "file-name-invalid",
+            ErrorCode::FileTooLarge => "file-too-large",
+            ErrorCode::ChunkTooLarge => "chunk-too-large",
+            ErrorCode::ChecksumMismatch => "checksum-mismatch",
+            ErrorCode::InvalidPublicKey => "invalid-public-key",
+            ErrorCode::InvalidEncryptedMessage => "invalid-encrypted-message",
+            ErrorCode::UsernameTaken => "username-taken",
+            ErrorCode::ServerBusy => "server-busy",
+            ErrorCode::JoinTimeout => "join-timeout",
+            ErrorCode::NotJoined => "not-joined",
+            ErrorCode::AlreadyJoined => "already-joined",
+            ErrorCode::RateLimited => "rate-limited",
+            ErrorCode::Banned => "banned",
+            ErrorCode::UnknownCommand => "unknown-command",
+            ErrorCode::InvalidCommand => "invalid-command",
+            ErrorCode::UserNotFound => "user-not-found",
+            ErrorCode::PermissionDenied => "permission-denied",
+            ErrorCode::Kicked => "kicked",
+            ErrorCode::PasswordRequired => "password-required",
+            ErrorCode::InviteRequired => "invite-required",
+            ErrorCode::SearchUnavailable => "search-unavailable",
+            ErrorCode::TooManyTransfers => "too-many-transfers",
+            ErrorCode::ReplyNotFound => "reply-not-found",
+            ErrorCode::MimeTypeInvalid => "mime-type-invalid",
+            ErrorCode::RecipientBusy => "recipient-busy",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
 /// 协议操作结果类型
 pub type Result<T> = std::result::Result<T, ProtocolError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_code_name() {
+        assert_eq!(ErrorCode::UsernameTaken.name(), "username-taken");
+        assert_eq!(ErrorCode::Io.to_string(), "io");
+    }
+
+    #[test]
+    fn test_protocol_error_code() {
+        assert_eq!(ProtocolError::UsernameEmpty.code(), ErrorCode::UsernameEmpty);
+        assert_eq!(
+            ProtocolError::MessageTooLong { len: 5000, max: 4096 }.code(),
+            ErrorCode::MessageTooLong
+        );
+        assert_eq!(
+            ProtocolError::Codec { codec: "json", message: String::new() }.code(),
+            ErrorCode::Serialization
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! 序列化格式
+//!
+//! `Codec` 负责帧负载与消息之间的转换，帧头和压缩由 `FrameReader`/`FrameWriter` 处理。
+//! 连接双方必须使用相同的编码格式。
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::{ProtocolError, Result};
+use crate::FILE_CHUNK_SIZE;
+
+/// 消息序列化格式
+pub trait Codec: Clone + Send + Sync + 'static {
+    /// 格式名称
+    fn name(&self) -> &'static str;
+
+    /// 序列化消息
+    fn encode<M: Serialize>(&self, msg: &M) -> Result<Vec<u8>>;
+
+    /// 反序列化消息
+    fn decode<M: DeserializeOwned>(&self, bytes: &[u8]) -> Result<M>;
+
+    /// 文件分块的最大数据长度，保证编码后的分块消息不超过 MAX_FRAME_SIZE
+    fn max_chunk_size(&self) -> usize {
+        FILE_CHUNK_SIZE
+    }
+}
+
+/// 构造编码错误
+fn codec_error(codec: &'static str, e: impl fmt::Display) -> ProtocolError {
+    ProtocolError::Codec {
+        codec,
+        message: e.to_string(),
+    }
+}
+
+/// bincode 格式（默认）
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bincode;
+
+impl Codec for Bincode {
+    fn name(&self) -> &'static str {
+        "bincode"
+    }
+
+    fn encode<M: Serialize>(&self, msg: &M) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(msg)?)
+    }
+
+    fn decode<M: DeserializeOwned>(&self, bytes: &[u8]) -> Result<M> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+/// postcard 格式（变长整数，体积最小）
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Postcard;
+
+impl Codec for Postcard {
+    fn name(&self) -> &'static str {
+        "postcard"
+    }
+
+    fn encode<M: Serialize>(&self, msg: &M) -> Result<Vec<u8>> {
+        postcard::to_allocvec(msg).map_err(|e| codec_error(self.name(), e))
+    }
+
+    fn decode<M: DeserializeOwned>(&self, bytes: &[u8]) -> Result<M> {
+        postcard::from_bytes(bytes).map_err(|e| codec_error(self.name(), e))
+    }
+}
+
+/// MessagePack 格式（结构体编码为带字段名的 map，便于其他语言解析）
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MessagePack;
+
+impl Codec for MessagePack {
+    fn name(&self) -> &'static str {
+        "msgpack"
+    }
+
+    fn encode<M: Serialize>(&self, msg: &M) -> Result<Vec<u8>> {
+        rmp_serde::to_vec_named(msg).map_err(|e| codec_error(self.name(), e))
+    }
+
+    fn decode<M: DeserializeOwned>(&self, bytes: &[u8]) -> Result<M> {
+        rmp_serde::from_slice(bytes).map_err(|e| codec_error(self.name(), e))
+    }
+}
+
+/// JSON 格式（便于调试）
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json;
+
+impl Codec for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn encode<M: Serialize>(&self, msg: &M) -> Result<Vec<u8>> {
+        serde_json::to_vec(msg).map_err(|e| codec_error(self.name(), e))
+    }
+
+    fn decode<M: DeserializeOwned>(&self, bytes: &[u8]) -> Result<M> {
+        serde_json::from_slice(bytes).map_err(|e| codec_error(self.name(), e))
+    }
+
+    /// JSON 将字节数组编码为数字列表，每字节最多占 4 个字符
+    fn max_chunk_size(&self) -> usize {
+        FILE_CHUNK_SIZE / 4
+    }
+}
+
+/// 运行时选择的编码格式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CodecKind {
+    #[default]
+    Bincode,
+    Postcard,
+    MessagePack,
+    Json,
+}
+
+impl CodecKind {
+    /// 所有可选格式
+    pub const ALL: &'static [CodecKind] = &[
+        CodecKind::Bincode,
+        CodecKind::Postcard,
+        CodecKind::MessagePack,
+        CodecKind::Json,
+    ];
+}
+
+impl Codec for CodecKind {
+    fn name(&self) -> &'static str {
+        match self {
+            CodecKind::Bincode => Bincode.name(),
+            CodecKind::Postcard => Postcard.name(),
+            CodecKind::MessagePack => MessagePack.name(),
+            CodecKind::Json => Json.name(),
+        }
+    }
+
+    fn encode<M: Serialize>(&self, msg: &M) -> Result<Vec<u8>> {
+        match self {
+            CodecKind::Bincode => Bincode.encode(msg),
+            CodecKind::Postcard => Postcard.encode(msg),
+            CodecKind::MessagePack => MessagePack.encode(msg),
+            CodecKind::Json => Json.encode(msg),
+        }
+    }
+
+    fn decode<M: DeserializeOwned>(&self, bytes: &[u8]) -> Result<M> {
+        match self {
+            CodecKind::Bincode => Bincode.decode(bytes),
+            CodecKind::Postcard => Postcard.decode(bytes),
+            CodecKind::MessagePack => MessagePack.decode(bytes),
+            CodecKind::Json => Json.decode(bytes),
+        }
+    }
+
+    fn max_chunk_size(&self) -> usize {
+        match self {
+            CodecKind::Bincode => Bincode.max_chunk_size(),
+            CodecKind::Postcard => Postcard.max_chunk_size(),
+            CodecKind::MessagePack => MessagePack.max_chunk_size(),
+            CodecKind::Json => Json.max_chunk_size(),
+        }
+    }
+}
+
+impl fmt::Display for CodecKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for CodecKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        CodecKind::ALL
+            .iter()
+            .copied()
+            .find(|c| c.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown codec: {}", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunk_checksum, ClientMessage, Compression, OnlineUser, ReadReceipt, Role, SearchHit, ServerMessage,
+        MAX_FRAME_SIZE, MAX_MESSAGE_LEN, MAX_USERNAME_LEN,
+    };
+
+    fn sample_messages() -> Vec<ServerMessage> {
+        vec![
+            ServerMessage::Welcome {
+                user_id: 1,
+                compression: Some(Compression::Lz4),
+                topic: Some("release day".to_string()),
+            },
+            ServerMessage::OnlineUsers {
+                users: vec![
+                    OnlineUser { username: "alice".to_string(), role: Role::Owner, public_key: Some(vec![7; 32]) },
+                    OnlineUser { username: "bob".to_string(), role: Role::Member, public_key: None },
+                ],
+                more: true,
+            },
+            ServerMessage::ChatBroadcast {
+                message_id: u64::MAX,
+                username: "alice".to_string(),
+                content: "你好, world!".to_string(),
+                timestamp: 1234567890,
+                reply_to: Some(3),
+                mentions: vec!["bob".to_string()],
+            },
+            ServerMessage::EncryptedDirect {
+                message_id: 9,
+                from: "alice".to_string(),
+                to: "bob".to_string(),
+                nonce: vec![1; 12],
+                ciphertext: vec![0, 255, 128],
+                timestamp: 1234567890,
+            },
+            ServerMessage::SearchResults {
+                before: Some(100),
+                hits: vec![SearchHit {
+                    message_id: 42,
+                    username: "bob".to_string(),
+                    content: "部署在 \"周五\"".to_string(),
+                    timestamp: 1234567890,
+                    action: true,
+                }],
+                next_before: None,
+            },
+            ServerMessage::ReadReceipts {
+                receipts: vec![ReadReceipt { username: "小明".to_string(), up_to: u64::MAX }],
+            },
+            ServerMessage::Pong,
+        ]
+    }
+
+    #[test]
+    fn test_all_codecs_roundtrip() {
+        for codec in CodecKind::ALL {
+            for msg in sample_messages() {
+                let bytes = codec.encode(&msg).unwrap();
+                let decoded: ServerMessage = codec.decode(&bytes).unwrap();
+                assert_eq!(decoded, msg, "codec {}", codec);
+            }
+        }
+    }
+
+    #[test]
+    fn test_max_chunk_fits_in_frame() {
+        for codec in CodecKind::ALL {
+            let data = vec![255u8; codec.max_chunk_size()];
+            let msg = ClientMessage::FileChunk {
+                transfer_id: u32::MAX,
+                index: u32::MAX,
+                checksum: chunk_checksum(&data),
+                data,
+            };
+            let len = codec.encode(&msg).unwrap().len();
+            assert!(len <= MAX_FRAME_SIZE, "codec {} chunk is {} bytes", codec, len);
+        }
+    }
+
+    #[test]
+    fn test_longest_chat_message_frame_size() {
+        // 控制字符在 JSON 中转义为 \u0001，每字节占 6 个字符
+        let msg = ServerMessage::ChatBroadcast {
+            message_id: u64::MAX,
+            username: "字".repeat(MAX_USERNAME_LEN),
+            content: "\u{1}".repeat(MAX_MESSAGE_LEN),
+            timestamp: u64::MAX,
+            reply_to: Some(u64::MAX),
+            mentions: Vec::new(),
+        };
+        for codec in CodecKind::ALL {
+            let len = codec.encode(&msg).unwrap().len();
+            // 二进制格式总能容纳最长的消息；JSON 不能，服务器以 MessageTooLong 拒绝这类消息
+            let fits = len <= MAX_FRAME_SIZE;
+            assert_eq!(fits, *codec != CodecKind::Json, "codec {} message is {} bytes", codec, len);
+        }
+    }
+
+    #[test]
+    fn test_codec_kind_from_str() {
+        assert_eq!("JSON".parse::<CodecKind>().unwrap(), CodecKind::Json);
+        assert_eq!("msgpack".parse::<CodecKind>().unwrap(), CodecKind::MessagePack);
+        assert!("xml".parse::<CodecKind>().is_err());
+    }
+
+    #[test]
+    fn test_json_is_readable() {
+        let bytes = Json.encode(&ClientMessage::Ping).unwrap();
+        assert_eq!(bytes, br#""Ping""#);
+    }
+}
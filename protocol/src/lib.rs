@@ -3,19 +3,38 @@
 //! 包含:
 //! - 消息类型定义 (ClientMessage, ServerMessage)
 //! - 传输层抽象 (Transport trait)
-//! - 帧编解码 (Codec)
+//! - 帧编解码 (FrameReader, FrameWriter)
+//! - 序列化格式 (Codec)
+//! - 帧负载压缩 (Compression)
 //! - 连接封装 (Connection)
+//! - 错误类型与错误码 (ProtocolError, ErrorCode)
+//! - 用户名规则 (validate_username, username_key)
+//! - 用户角色 (Role)
+//! - @提及解析 (extract_mentions)
+//! - 文件分块传输辅助函数
 
 mod message;
 mod constants;
 mod transport;
 mod codec;
+mod compression;
+mod format;
 mod connection;
 mod error;
+mod mention;
+mod transfer;
+mod username;
+mod role;
 
-pub use message::{ClientMessage, ServerMessage};
+pub use message::{ClientMessage, CommandHelp, CommandReply, ReadReceipt, SearchHit, ServerMessage};
+pub use role::{OnlineUser, Role};
 pub use constants::*;
 pub use transport::{Transport, TransportListener, TransportConfig, TcpTransport, TcpListener};
-pub use codec::{FrameReader, FrameWriter};
+pub use codec::{EncodedFrame, FrameReader, FrameWriter};
+pub use compression::Compression;
+pub use format::{Bincode, Codec, CodecKind, Json, MessagePack, Postcard};
 pub use connection::Connection;
-pub use error::{ProtocolError, Result};
+pub use error::{ErrorCode, ProtocolError, Result};
+pub use mention::{extract_mentions, mention_prefixes};
+pub use transfer::{chunk_checksum, chunk_count, is_image_mime, is_valid_mime, mime_from_name};
+pub use username::{normalize_username, username_key, validate_username};
@@ -0,0 +1,82 @@
+//! @提及解析
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::username::is_username_char;
+use crate::MAX_USERNAME_LEN;
+
+/// 从消息内容中提取 `@username` 形式的提及（去重，保持出现顺序）
+///
+/// `@` 前必须是消息开头或非用户名字符，避免把邮箱地址识别为提及。
+pub fn extract_mentions(content: &str) -> Vec<&str> {
+    let mut mentions: Vec<&str> = Vec::new();
+    let mut prev: Option<char> = None;
+
+    for (i, c) in content.char_indices() {
+        if c == '@' && !prev.is_some_and(is_username_char) {
+            let rest = &content[i + 1..];
+            let end = rest
+                .char_indices()
+                .find(|&(_, c)| !is_username_char(c))
+                .map(|(j, _)| j)
+                .unwrap_or(rest.len());
+            let name = &rest[..end];
+            if !name.is_empty() && !mentions.contains(&name) {
+                mentions.push(name);
+            }
+        }
+        prev = Some(c);
+    }
+
+    mentions
+}
+
+/// 提及可能指向的用户名：`token` 的前缀，从长到短
+///
+/// 中文等不用空格分词的文字里 @名字 后面可能紧跟正文，因此要逐个尝试前缀。
+/// 用户名最多 [`MAX_USERNAME_LEN`] 个字素，更长的前缀不可能匹配，不会返回。
+pub fn mention_prefixes(token: &str) -> impl Iterator<Item = &str> {
+    let ends: Vec<usize> = token
+        .grapheme_indices(true)
+        .take(MAX_USERNAME_LEN)
+        .map(|(i, g)| i + g.len())
+        .collect();
+    ends.into_iter().rev().map(move |end| &token[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_mentions() {
+        assert_eq!(extract_mentions("@alice hi"), vec!["alice"]);
+        assert_eq!(
+            extract_mentions("hi @bob and @carol-1, @bob again"),
+            vec!["bob", "carol-1"]
+        );
+    }
+
+    #[test]
+    fn test_extract_mentions_ignores_email_and_bare_at() {
+        assert!(extract_mentions("mail me at bob@example.com").is_empty());
+        assert!(extract_mentions("@ nobody").is_empty());
+    }
+
+    #[test]
+    fn test_mention_prefixes() {
+        assert_eq!(mention_prefixes("张三你好").collect::<Vec<_>>(), vec!["张三你好", "张三你", "张三", "张"]);
+        assert_eq!(mention_prefixes("e\u{0301}x").collect::<Vec<_>>(), vec!["e\u{0301}x", "e\u{0301}"]);
+
+        let long = "a".repeat(4096);
+        let prefixes: Vec<_> = mention_prefixes(&long).collect();
+        assert_eq!(prefixes.len(), MAX_USERNAME_LEN);
+        assert_eq!(prefixes[0].len(), MAX_USERNAME_LEN);
+    }
+
+    #[test]
+    fn test_extract_mentions_unicode() {
+        assert_eq!(extract_mentions("你好 @张三"), vec!["张三"]);
+        assert_eq!(extract_mentions("@José, hi"), vec!["José"]);
+    }
+}
@@ -2,45 +2,107 @@
 
 use serde::{Deserialize, Serialize};
 
-use crate::error::{ProtocolError, Result};
-use crate::{MAX_MESSAGE_LEN, MAX_USERNAME_LEN};
+use crate::compression::Compression;
+use crate::error::{ErrorCode, ProtocolError, Result};
+use crate::role::{OnlineUser, Role};
+use crate::username::validate_username;
+use crate::{
+    is_valid_mime, FILE_CHUNK_SIZE, MAX_CIPHERTEXT_LEN, MAX_FILE_NAME_LEN, MAX_FILE_SIZE, MAX_MESSAGE_LEN, MAX_SEARCH_QUERY_LEN,
+    NONCE_LEN, PUBLIC_KEY_LEN,
+};
 
 /// 客户端发送给服务端的消息
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum ClientMessage {
     /// 加入聊天室
-    Join { username: String },
+    Join {
+        username: String,
+        /// 客户端支持的压缩算法，由服务端在 Welcome 中选定
+        compression: Vec<Compression>,
+        /// 聊天室的密码或邀请码（公开聊天室不需要）
+        access_key: Option<String>,
+        /// 用于加密私聊的 X25519 公钥，随在线用户列表分发给其他客户端
+        #[serde(with = "serde_bytes")]
+        public_key: Option<Vec<u8>>,
+    },
     /// 发送聊天消息
-    Chat { content: String },
+    Chat {
+        content: String,
+        /// 回复的消息 ID（可选），不是服务器已分配的 ID 时消息被拒绝（`ReplyNotFound`）
+        reply_to: Option<u64>,
+    },
     /// 离开聊天室
     Leave,
     /// 心跳请求
     Ping,
+    /// 发起文件上传
+    ///
+    /// `transfer_id` 由客户端分配，在单个连接内唯一，用于关联后续分块。
+    FileOffer {
+        transfer_id: u32,
+        name: String,
+        size: u64,
+        mime: String,
+    },
+    /// 上传文件分块（须按 index 顺序发送）
+    FileChunk {
+        transfer_id: u32,
+        index: u32,
+        #[serde(with = "serde_bytes")]
+        data: Vec<u8>,
+        /// 分块数据的 CRC32 校验值
+        checksum: u32,
+    },
+    /// 上传的所有分块已发送完毕
+    FileComplete { transfer_id: u32 },
+    /// 请求下载已共享的文件
+    FileDownload { transfer_id: u32, file_id: u64 },
+    /// 取消上传或下载
+    FileCancel { transfer_id: u32 },
+    /// 端到端加密的私聊消息，服务端只转发密文
+    EncryptedDirect {
+        to: String,
+        #[serde(with = "serde_bytes")]
+        nonce: Vec<u8>,
+        #[serde(with = "serde_bytes")]
+        ciphertext: Vec<u8>,
+    },
+    /// 搜索历史消息（服务器启用了消息历史时可用）
+    ///
+    /// 结果按消息 ID 从新到旧排列；`before` 为上一页 `SearchResults::next_before`，用于翻页。
+    Search {
+        /// 关键词，以空白分隔，须全部出现（不区分大小写）
+        query: String,
+        /// 只搜索该用户发送的消息
+        from_user: Option<String>,
+        /// 时间范围（Unix 时间戳，秒，包含两端）
+        since: Option<u64>,
+        until: Option<u64>,
+        /// 只返回 ID 小于该值的消息
+        before: Option<u64>,
+        /// 每页结果数，超过 `MAX_SEARCH_RESULTS` 时按上限处理
+        limit: u32,
+    },
+    /// 已读回执：已看到 ID 不大于 `up_to` 的所有消息
+    ///
+    /// 服务端只记录前进的已读位置，并通过 `ReadReceipts` 广播给所有用户。
+    /// 不想让别人知道自己是否已读的客户端不发送此消息。
+    ///
+    /// `up_to` 为 0（不是有效的消息 ID）时清除自己的已读位置，关闭已读回执时发送。
+    MarkRead { up_to: u64 },
 }
 
 impl ClientMessage {
     /// 校验消息内容是否符合约束
     pub fn validate(&self) -> Result<()> {
         match self {
-            ClientMessage::Join { username } => {
-                if username.is_empty() {
-                    return Err(ProtocolError::UsernameEmpty);
-                }
-                if username.len() > MAX_USERNAME_LEN {
-                    return Err(ProtocolError::UsernameTooLong {
-                        len: username.len(),
-                        max: MAX_USERNAME_LEN,
-                    });
-                }
-                // 只允许字母、数字、下划线、连字符
-                if !username
-                    .chars()
-                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
-                {
-                    return Err(ProtocolError::UsernameInvalidChars);
+            ClientMessage::Join { username, public_key, .. } => {
+                validate_username(username)?;
+                if let Some(key) = public_key.as_ref().filter(|k| k.len() != PUBLIC_KEY_LEN) {
+                    return Err(ProtocolError::InvalidPublicKey { len: key.len() });
                 }
             }
-            ClientMessage::Chat { content } => {
+            ClientMessage::Chat { content, .. } => {
                 if content.is_empty() {
                     return Err(ProtocolError::MessageEmpty);
                 }
@@ -51,6 +113,47 @@ impl ClientMessage {
                     });
                 }
             }
+            ClientMessage::FileOffer { name, size, mime, .. } => {
+                if name.is_empty()
+                    || name.len() > MAX_FILE_NAME_LEN
+                    || name.contains(['/', '\\', '\0'])
+                    || name == "."
+                    || name == ".."
+                {
+                    return Err(ProtocolError::FileNameInvalid);
+                }
+                if !is_valid_mime(mime) {
+                    return Err(ProtocolError::MimeTypeInvalid);
+                }
+                if *size > MAX_FILE_SIZE {
+                    return Err(ProtocolError::FileTooLarge {
+                        size: *size,
+                        max: MAX_FILE_SIZE,
+                    });
+                }
+            }
+            ClientMessage::FileChunk { data, .. } if data.len() > FILE_CHUNK_SIZE => {
+                return Err(ProtocolError::ChunkTooLarge {
+                    len: data.len(),
+                    max: FILE_CHUNK_SIZE,
+                });
+            }
+            ClientMessage::Search { query, .. } => {
+                if query.trim().is_empty() {
+                    return Err(ProtocolError::MessageEmpty);
+                }
+                if query.len() > MAX_SEARCH_QUERY_LEN {
+                    return Err(ProtocolError::MessageTooLong {
+                        len: query.len(),
+                        max: MAX_SEARCH_QUERY_LEN,
+                    });
+                }
+            }
+            ClientMessage::EncryptedDirect { nonce, ciphertext, .. }
+                if nonce.len() != NONCE_LEN || ciphertext.is_empty() || ciphertext.len() > MAX_CIPHERTEXT_LEN =>
+            {
+                return Err(ProtocolError::InvalidEncryptedMessage);
+            }
             _ => {}
         }
         Ok(())
@@ -60,38 +163,221 @@ impl ClientMessage {
 /// 服务端发送给客户端的消息
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum ServerMessage {
-    /// 欢迎消息，包含分配的用户 ID 和当前在线用户列表
+    /// 欢迎消息，包含分配的用户 ID，之后紧接着发送 `OnlineUsers`
     Welcome {
         user_id: u32,
-        online_users: Vec<String>,
+        /// 本连接后续使用的压缩算法（None 表示不压缩）
+        compression: Option<Compression>,
+        /// 聊天室当前话题
+        topic: Option<String>,
     },
+    /// 加入时的在线用户（包括自己）及其角色
+    ///
+    /// 紧接 Welcome 发送；列表编码后可能超过帧大小，因此分成多帧，最后一帧的 `more` 为 false。
+    OnlineUsers { users: Vec<OnlineUser>, more: bool },
+    /// 每日消息（MOTD），紧接 Welcome 发送，服务器未配置时不发送
+    Motd { message: String },
     /// 用户加入通知
-    UserJoined { username: String },
+    UserJoined {
+        username: String,
+        role: Role,
+        #[serde(with = "serde_bytes")]
+        public_key: Option<Vec<u8>>,
+    },
     /// 用户离开通知
     UserLeft { username: String },
     /// 聊天消息广播
     ChatBroadcast {
+        /// 服务端分配的消息 ID
+        message_id: u64,
         username: String,
         content: String,
         /// Unix 时间戳（秒）
         timestamp: u64,
+        /// 回复的消息 ID（可选）
+        reply_to: Option<u64>,
+        /// 被 @提及 的在线用户
+        mentions: Vec<String>,
+    },
+    /// 已共享文件通知（上传完成后广播）
+    FileShared {
+        file_id: u64,
+        username: String,
+        name: String,
+        size: u64,
+        mime: String,
+        /// Unix 时间戳（秒）
+        timestamp: u64,
+    },
+    /// 服务端接受上传请求，客户端可以开始发送分块
+    FileAccepted { transfer_id: u32 },
+    /// 下载文件分块（按 index 顺序发送）
+    FileChunk {
+        transfer_id: u32,
+        index: u32,
+        #[serde(with = "serde_bytes")]
+        data: Vec<u8>,
+        /// 分块数据的 CRC32 校验值
+        checksum: u32,
+    },
+    /// 传输完成：上传已保存，或下载的所有分块已发送
+    FileComplete { transfer_id: u32 },
+    /// 传输被取消或失败
+    ///
+    /// `code` 为服务器拒绝传输的原因（如 `TooManyTransfers`），`reason` 为展示给用户的说明。
+    FileCancelled {
+        transfer_id: u32,
+        code: Option<ErrorCode>,
+        reason: String,
     },
     /// 错误消息
-    Error { message: String },
+    ///
+    /// `message` 为可选的人类可读说明，客户端可以忽略它并根据 `code` 自行本地化。
+    Error {
+        code: ErrorCode,
+        message: Option<String>,
+    },
+    /// `/me` 动作消息广播
+    ActionBroadcast {
+        message_id: u64,
+        username: String,
+        content: String,
+        /// Unix 时间戳（秒）
+        timestamp: u64,
+    },
+    /// `/msg` 私聊消息，只发给收件人和发送者
+    DirectMessage {
+        message_id: u64,
+        from: String,
+        to: String,
+        content: String,
+        /// Unix 时间戳（秒）
+        timestamp: u64,
+    },
+    /// 端到端加密的私聊消息，只发给收件人和发送者
+    EncryptedDirect {
+        message_id: u64,
+        from: String,
+        to: String,
+        #[serde(with = "serde_bytes")]
+        nonce: Vec<u8>,
+        #[serde(with = "serde_bytes")]
+        ciphertext: Vec<u8>,
+        /// Unix 时间戳（秒）
+        timestamp: u64,
+    },
+    /// 用户改名通知
+    UserRenamed {
+        old_username: String,
+        new_username: String,
+    },
+    /// 用户角色变更通知，`by` 为执行变更的用户
+    RoleChanged {
+        username: String,
+        role: Role,
+        by: String,
+    },
+    /// 话题变更通知
+    TopicChanged { topic: String, username: String },
+    /// 斜杠命令的执行结果，只发给执行命令的用户
+    CommandReply { reply: CommandReply },
+    /// 搜索结果
+    SearchResults {
+        /// 请求中的 `before`，为 None 表示第一页
+        before: Option<u64>,
+        hits: Vec<SearchHit>,
+        /// 还有更多结果时，作为下一页请求的 `before`
+        next_before: Option<u64>,
+    },
+    /// 已读回执，加入时收到所有用户的已读位置，之后每次有用户的已读位置前进或清除时收到一条
+    ReadReceipts { receipts: Vec<ReadReceipt> },
     /// 心跳响应
     Pong,
     /// 服务器关闭通知
     Shutdown { message: String },
+    /// 收件人离线时排队的 `/msg` 私聊，在其下次加入后（已读回执之后）逐条发送
+    OfflineDirect {
+        message_id: u64,
+        from: String,
+        to: String,
+        content: String,
+        /// 发送时的 Unix 时间戳（秒）
+        timestamp: u64,
+    },
+}
+
+/// 斜杠命令返回的结构化结果
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum CommandReply {
+    /// `/who`：在线用户列表
+    ///
+    /// 列表编码后可能超过帧大小，因此分成多条回复，最后一条的 `more` 为 false。
+    Who { users: Vec<String>, more: bool },
+    /// 不带参数的 `/topic`：当前话题（未设置时为 None）
+    Topic { topic: Option<String> },
+    /// `/help`：可用命令
+    Help { commands: Vec<CommandHelp> },
+    /// `/invite`：新建的邀请码
+    InviteCreated { code: String },
+    /// `/invite revoke`：已撤销的邀请码
+    InviteRevoked { code: String },
+    /// `/invite list`：有效的邀请码
+    Invites { codes: Vec<String> },
+    /// `/msg`：收件人离线，私聊已排队，在其下次加入时投递
+    OfflineQueued { to: String },
+}
+
+/// 单个命令的帮助信息
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CommandHelp {
+    /// 用法，如 "/msg <user> <message>"
+    pub usage: String,
+    /// 说明（服务器语言）
+    pub description: String,
+}
+
+/// 一条搜索结果
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub message_id: u64,
+    pub username: String,
+    pub content: String,
+    /// Unix 时间戳（秒）
+    pub timestamp: u64,
+    /// 是否为 `/me` 动作消息
+    pub action: bool,
+}
+
+/// 一个用户的已读位置
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReadReceipt {
+    pub username: String,
+    /// 已看到 ID 不大于此值的所有消息，为 0 时表示该用户清除了已读位置
+    pub up_to: u64,
+}
+
+impl ServerMessage {
+    /// 构造附带说明文本的错误消息
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+        ServerMessage::Error {
+            code,
+            message: Some(message.into()),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{MAX_MIME_LEN, MAX_USERNAME_LEN};
 
     #[test]
     fn test_client_message_serialize() {
         let msg = ClientMessage::Join {
             username: "alice".to_string(),
+            compression: vec![],
+            access_key: None,
+            public_key: None,
         };
         let bytes = bincode::serialize(&msg).unwrap();
         let decoded: ClientMessage = bincode::deserialize(&bytes).unwrap();
@@ -101,19 +387,55 @@ mod tests {
     #[test]
     fn test_server_message_serialize() {
         let msg = ServerMessage::ChatBroadcast {
+            message_id: 42,
             username: "bob".to_string(),
             content: "Hello!".to_string(),
             timestamp: 1234567890,
+            reply_to: Some(7),
+            mentions: vec!["alice".to_string()],
         };
         let bytes = bincode::serialize(&msg).unwrap();
         let decoded: ServerMessage = bincode::deserialize(&bytes).unwrap();
         assert_eq!(msg, decoded);
     }
 
+    #[test]
+    fn test_command_reply_serialize() {
+        let msg = ServerMessage::CommandReply {
+            reply: CommandReply::Help {
+                commands: vec![CommandHelp {
+                    usage: "/who".to_string(),
+                    description: "List online users".to_string(),
+                }],
+            },
+        };
+        let bytes = bincode::serialize(&msg).unwrap();
+        let decoded: ServerMessage = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(msg, decoded);
+    }
+
+    #[test]
+    fn test_error_message_serialize() {
+        for msg in [
+            ServerMessage::error(ErrorCode::UsernameTaken, "用户名已存在"),
+            ServerMessage::Error {
+                code: ErrorCode::ServerBusy,
+                message: None,
+            },
+        ] {
+            let bytes = bincode::serialize(&msg).unwrap();
+            let decoded: ServerMessage = bincode::deserialize(&bytes).unwrap();
+            assert_eq!(msg, decoded);
+        }
+    }
+
     #[test]
     fn test_validate_username_empty() {
         let msg = ClientMessage::Join {
             username: "".to_string(),
+            compression: vec![],
+            access_key: None,
+            public_key: None,
         };
         assert!(msg.validate().is_err());
     }
@@ -122,6 +444,9 @@ mod tests {
     fn test_validate_username_too_long() {
         let msg = ClientMessage::Join {
             username: "a".repeat(MAX_USERNAME_LEN + 1),
+            compression: vec![],
+            access_key: None,
+            public_key: None,
         };
         assert!(msg.validate().is_err());
     }
@@ -130,6 +455,9 @@ mod tests {
     fn test_validate_username_ok() {
         let msg = ClientMessage::Join {
             username: "valid_user".to_string(),
+            compression: vec![],
+            access_key: None,
+            public_key: None,
         };
         assert!(msg.validate().is_ok());
     }
@@ -138,6 +466,7 @@ mod tests {
     fn test_validate_message_too_long() {
         let msg = ClientMessage::Chat {
             content: "a".repeat(MAX_MESSAGE_LEN + 1),
+            reply_to: None,
         };
         assert!(msg.validate().is_err());
     }
@@ -146,6 +475,7 @@ mod tests {
     fn test_validate_message_ok() {
         let msg = ClientMessage::Chat {
             content: "Hello!".to_string(),
+            reply_to: None,
         };
         assert!(msg.validate().is_ok());
     }
@@ -155,20 +485,63 @@ mod tests {
         // 包含空格
         let msg = ClientMessage::Join {
             username: "user name".to_string(),
+            compression: vec![],
+            access_key: None,
+            public_key: None,
         };
         assert!(msg.validate().is_err());
 
         // 包含特殊字符
         let msg = ClientMessage::Join {
             username: "user@name".to_string(),
+            compression: vec![],
+            access_key: None,
+            public_key: None,
         };
         assert!(msg.validate().is_err());
+    }
 
-        // 包含中文
-        let msg = ClientMessage::Join {
-            username: "用户".to_string(),
+    #[test]
+    fn test_validate_public_key_and_ciphertext() {
+        let join = |public_key| ClientMessage::Join {
+            username: "alice".to_string(),
+            compression: vec![],
+            access_key: None,
+            public_key,
         };
-        assert!(msg.validate().is_err());
+        assert!(join(Some(vec![0; PUBLIC_KEY_LEN])).validate().is_ok());
+        assert!(matches!(
+            join(Some(vec![0; 31])).validate(),
+            Err(ProtocolError::InvalidPublicKey { len: 31 })
+        ));
+
+        let direct = |nonce_len, ciphertext_len| ClientMessage::EncryptedDirect {
+            to: "bob".to_string(),
+            nonce: vec![0; nonce_len],
+            ciphertext: vec![0; ciphertext_len],
+        };
+        assert!(direct(NONCE_LEN, MAX_CIPHERTEXT_LEN).validate().is_ok());
+        assert!(direct(NONCE_LEN - 1, 32).validate().is_err());
+        assert!(direct(NONCE_LEN, 0).validate().is_err());
+        assert!(direct(NONCE_LEN, MAX_CIPHERTEXT_LEN + 1).validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_search_query() {
+        let search = |query: &str| ClientMessage::Search {
+            query: query.to_string(),
+            from_user: None,
+            since: None,
+            until: None,
+            before: None,
+            limit: 20,
+        };
+        assert!(search("deploy 周五").validate().is_ok());
+        assert!(matches!(search("  ").validate(), Err(ProtocolError::MessageEmpty)));
+        assert!(matches!(
+            search(&"a".repeat(MAX_SEARCH_QUERY_LEN + 1)).validate(),
+            Err(ProtocolError::MessageTooLong { .. })
+        ));
     }
 
     #[test]
@@ -176,14 +549,58 @@ mod tests {
         // 允许下划线和连字符
         let msg = ClientMessage::Join {
             username: "user_name-123".to_string(),
+            compression: vec![],
+            access_key: None,
+            public_key: None,
+        };
+        assert!(msg.validate().is_ok());
+
+        // 允许中文
+        let msg = ClientMessage::Join {
+            username: "用户".to_string(),
+            compression: vec![],
+            access_key: None,
+            public_key: None,
         };
         assert!(msg.validate().is_ok());
     }
 
+    #[test]
+    fn test_validate_file_offer() {
+        let offer_mime = |name: &str, size: u64, mime: &str| ClientMessage::FileOffer {
+            transfer_id: 1,
+            name: name.to_string(),
+            size,
+            mime: mime.to_string(),
+        };
+        let offer = |name: &str, size: u64| offer_mime(name, size, "image/png");
+        assert!(offer("cat.png", 1024).validate().is_ok());
+        assert!(offer_mime("a.bin", 1024, "application/vnd.ms-excel").validate().is_ok());
+        assert!(offer_mime("a.bin", 1024, "text/plain; charset=utf-8").validate().is_err());
+        assert!(offer_mime("a.bin", 1024, "image/").validate().is_err());
+        assert!(offer_mime("a.bin", 1024, &format!("a/{}", "b".repeat(MAX_MIME_LEN))).validate().is_err());
+        assert!(offer("", 1024).validate().is_err());
+        assert!(offer("../etc/passwd", 1024).validate().is_err());
+        assert!(offer("..", 1024).validate().is_err());
+        assert!(offer("big.bin", MAX_FILE_SIZE + 1).validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_file_chunk_too_large() {
+        let msg = ClientMessage::FileChunk {
+            transfer_id: 1,
+            index: 0,
+            data: vec![0; FILE_CHUNK_SIZE + 1],
+            checksum: 0,
+        };
+        assert!(msg.validate().is_err());
+    }
+
     #[test]
     fn test_validate_message_empty() {
         let msg = ClientMessage::Chat {
             content: "".to_string(),
+            reply_to: None,
         };
         assert!(msg.validate().is_err());
     }
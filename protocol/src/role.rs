@@ -0,0 +1,81 @@
+//! 用户角色
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// 用户角色，按权限从低到高排列
+///
+/// 具体权限由服务端的权限矩阵决定，客户端只用于显示。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Role {
+    /// 访客：只能阅读和下载文件
+    Guest,
+    /// 普通成员
+    #[default]
+    Member,
+    /// 管理员：可修改话题、踢出用户、分配角色
+    Operator,
+    /// 所有者：不受其他用户管理
+    Owner,
+}
+
+impl Role {
+    /// 所有角色（从低到高）
+    pub const ALL: &'static [Role] = &[Role::Guest, Role::Member, Role::Operator, Role::Owner];
+
+    /// 配置文件和命令中使用的名称
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Guest => "guest",
+            Role::Member => "member",
+            Role::Operator => "operator",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Role::ALL
+            .iter()
+            .copied()
+            .find(|r| r.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown role: {}", s))
+    }
+}
+
+/// 在线用户及其角色
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OnlineUser {
+    pub username: String,
+    pub role: Role,
+    /// 用于加密私聊的 X25519 公钥（客户端未提供时为 None）
+    #[serde(with = "serde_bytes")]
+    pub public_key: Option<Vec<u8>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_order_and_names() {
+        assert!(Role::Guest < Role::Member);
+        assert!(Role::Operator < Role::Owner);
+        for &role in Role::ALL {
+            assert_eq!(role.name().parse::<Role>(), Ok(role));
+        }
+        assert_eq!("OPERATOR".parse::<Role>(), Ok(Role::Operator));
+        assert!("admin".parse::<Role>().is_err());
+    }
+}
@@ -0,0 +1,82 @@
+//! 文件分块传输辅助函数
+
+use crate::{FILE_CHUNK_SIZE, MAX_MIME_LEN};
+
+/// 计算分块数据的 CRC32 校验值
+pub fn chunk_checksum(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+/// 计算文件的分块数量
+pub fn chunk_count(size: u64) -> u32 {
+    size.div_ceil(FILE_CHUNK_SIZE as u64) as u32
+}
+
+/// 根据文件扩展名推断 MIME 类型
+pub fn mime_from_name(name: &str) -> &'static str {
+    let ext = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "txt" | "log" | "md" => "text/plain",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// 是否为有效的 MIME 类型：`类型/子类型`，只含 RFC 6838 允许的字符，不带参数
+pub fn is_valid_mime(mime: &str) -> bool {
+    let is_name = |s: &str| {
+        !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$&^_.+-".contains(&b))
+    };
+    mime.len() <= MAX_MIME_LEN && mime.split_once('/').is_some_and(|(ty, sub)| is_name(ty) && is_name(sub))
+}
+
+/// 是否为客户端可内联预览的图片类型
+pub fn is_image_mime(mime: &str) -> bool {
+    matches!(
+        mime,
+        "image/png" | "image/jpeg" | "image/gif" | "image/webp" | "image/bmp"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClientMessage, MAX_FRAME_SIZE};
+
+    #[test]
+    fn test_chunk_count() {
+        assert_eq!(chunk_count(0), 0);
+        assert_eq!(chunk_count(1), 1);
+        assert_eq!(chunk_count(FILE_CHUNK_SIZE as u64), 1);
+        assert_eq!(chunk_count(FILE_CHUNK_SIZE as u64 + 1), 2);
+    }
+
+    #[test]
+    fn test_mime_from_name() {
+        assert_eq!(mime_from_name("Photo.JPG"), "image/jpeg");
+        assert_eq!(mime_from_name("notes.txt"), "text/plain");
+        assert_eq!(mime_from_name("Makefile"), "application/octet-stream");
+        assert!(is_image_mime(mime_from_name("cat.png")));
+    }
+
+    #[test]
+    fn test_full_chunk_fits_in_frame() {
+        let data = vec![0xAB; FILE_CHUNK_SIZE];
+        let msg = ClientMessage::FileChunk {
+            transfer_id: u32::MAX,
+            index: u32::MAX,
+            checksum: chunk_checksum(&data),
+            data,
+        };
+        assert!(bincode::serialize(&msg).unwrap().len() <= MAX_FRAME_SIZE);
+    }
+}
@@ -0,0 +1,124 @@
+//! 用户名规则
+//!
+//! 用户名以 NFKC 规范化形式保存和比较，长度按字素簇计算。
+//! 为防止冒充，拒绝 UTS #39 中不允许用于标识符的字符以及可疑的多文字混用，
+//! 查重时比较忽略大小写的易混淆骨架（见 `username_key`）。
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::{
+    skeleton, GeneralSecurityProfile, RestrictionLevel, RestrictionLevelDetection,
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::error::{ProtocolError, Result};
+use crate::MAX_USERNAME_LEN;
+
+/// 单个字素簇允许的最大字符数（防止堆叠组合符号）
+const MAX_GRAPHEME_CHARS: usize = 8;
+
+/// 用户名允许的字符：任意文字的字母、数字及组合符号，以及下划线、连字符
+pub(crate) fn is_username_char(c: char) -> bool {
+    c == '_' || c == '-' || c.is_alphanumeric() || is_combining_mark(c)
+}
+
+/// 将用户名规范化为 NFKC 形式
+///
+/// 服务端保存和广播的都是规范化后的用户名，客户端发送前也应先规范化。
+pub fn normalize_username(username: &str) -> String {
+    username.nfkc().collect()
+}
+
+/// 校验用户名是否符合约束（按 NFKC 规范化后的形式检查）
+pub fn validate_username(username: &str) -> Result<()> {
+    let username = normalize_username(username);
+    if username.is_empty() {
+        return Err(ProtocolError::UsernameEmpty);
+    }
+
+    let len = username.graphemes(true).count();
+    if len > MAX_USERNAME_LEN {
+        return Err(ProtocolError::UsernameTooLong {
+            len,
+            max: MAX_USERNAME_LEN,
+        });
+    }
+
+    let valid_chars = username
+        .chars()
+        .all(|c| is_username_char(c) && c.identifier_allowed());
+    let valid_graphemes = username
+        .graphemes(true)
+        .all(|g| g.chars().count() <= MAX_GRAPHEME_CHARS);
+    if !valid_chars || !valid_graphemes {
+        return Err(ProtocolError::UsernameInvalidChars);
+    }
+
+    // 允许单一文字，或拉丁字母与中日韩文字的常见组合
+    if !username.check_restriction_level(RestrictionLevel::HighlyRestrictive) {
+        return Err(ProtocolError::UsernameMixedScript);
+    }
+    Ok(())
+}
+
+/// 用户名的比较键：NFKC 规范化、转小写后的易混淆骨架
+///
+/// 键相同的两个用户名视为同名，例如 "Alice" 与 "alice"、"раypal"（西里尔字母）与 "paypal"。
+pub fn username_key(username: &str) -> String {
+    let lowered = normalize_username(username).to_lowercase();
+    skeleton(&lowered).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unicode_usernames() {
+        assert!(validate_username("valid_user").is_ok());
+        assert!(validate_username("张三").is_ok());
+        assert!(validate_username("たなか").is_ok());
+        assert!(validate_username("José").is_ok());
+        assert!(validate_username("李lee").is_ok());
+
+        assert!(matches!(
+            validate_username("bad name"),
+            Err(ProtocolError::UsernameInvalidChars)
+        ));
+        assert!(matches!(
+            validate_username("a\u{200B}b"),
+            Err(ProtocolError::UsernameInvalidChars)
+        ));
+        // 拉丁字母与西里尔字母混用
+        assert!(matches!(
+            validate_username("p\u{0430}ypal"),
+            Err(ProtocolError::UsernameMixedScript)
+        ));
+    }
+
+    #[test]
+    fn test_length_counts_graphemes() {
+        // 每个汉字 3 字节，但只算一个字素
+        assert!(validate_username(&"字".repeat(MAX_USERNAME_LEN)).is_ok());
+        assert!(matches!(
+            validate_username(&"字".repeat(MAX_USERNAME_LEN + 1)),
+            Err(ProtocolError::UsernameTooLong { .. })
+        ));
+        // 分解形式的 é 规范化后是一个字素
+        assert!(validate_username(&"e\u{0301}".repeat(MAX_USERNAME_LEN)).is_ok());
+        // 堆叠的组合符号
+        assert!(matches!(
+            validate_username(&format!("a{}", "\u{0301}".repeat(MAX_GRAPHEME_CHARS + 1))),
+            Err(ProtocolError::UsernameInvalidChars)
+        ));
+    }
+
+    #[test]
+    fn test_username_key() {
+        assert_eq!(username_key("Alice"), username_key("alice"));
+        assert_eq!(username_key("ｂｏｂ"), username_key("bob"));
+        assert_eq!(username_key("p\u{0430}ypal"), username_key("paypal"));
+        assert_eq!(username_key("rnike"), username_key("mike"));
+        assert_ne!(username_key("alice"), username_key("alicia"));
+    }
+}